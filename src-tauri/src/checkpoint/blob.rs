@@ -0,0 +1,314 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Read;
+use zstd::bulk::{Compressor, Decompressor};
+use zstd::stream::{decode_all, encode_all};
+use zstd::zstd_safe::CParameter;
+
+use super::{crypto, pool, storage::CheckpointStorage, CheckpointPaths};
+
+/// A blob is stored in full after this many deltas in a row, bounding the work of a read
+pub const KEYFRAME_INTERVAL: u32 = 8;
+
+/// Marks a blob compressed against an earlier version of the same file
+const DELTA_MAGIC: &[u8; 8] = b"OPDELTA1";
+
+/// Magic, base hash, chain depth and decoded size
+const DELTA_HEADER_LEN: usize = 8 + 64 + 4 + 8;
+
+/// Header of a delta blob
+#[derive(Debug, Clone)]
+pub struct DeltaHeader {
+    /// Blob whose content is the compression dictionary
+    pub base_hash: String,
+    /// Number of deltas between this blob and the nearest full blob
+    pub depth: u32,
+    /// Size of the decoded content in bytes
+    pub size: u64,
+}
+
+impl DeltaHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < DELTA_HEADER_LEN || &data[..8] != DELTA_MAGIC {
+            return None;
+        }
+        // The base names a file in the pool, so it must not be able to name anything else
+        let base_hash = std::str::from_utf8(&data[8..72]).ok()?;
+        if !pool::is_content_hash(base_hash) {
+            return None;
+        }
+        let base_hash = base_hash.to_string();
+        let depth = u32::from_le_bytes(data[72..76].try_into().ok()?);
+        let size = u64::from_le_bytes(data[76..84].try_into().ok()?);
+        Some(Self {
+            base_hash,
+            depth,
+            size,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(DELTA_HEADER_LEN);
+        header.extend_from_slice(DELTA_MAGIC);
+        header.extend_from_slice(self.base_hash.as_bytes());
+        header.extend_from_slice(&self.depth.to_le_bytes());
+        header.extend_from_slice(&self.size.to_le_bytes());
+        header
+    }
+}
+
+impl CheckpointStorage {
+    /// Write a blob to the project pool unless it is already there
+    ///
+    /// With a `base_hash`, the content is compressed using the base's content as a zstd
+    /// dictionary, unless the chain is due for a keyframe or the delta isn't smaller.
+    /// Returns the number of bytes written, which is zero when the blob already existed.
+    pub(super) fn write_blob(
+        &self,
+        paths: &CheckpointPaths,
+        hash: &str,
+        content: &[u8],
+        base_hash: Option<&str>,
+    ) -> Result<u64> {
+        fs::create_dir_all(&paths.content_pool_dir)
+            .context("Failed to create content pool directory")?;
+
+        let content_file = paths.content_pool_dir.join(hash);
+        if self.blob_path(paths, hash).exists() {
+            // Mark the blob as in use so a concurrent garbage collection keeps it
+            let _ = fs::File::options()
+                .write(true)
+                .open(&content_file)
+                .and_then(|f| f.set_modified(std::time::SystemTime::now()));
+            return Ok(0);
+        }
+
+        let full = encode_all(content, self.compression_level)
+            .context("Failed to compress file content")?;
+        let delta = match base_hash.filter(|base| *base != hash) {
+            Some(base) => self.encode_delta(paths, base, content).unwrap_or_else(|e| {
+                log::warn!(
+                    "Storing {} in full, delta against {} failed: {}",
+                    hash,
+                    base,
+                    e
+                );
+                None
+            }),
+            None => None,
+        };
+
+        let encoded = match delta {
+            Some(delta) if delta.len() < full.len() => delta,
+            _ => full,
+        };
+        self.write_sealed(&content_file, encoded, &crypto::blob_context(hash))
+            .context("Failed to write file content to pool")
+    }
+
+    /// Compress `content` against a base blob, or `None` when a keyframe is due
+    fn encode_delta(
+        &self,
+        paths: &CheckpointPaths,
+        base_hash: &str,
+        content: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        let base_depth = self.blob_header(paths, base_hash)?.map_or(0, |h| h.depth);
+        if base_depth + 1 >= KEYFRAME_INTERVAL {
+            return Ok(None);
+        }
+        let base = self.read_blob(paths, base_hash)?;
+
+        let mut compressor = Compressor::with_dictionary(self.compression_level, &base)
+            .context("Failed to prepare delta compressor")?;
+        // Let matches reach back across the whole base
+        let window_log = (base.len() + content.len())
+            .max(1)
+            .next_power_of_two()
+            .trailing_zeros()
+            .clamp(10, 27);
+        compressor
+            .set_parameter(CParameter::WindowLog(window_log))
+            .context("Failed to configure delta compressor")?;
+        let payload = compressor
+            .compress(content)
+            .context("Failed to delta-compress file content")?;
+
+        let header = DeltaHeader {
+            base_hash: base_hash.to_string(),
+            depth: base_depth + 1,
+            size: content.len() as u64,
+        };
+        let mut encoded = header.encode();
+        encoded.extend_from_slice(&payload);
+        Ok(Some(encoded))
+    }
+
+    /// Read a blob's content, resolving any chain of deltas
+    pub(super) fn read_blob(&self, paths: &CheckpointPaths, hash: &str) -> Result<Vec<u8>> {
+        self.read_blob_at_depth(paths, hash, 0)
+    }
+
+    fn read_blob_at_depth(
+        &self,
+        paths: &CheckpointPaths,
+        hash: &str,
+        depth: u32,
+    ) -> Result<Vec<u8>> {
+        if depth > KEYFRAME_INTERVAL {
+            anyhow::bail!("Delta chain too long at {}", hash);
+        }
+
+        let data = self.unseal(
+            fs::read(self.blob_path(paths, hash))
+                .with_context(|| format!("Failed to read blob {}", hash))?,
+            &crypto::blob_context(hash),
+        )?;
+        let Some(header) = DeltaHeader::parse(&data) else {
+            return decode_all(&data[..]).context("Failed to decompress file content");
+        };
+
+        let base = self.read_blob_at_depth(paths, &header.base_hash, depth + 1)?;
+        let mut decompressor =
+            Decompressor::with_dictionary(&base).context("Failed to prepare delta decompressor")?;
+        decompressor
+            .decompress(&data[DELTA_HEADER_LEN..], header.size as usize)
+            .context("Failed to decompress delta")
+    }
+
+    /// Delta header of a blob, or `None` for a full blob
+    pub(super) fn blob_header(
+        &self,
+        paths: &CheckpointPaths,
+        hash: &str,
+    ) -> Result<Option<DeltaHeader>> {
+        let mut file = fs::File::open(self.blob_path(paths, hash))
+            .with_context(|| format!("Failed to open blob {}", hash))?;
+        let mut prefix = Vec::with_capacity(DELTA_HEADER_LEN);
+        file.by_ref()
+            .take(DELTA_HEADER_LEN as u64)
+            .read_to_end(&mut prefix)
+            .context("Failed to read blob header")?;
+
+        // An encrypted blob has to be decrypted whole before its header can be seen
+        if crypto::is_sealed(&prefix) {
+            file.read_to_end(&mut prefix)
+                .context("Failed to read blob header")?;
+            prefix = self.unseal(prefix, &crypto::blob_context(hash))?;
+        }
+        Ok(DeltaHeader::parse(&prefix))
+    }
+
+    /// Add every blob that a delta in `hashes` depends on, directly or through its base
+    ///
+    /// Missing blobs have no bases to add. Fails if a blob that exists can't be read, since
+    /// its bases would otherwise go unnoticed.
+    pub(super) fn with_delta_bases(
+        &self,
+        paths: &CheckpointPaths,
+        hashes: impl IntoIterator<Item = String>,
+    ) -> Result<std::collections::BTreeSet<String>> {
+        let mut all = std::collections::BTreeSet::new();
+        let mut pending: Vec<String> = hashes.into_iter().collect();
+        while let Some(hash) = pending.pop() {
+            if !all.insert(hash.clone()) || !self.blob_path(paths, &hash).exists() {
+                continue;
+            }
+            if let Some(header) = self.blob_header(paths, &hash)? {
+                pending.push(header.base_hash);
+            }
+        }
+        Ok(all)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A file's content with line `changed` edited `version` times
+    fn version(changed: usize, version: usize) -> Vec<u8> {
+        (0..2000)
+            .map(|i| {
+                if i == changed {
+                    format!("line {} edited {} times\n", i, version)
+                } else {
+                    format!("line {} holds {}\n", i, i * 7919 % 10007)
+                }
+            })
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    fn write(
+        storage: &CheckpointStorage,
+        paths: &CheckpointPaths,
+        content: &[u8],
+        base_hash: Option<&str>,
+    ) -> String {
+        let hash = CheckpointStorage::calculate_file_hash(content);
+        storage
+            .write_blob(paths, &hash, content, base_hash)
+            .unwrap();
+        hash
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = CheckpointStorage::new(temp_dir.path().to_path_buf());
+        let paths = CheckpointPaths::new(&storage.claude_dir, "test-project", "test-session");
+
+        let base = write(&storage, &paths, &version(10, 0), None);
+        let content = version(10, 1);
+        let hash = write(&storage, &paths, &content, Some(&base));
+
+        let header = storage.blob_header(&paths, &hash).unwrap().unwrap();
+        assert_eq!(header.base_hash, base);
+        assert_eq!(header.depth, 1);
+        assert_eq!(header.size, content.len() as u64);
+        assert_eq!(storage.read_blob(&paths, &hash).unwrap(), content);
+        assert!(storage.blob_header(&paths, &base).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_delta_chains_restart_at_keyframes() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = CheckpointStorage::new(temp_dir.path().to_path_buf());
+        let paths = CheckpointPaths::new(&storage.claude_dir, "test-project", "test-session");
+
+        let mut base: Option<String> = None;
+        for i in 0..=KEYFRAME_INTERVAL as usize + 1 {
+            let content = version(i % 50, i);
+            let hash = write(&storage, &paths, &content, base.as_deref());
+
+            let depth = storage
+                .blob_header(&paths, &hash)
+                .unwrap()
+                .map_or(0, |h| h.depth);
+            assert_eq!(depth, i as u32 % KEYFRAME_INTERVAL, "version {}", i);
+            assert_eq!(storage.read_blob(&paths, &hash).unwrap(), content);
+            base = Some(hash);
+        }
+
+        let chain = storage.with_delta_bases(&paths, base).unwrap();
+        assert_eq!(chain.len(), 2);
+    }
+
+    #[test]
+    fn test_delta_header_rejects_bases_that_arent_hashes() {
+        let header = |base_hash: &str| {
+            let mut data = DELTA_MAGIC.to_vec();
+            data.extend_from_slice(base_hash.as_bytes());
+            data.extend_from_slice(&1u32.to_le_bytes());
+            data.extend_from_slice(&5u64.to_le_bytes());
+            DeltaHeader::parse(&data)
+        };
+
+        assert!(header(&"ab".repeat(32)).is_some());
+        let traversal = format!("{:/<64}", "../../../../../../etc/passwd");
+        assert!(header(&traversal).is_none());
+        assert!(header(&"AG".repeat(32)).is_none());
+    }
+}
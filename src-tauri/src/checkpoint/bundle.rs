@@ -0,0 +1,578 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path};
+
+use super::{
+    crypto, pool,
+    storage::{self, CheckpointStorage},
+    CheckpointPaths, SessionTimeline, TimelineNode,
+};
+
+/// Version of the bundle layout written by `export_bundle`
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Name of the manifest entry at the root of a bundle
+const MANIFEST_FILE: &str = "bundle.json";
+
+/// Top-level entries a bundle may contain
+const BUNDLE_ENTRIES: [&str; 4] = [MANIFEST_FILE, "timeline.json", "checkpoints", "files"];
+
+/// Describes the contents of a checkpoint bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleManifest {
+    /// Bundle layout version
+    pub format_version: u32,
+    /// Project the checkpoints were exported from
+    pub source_project_id: String,
+    /// Session the checkpoints were exported from
+    pub source_session_id: String,
+    /// IDs of every checkpoint in the bundle
+    pub checkpoint_ids: Vec<String>,
+    /// Number of content blobs in the bundle
+    pub blob_count: usize,
+    /// When the bundle was created
+    pub exported_at: DateTime<Utc>,
+}
+
+/// Result of importing a checkpoint bundle
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleImportResult {
+    /// Project the timeline was imported into
+    pub project_id: String,
+    /// Session the timeline was imported as
+    pub session_id: String,
+    /// The imported timeline
+    pub timeline: SessionTimeline,
+    /// Manifest read from the bundle
+    pub manifest: BundleManifest,
+}
+
+impl CheckpointStorage {
+    /// Export a session's timeline, or a single checkpoint of it, into a tar archive
+    ///
+    /// The archive holds the timeline, checkpoint metadata, compressed messages, file
+    /// references and the content blobs they point at, laid out like the session's
+    /// `.timelines` directory.
+    pub fn export_bundle(
+        &self,
+        project_id: &str,
+        session_id: &str,
+        checkpoint_id: Option<&str>,
+        output_path: &Path,
+    ) -> Result<BundleManifest> {
+        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
+        let full_timeline = self.load_timeline(&paths.timeline_file)?;
+
+        // A single checkpoint becomes the root of a one-node timeline
+        let timeline = match checkpoint_id {
+            Some(id) => {
+                let node = full_timeline
+                    .find_checkpoint(id)
+                    .ok_or_else(|| anyhow::anyhow!("Checkpoint not found: {}", id))?;
+                let mut checkpoint = node.checkpoint.clone();
+                checkpoint.parent_checkpoint_id = None;
+                SessionTimeline {
+                    root_node: Some(TimelineNode {
+                        checkpoint,
+                        children: Vec::new(),
+                        file_snapshot_ids: node.file_snapshot_ids.clone(),
+                    }),
+                    current_checkpoint_id: Some(id.to_string()),
+                    total_checkpoints: 1,
+                    ..full_timeline.clone()
+                }
+            }
+            None => full_timeline.clone(),
+        };
+
+        let mut checkpoints = Vec::new();
+        if let Some(root) = &timeline.root_node {
+            Self::collect_checkpoints(root, &mut checkpoints);
+        }
+        if checkpoints.is_empty() {
+            anyhow::bail!("Session {} has no checkpoints to export", session_id);
+        }
+        if checkpoints.iter().any(|c| c.git_commit.is_some()) {
+            anyhow::bail!(
+                "Session {} has git-backed checkpoints, whose files live in the project repository",
+                session_id
+            );
+        }
+
+        let file = fs::File::create(output_path).context("Failed to create bundle file")?;
+        let mut builder = tar::Builder::new(file);
+        let mut hashes = BTreeSet::new();
+
+        for checkpoint in &checkpoints {
+            let metadata_json = serde_json::to_string_pretty(checkpoint)
+                .context("Failed to serialize checkpoint metadata")?;
+            append_bytes(
+                &mut builder,
+                &format!("checkpoints/{}/metadata.json", checkpoint.id),
+                metadata_json.as_bytes(),
+            )?;
+            // Bundles are written decrypted so they can be imported anywhere
+            let messages = self.unseal(
+                fs::read(paths.checkpoint_messages_file(&checkpoint.id))
+                    .context("Failed to read checkpoint messages")?,
+                &crypto::messages_context(&checkpoint.id),
+            )?;
+            append_bytes(
+                &mut builder,
+                &format!("checkpoints/{}/messages.jsonl", checkpoint.id),
+                &messages,
+            )?;
+
+            // A checkpoint exported on its own loses its parent, so it records its whole tree
+            let refs = match checkpoint_id {
+                Some(_) => self.tree_refs(&paths, &full_timeline, &checkpoint.id)?,
+                None => own_refs(&paths.files_dir.join("refs").join(&checkpoint.id))?,
+            };
+            for (name, ref_json) in refs {
+                let ref_metadata: serde_json::Value =
+                    serde_json::from_str(&ref_json).context("Failed to parse file reference")?;
+                if let Some(hash) = ref_metadata["hash"].as_str().filter(|h| !h.is_empty()) {
+                    hashes.insert(hash.to_string());
+                }
+                append_bytes(
+                    &mut builder,
+                    &format!("files/refs/{}/{}", checkpoint.id, name),
+                    ref_json.as_bytes(),
+                )?;
+            }
+        }
+
+        // Deltas are useless without the blobs they were compressed against
+        let hashes = self.with_delta_bases(&paths, hashes)?;
+
+        let mut blob_count = 0;
+        for hash in &hashes {
+            let content_file = self.blob_path(&paths, hash);
+            if !content_file.is_file() {
+                log::warn!("Content file missing for hash, not bundled: {}", hash);
+                continue;
+            }
+            let content = self.unseal(
+                fs::read(&content_file).context("Failed to read file content")?,
+                &crypto::blob_context(hash),
+            )?;
+            append_bytes(
+                &mut builder,
+                &format!("files/content_pool/{}", hash),
+                &content,
+            )?;
+            blob_count += 1;
+        }
+
+        let timeline_json =
+            serde_json::to_string_pretty(&timeline).context("Failed to serialize timeline")?;
+        append_bytes(&mut builder, "timeline.json", timeline_json.as_bytes())?;
+
+        let manifest = BundleManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            source_project_id: project_id.to_string(),
+            source_session_id: session_id.to_string(),
+            checkpoint_ids: checkpoints.iter().map(|c| c.id.clone()).collect(),
+            blob_count,
+            exported_at: Utc::now(),
+        };
+        let manifest_json =
+            serde_json::to_string_pretty(&manifest).context("Failed to serialize manifest")?;
+        append_bytes(&mut builder, MANIFEST_FILE, manifest_json.as_bytes())?;
+
+        builder
+            .into_inner()
+            .context("Failed to finish bundle")?
+            .flush()
+            .context("Failed to flush bundle")?;
+
+        Ok(manifest)
+    }
+
+    /// Refs for every file in the project as of a checkpoint, named as the checkpoint's own
+    fn tree_refs(
+        &self,
+        paths: &CheckpointPaths,
+        timeline: &SessionTimeline,
+        checkpoint_id: &str,
+    ) -> Result<Vec<(String, String)>> {
+        self.load_file_state(paths, timeline, checkpoint_id)?
+            .into_values()
+            .map(|snapshot| {
+                let name = format!("{}.json", storage::safe_ref_name(&snapshot.file_path));
+                let ref_json = serde_json::to_string_pretty(&storage::ref_metadata(&snapshot))?;
+                Ok((name, ref_json))
+            })
+            .collect()
+    }
+
+    /// Check that every blob a bundle brings into the pool holds the content it's named for
+    ///
+    /// Blobs the project pool already has are skipped, since the pool's copy is kept. Anything
+    /// else would be shared with every session of the project, so one that doesn't decode to
+    /// its hash fails the import.
+    fn verify_bundle_blobs(&self, paths: &CheckpointPaths) -> Result<()> {
+        let bundle_pool = paths.files_dir.join("content_pool");
+        if !bundle_pool.exists() {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(&bundle_pool)? {
+            let blob = entry?.path();
+            let Some(hash) = blob.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if !pool::is_content_hash(hash) || paths.content_pool_dir.join(hash).exists() {
+                continue;
+            }
+            let content = self
+                .read_blob(paths, hash)
+                .with_context(|| format!("Bundle blob {} can't be read", hash))?;
+            if CheckpointStorage::calculate_file_hash(&content) != hash {
+                anyhow::bail!("Bundle blob {} doesn't match its content", hash);
+            }
+        }
+        Ok(())
+    }
+
+    /// Import a bundle as a new session timeline under `project_id`
+    ///
+    /// Checkpoints are rebound to the target project and session, and the session's JSONL
+    /// file is recreated from the current checkpoint's messages so it can be resumed.
+    pub fn import_bundle(
+        &self,
+        bundle_path: &Path,
+        project_id: &str,
+        session_id: &str,
+    ) -> Result<BundleImportResult> {
+        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
+        let base_dir = paths
+            .timeline_file
+            .parent()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| anyhow::anyhow!("Invalid timeline path"))?;
+        if base_dir.exists() {
+            anyhow::bail!("Session {} already has a timeline", session_id);
+        }
+
+        let result = self.extract_bundle(bundle_path, &base_dir, &paths, project_id, session_id);
+        if result.is_err() {
+            // Don't leave a half-imported timeline behind
+            let _ = fs::remove_dir_all(&base_dir);
+        }
+        result
+    }
+
+    fn extract_bundle(
+        &self,
+        bundle_path: &Path,
+        base_dir: &Path,
+        paths: &CheckpointPaths,
+        project_id: &str,
+        session_id: &str,
+    ) -> Result<BundleImportResult> {
+        let file = fs::File::open(bundle_path).context("Failed to open bundle")?;
+        let mut archive = tar::Archive::new(file);
+        let mut manifest_json = None;
+
+        for entry in archive.entries().context("Failed to read bundle")? {
+            let mut entry = entry.context("Failed to read bundle entry")?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let rel_path = entry.path().context("Invalid path in bundle")?.into_owned();
+            if !is_safe_bundle_path(&rel_path) {
+                anyhow::bail!("Unexpected entry in bundle: {}", rel_path.display());
+            }
+
+            if rel_path == Path::new(MANIFEST_FILE) {
+                let mut json = String::new();
+                std::io::Read::read_to_string(&mut entry, &mut json)
+                    .context("Failed to read bundle manifest")?;
+                manifest_json = Some(json);
+                continue;
+            }
+
+            let dest = base_dir.join(&rel_path);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).context("Failed to create bundle directory")?;
+            }
+            let mut out = fs::File::create(&dest).context("Failed to write bundle entry")?;
+            std::io::copy(&mut entry, &mut out).context("Failed to write bundle entry")?;
+        }
+
+        let manifest: BundleManifest = serde_json::from_str(
+            &manifest_json.ok_or_else(|| anyhow::anyhow!("Bundle has no manifest"))?,
+        )
+        .context("Failed to parse bundle manifest")?;
+        if manifest.format_version > BUNDLE_FORMAT_VERSION {
+            anyhow::bail!(
+                "Bundle format v{} is newer than supported v{}",
+                manifest.format_version,
+                BUNDLE_FORMAT_VERSION
+            );
+        }
+
+        // Rebind every checkpoint to the target project and session
+        let lock = self.lock_timeline(&paths.timeline_file)?;
+        let mut timeline = lock.read()?;
+        timeline.session_id = session_id.to_string();
+        if let Some(root) = &mut timeline.root_node {
+            rebind_node(root, project_id, session_id);
+        }
+
+        let mut checkpoints = Vec::new();
+        if let Some(root) = &timeline.root_node {
+            Self::collect_checkpoints(root, &mut checkpoints);
+        }
+        for checkpoint in &checkpoints {
+            let metadata_path = paths.checkpoint_metadata_file(&checkpoint.id);
+            fs::create_dir_all(paths.checkpoint_dir(&checkpoint.id))
+                .context("Failed to create checkpoint directory")?;
+            fs::write(&metadata_path, serde_json::to_string_pretty(checkpoint)?)
+                .context("Failed to write checkpoint metadata")?;
+        }
+        fs::create_dir_all(&paths.checkpoints_dir)
+            .context("Failed to create checkpoints directory")?;
+        fs::create_dir_all(&paths.files_dir).context("Failed to create files directory")?;
+        lock.write(&timeline)?;
+        drop(lock);
+
+        // Encrypt the bundle's data if encryption is on, so nothing unencrypted reaches the
+        // project pool, then bring its blobs into the pool and up to the current format
+        self.reseal_project(project_id)?;
+        self.verify_bundle_blobs(paths)?;
+        self.migrate_storage(paths)?;
+
+        // Recreate the session file so the imported session can be opened and resumed
+        if let Some(current_id) = &timeline.current_checkpoint_id {
+            let session_file = self
+                .claude_dir
+                .join("projects")
+                .join(project_id)
+                .join(format!("{}.jsonl", session_id));
+            if !session_file.exists() {
+                let (_, _, messages) = self.load_checkpoint(project_id, session_id, current_id)?;
+                fs::write(&session_file, messages).context("Failed to write session file")?;
+            }
+        }
+
+        log::info!(
+            "Imported {} checkpoint(s) from {} into session {}",
+            checkpoints.len(),
+            bundle_path.display(),
+            session_id
+        );
+
+        Ok(BundleImportResult {
+            project_id: project_id.to_string(),
+            session_id: session_id.to_string(),
+            timeline,
+            manifest,
+        })
+    }
+}
+
+/// A checkpoint's own refs, by file name
+fn own_refs(refs_dir: &Path) -> Result<Vec<(String, String)>> {
+    if !refs_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut refs = Vec::new();
+    for entry in fs::read_dir(refs_dir)? {
+        let ref_path = entry?.path();
+        if ref_path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let ref_json = fs::read_to_string(&ref_path).context("Failed to read file reference")?;
+        let name = ref_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+            .to_string();
+        refs.push((name, ref_json));
+    }
+    Ok(refs)
+}
+
+/// Point a subtree of checkpoints at a new project and session
+fn rebind_node(node: &mut TimelineNode, project_id: &str, session_id: &str) {
+    node.checkpoint.project_id = project_id.to_string();
+    node.checkpoint.session_id = session_id.to_string();
+    for child in &mut node.children {
+        rebind_node(child, project_id, session_id);
+    }
+}
+
+/// Only accept plain relative paths within the known bundle layout
+fn is_safe_bundle_path(path: &Path) -> bool {
+    let mut components = path.components();
+    let first = match components.next() {
+        Some(Component::Normal(first)) => first,
+        _ => return false,
+    };
+
+    BUNDLE_ENTRIES.iter().any(|name| first == *name)
+        && components.all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Add an in-memory file to the archive
+fn append_bytes<W: Write>(builder: &mut tar::Builder<W>, name: &str, data: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    builder
+        .append_data(&mut header, name, data)
+        .with_context(|| format!("Failed to add {} to bundle", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkpoint::manager::CheckpointManager;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    /// Files of a checkpoint as path and content
+    fn tree(
+        storage: &CheckpointStorage,
+        project_id: &str,
+        session_id: &str,
+        checkpoint_id: &str,
+    ) -> BTreeMap<PathBuf, String> {
+        storage
+            .load_checkpoint_files(project_id, session_id, checkpoint_id)
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.file_path, String::from_utf8(s.content).unwrap()))
+            .collect()
+    }
+
+    async fn two_checkpoints(temp_dir: &TempDir) -> (CheckpointManager, String, String) {
+        let project_path = temp_dir.path().join("project");
+        fs::create_dir_all(&project_path).unwrap();
+        let manager = CheckpointManager::new(
+            "test-project".to_string(),
+            "test-session".to_string(),
+            project_path.clone(),
+            temp_dir.path().join("claude"),
+        )
+        .await
+        .unwrap();
+
+        fs::write(project_path.join("a.txt"), "a1").unwrap();
+        fs::write(project_path.join("b.txt"), "b1").unwrap();
+        let first = manager.create_checkpoint(None, None).await.unwrap();
+        fs::write(project_path.join("a.txt"), "a2").unwrap();
+        let second = manager.create_checkpoint(None, None).await.unwrap();
+        (manager, first.checkpoint.id, second.checkpoint.id)
+    }
+
+    #[tokio::test]
+    async fn test_bundle_round_trip_keeps_every_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let (manager, first, second) = two_checkpoints(&temp_dir).await;
+        let storage = &manager.storage;
+        let bundle = temp_dir.path().join("session.tar");
+
+        let manifest = storage
+            .export_bundle("test-project", "test-session", None, &bundle)
+            .unwrap();
+        assert_eq!(manifest.checkpoint_ids.len(), 2);
+
+        let imported = storage
+            .import_bundle(&bundle, "other-project", "imported")
+            .unwrap();
+        assert_eq!(imported.timeline.total_checkpoints, 2);
+        for id in [&first, &second] {
+            assert_eq!(
+                tree(storage, "other-project", "imported", id),
+                tree(storage, "test-project", "test-session", id)
+            );
+        }
+
+        // The session file is recreated from the current checkpoint's messages
+        assert!(temp_dir
+            .path()
+            .join("claude/projects/other-project/imported.jsonl")
+            .exists());
+    }
+
+    #[tokio::test]
+    async fn test_single_checkpoint_bundle_holds_its_whole_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let (manager, _, second) = two_checkpoints(&temp_dir).await;
+        let storage = &manager.storage;
+        let bundle = temp_dir.path().join("checkpoint.tar");
+
+        // The second checkpoint only records a.txt itself
+        storage
+            .export_bundle("test-project", "test-session", Some(&second), &bundle)
+            .unwrap();
+        let imported = storage
+            .import_bundle(&bundle, "other-project", "imported")
+            .unwrap();
+
+        let root = imported.timeline.root_node.unwrap();
+        assert_eq!(root.checkpoint.id, second);
+        assert!(root.checkpoint.parent_checkpoint_id.is_none());
+        assert_eq!(
+            tree(storage, "other-project", "imported", &second),
+            BTreeMap::from([
+                (PathBuf::from("a.txt"), "a2".to_string()),
+                (PathBuf::from("b.txt"), "b1".to_string()),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_blobs_that_dont_match_their_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let (manager, _, _) = two_checkpoints(&temp_dir).await;
+        let storage = &manager.storage;
+        let bundle = temp_dir.path().join("session.tar");
+        storage
+            .export_bundle("test-project", "test-session", None, &bundle)
+            .unwrap();
+
+        // Swap the content of every blob for something else under the same name
+        let forged = temp_dir.path().join("forged.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&forged).unwrap());
+        let mut archive = tar::Archive::new(fs::File::open(&bundle).unwrap());
+        let mut pool_hashes = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut data = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut data).unwrap();
+            if let Some(hash) = name.strip_prefix("files/content_pool/") {
+                pool_hashes.push(hash.to_string());
+                data = zstd::stream::encode_all(&b"forged"[..], 3).unwrap();
+            }
+            append_bytes(&mut builder, &name, &data).unwrap();
+        }
+        builder.into_inner().unwrap();
+        assert!(!pool_hashes.is_empty());
+
+        let err = storage
+            .import_bundle(&forged, "other-project", "imported")
+            .unwrap_err();
+        assert!(err.to_string().contains("doesn't match its content"));
+
+        let paths = CheckpointPaths::new(&storage.claude_dir, "other-project", "imported");
+        assert!(!paths.timeline_file.exists());
+        for hash in &pool_hashes {
+            assert!(!paths.content_pool_dir.join(hash).exists());
+        }
+    }
+}
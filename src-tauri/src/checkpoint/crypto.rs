@@ -0,0 +1,597 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use super::{pool::is_content_hash, storage::CheckpointStorage};
+
+/// Keyring for checkpoint encryption, kept in the Claude directory
+pub const KEYFILE: &str = "checkpoint-keys.json";
+
+/// Held shared while sealing and writing data, and exclusively while the keyring changes
+const KEYFILE_LOCK: &str = "checkpoint-keys.lock";
+
+/// Marks content encrypted with a checkpoint key
+const SEALED_MAGIC: &[u8; 8] = b"OPSEAL01";
+
+/// Length of a key ID in hex characters
+const KEY_ID_LEN: usize = 16;
+
+const NONCE_LEN: usize = 24;
+
+/// Magic, key ID and nonce
+const SEALED_HEADER_LEN: usize = 8 + KEY_ID_LEN + NONCE_LEN;
+
+/// Content is encrypted but the key it needs isn't in the keyfile
+///
+/// Kept distinct from authentication failures so integrity repair never deletes data that
+/// is only unreadable because a key is missing.
+#[derive(Debug)]
+pub struct MissingKeyError {
+    pub key_id: String,
+}
+
+impl std::fmt::Display for MissingKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Checkpoint data is encrypted with key {}, which is not in the keyfile",
+            self.key_id
+        )
+    }
+}
+
+impl std::error::Error for MissingKeyError {}
+
+/// A key in the keyring
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredKey {
+    id: String,
+    /// Base64 of the 32-byte key
+    key: String,
+    created_at: DateTime<Utc>,
+}
+
+impl StoredKey {
+    fn generate() -> Self {
+        let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+        let id = uuid::Uuid::new_v4().simple().to_string()[..KEY_ID_LEN].to_string();
+        Self {
+            id,
+            key: BASE64.encode(key),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn cipher(&self) -> Result<XChaCha20Poly1305> {
+        let key = BASE64
+            .decode(&self.key)
+            .with_context(|| format!("Key {} is not valid base64", self.id))?;
+        if key.len() != 32 {
+            anyhow::bail!("Key {} is not 32 bytes", self.id);
+        }
+        Ok(XChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+}
+
+/// Contents of the keyfile
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Keyring {
+    /// Whether new checkpoint data is encrypted
+    enabled: bool,
+    /// Key used for new data; older keys only decrypt
+    active_key_id: String,
+    keys: Vec<StoredKey>,
+    /// Whether a reseal finished encrypting every file, so unencrypted data is refused
+    #[serde(default)]
+    fully_sealed: bool,
+}
+
+impl Keyring {
+    fn key(&self, id: &str) -> Option<&StoredKey> {
+        self.keys.iter().find(|k| k.id == id)
+    }
+}
+
+/// Last keyring read from disk, reused until the keyfile changes
+#[derive(Default)]
+pub struct KeyCache(Mutex<Option<(SystemTime, u64, Arc<Keyring>)>>);
+
+/// Whether checkpoint encryption is on, as reported to the frontend
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    pub active_key_id: Option<String>,
+    pub keyfile: PathBuf,
+}
+
+/// Outcome of re-encrypting checkpoint data
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyRotationResult {
+    pub active_key_id: Option<String>,
+    /// Blobs and message files rewritten
+    pub files_resealed: usize,
+    /// Keys removed from the keyfile once nothing needed them
+    pub retired_key_ids: Vec<String>,
+}
+
+/// Whether data starts with the header of encrypted content
+pub(super) fn is_sealed(data: &[u8]) -> bool {
+    data.len() >= SEALED_HEADER_LEN && &data[..8] == SEALED_MAGIC
+}
+
+/// Associated data binding a blob to its hash, so sealed blobs can't be swapped
+pub(super) fn blob_context(hash: &str) -> String {
+    format!("blob:{}", hash)
+}
+
+/// Associated data binding a messages file to its checkpoint
+pub(super) fn messages_context(checkpoint_id: &str) -> String {
+    format!("messages:{}", checkpoint_id)
+}
+
+/// Replace a file's contents without leaving it half written
+fn replace_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("reseal.tmp");
+    fs::write(&tmp, contents).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+impl CheckpointStorage {
+    fn keyfile_path(&self) -> PathBuf {
+        self.claude_dir.join(KEYFILE)
+    }
+
+    /// The keyring, or `None` when no keyfile exists
+    fn keyring(&self) -> Result<Option<Arc<Keyring>>> {
+        let keyfile = self.keyfile_path();
+        let metadata = match fs::metadata(&keyfile) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read checkpoint keyfile"),
+        };
+        let stamp = (metadata.modified()?, metadata.len());
+
+        let mut cache = self.keys.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((modified, len, keyring)) = cache.as_ref() {
+            if (*modified, *len) == stamp {
+                return Ok(Some(Arc::clone(keyring)));
+            }
+        }
+
+        let keyring_json =
+            fs::read_to_string(&keyfile).context("Failed to read checkpoint keyfile")?;
+        let keyring: Arc<Keyring> = Arc::new(
+            serde_json::from_str(&keyring_json).context("Failed to parse checkpoint keyfile")?,
+        );
+        *cache = Some((stamp.0, stamp.1, Arc::clone(&keyring)));
+        Ok(Some(keyring))
+    }
+
+    fn save_keyring(&self, keyring: &Keyring) -> Result<()> {
+        let keyfile = self.keyfile_path();
+        let keyring_json = serde_json::to_string_pretty(keyring)?;
+        let tmp = keyfile.with_extension("json.tmp");
+
+        // Created fresh and private, so the keys are never readable by anyone else
+        let _ = fs::remove_file(&tmp);
+        let mut options = fs::File::options();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&tmp)
+            .and_then(|mut file| file.write_all(keyring_json.as_bytes()))
+            .context("Failed to write checkpoint keyfile")?;
+        fs::rename(&tmp, &keyfile).context("Failed to replace checkpoint keyfile")?;
+        *self.keys.0.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        Ok(())
+    }
+
+    /// Wait for the keyring lock, released when dropped
+    ///
+    /// Writers share it, so a rotation holding it exclusively knows nothing is being
+    /// sealed with a key it is about to retire.
+    fn lock_keys(&self, exclusive: bool) -> Result<fs::File> {
+        fs::create_dir_all(&self.claude_dir).context("Failed to create Claude directory")?;
+        let lock_file = fs::File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.claude_dir.join(KEYFILE_LOCK))
+            .context("Failed to open checkpoint keyring lock")?;
+        if exclusive {
+            lock_file.lock()
+        } else {
+            lock_file.lock_shared()
+        }
+        .context("Failed to lock checkpoint keyring")?;
+        Ok(lock_file)
+    }
+
+    /// Encrypt data if encryption is on and write it to `path`, returning the bytes written
+    ///
+    /// The keyring can't change between sealing and writing, so the data never lands on
+    /// disk under a key that was retired in between.
+    pub(super) fn write_sealed(&self, path: &Path, data: Vec<u8>, context: &str) -> Result<u64> {
+        let _lock = self.lock_keys(false)?;
+        let sealed = self.seal(data, context)?;
+        fs::write(path, &sealed).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(sealed.len() as u64)
+    }
+
+    /// Encrypt data about to be written, if encryption is on
+    pub(super) fn seal(&self, data: Vec<u8>, context: &str) -> Result<Vec<u8>> {
+        match self.keyring()? {
+            Some(keyring) if keyring.enabled => Self::seal_with(&keyring, &data, context),
+            _ => Ok(data),
+        }
+    }
+
+    fn seal_with(keyring: &Keyring, data: &[u8], context: &str) -> Result<Vec<u8>> {
+        let key = keyring
+            .key(&keyring.active_key_id)
+            .ok_or_else(|| anyhow::anyhow!("Active checkpoint key is not in the keyfile"))?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = key
+            .cipher()?
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: data,
+                    aad: context.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt checkpoint data"))?;
+
+        let mut sealed = Vec::with_capacity(SEALED_HEADER_LEN + ciphertext.len());
+        sealed.extend_from_slice(SEALED_MAGIC);
+        sealed.extend_from_slice(key.id.as_bytes());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypt data read back from disk; data that was never encrypted passes through
+    ///
+    /// Fails if the data was altered or bound to a different `context`. Once encryption is
+    /// on and every file has been encrypted, unencrypted data can only have been put there
+    /// by something else and is refused too.
+    pub(super) fn unseal(&self, data: Vec<u8>, context: &str) -> Result<Vec<u8>> {
+        let keyring = self.keyring()?;
+        if !is_sealed(&data) {
+            if keyring
+                .as_ref()
+                .is_some_and(|k| k.enabled && k.fully_sealed)
+            {
+                log::error!("Checkpoint data is not encrypted: {}", context);
+                anyhow::bail!(
+                    "Checkpoint data is not encrypted although encryption is on, and may have \
+                     been tampered with: {}",
+                    context
+                );
+            }
+            return Ok(data);
+        }
+        let key_id = String::from_utf8_lossy(&data[8..8 + KEY_ID_LEN]).into_owned();
+        let key = keyring
+            .as_deref()
+            .and_then(|k| k.key(&key_id))
+            .ok_or(MissingKeyError { key_id })?;
+
+        let nonce = XNonce::from_slice(&data[8 + KEY_ID_LEN..SEALED_HEADER_LEN]);
+        key.cipher()?
+            .decrypt(
+                nonce,
+                Payload {
+                    msg: &data[SEALED_HEADER_LEN..],
+                    aad: context.as_bytes(),
+                },
+            )
+            .map_err(|_| {
+                log::error!("Checkpoint data failed authentication: {}", context);
+                anyhow::anyhow!(
+                    "Checkpoint data failed authentication and may have been tampered with: {}",
+                    context
+                )
+            })
+    }
+
+    /// Whether encryption is on and which key new data uses
+    pub fn encryption_status(&self) -> Result<EncryptionStatus> {
+        let keyring = self.keyring()?;
+        Ok(EncryptionStatus {
+            enabled: keyring.as_ref().is_some_and(|k| k.enabled),
+            active_key_id: keyring.map(|k| k.active_key_id.clone()),
+            keyfile: self.keyfile_path(),
+        })
+    }
+
+    /// Turn encryption on or off and rewrite existing checkpoint data to match
+    ///
+    /// Turning it on creates the keyfile on first use. Turning it off decrypts everything
+    /// but keeps the keyfile, so encryption can be turned back on with the same key.
+    pub fn set_encryption(&self, enabled: bool) -> Result<KeyRotationResult> {
+        let _lock = self.lock_keys(true)?;
+        let mut keyring = match self.keyring()? {
+            Some(keyring) => Keyring {
+                enabled,
+                fully_sealed: false,
+                ..(*keyring).clone()
+            },
+            None if !enabled => return Ok(KeyRotationResult::default()),
+            None => {
+                let key = StoredKey::generate();
+                Keyring {
+                    enabled,
+                    active_key_id: key.id.clone(),
+                    keys: vec![key],
+                    fully_sealed: false,
+                }
+            }
+        };
+        self.save_keyring(&keyring)?;
+
+        let files_resealed = self.reseal_all(&keyring)?;
+        if enabled {
+            keyring.fully_sealed = true;
+            self.save_keyring(&keyring)?;
+        }
+
+        Ok(KeyRotationResult {
+            active_key_id: Some(keyring.active_key_id.clone()),
+            files_resealed,
+            retired_key_ids: Vec::new(),
+        })
+    }
+
+    /// Switch to a new key, re-encrypt all checkpoint data with it and drop the old keys
+    ///
+    /// Old keys stay in the keyfile until every file has been rewritten, so an interrupted
+    /// rotation leaves everything readable and can simply be run again. Writers are held
+    /// off until it finishes, so none can seal with an old key after its files were checked.
+    pub fn rotate_encryption_key(&self) -> Result<KeyRotationResult> {
+        let _lock = self.lock_keys(true)?;
+        let mut keyring = match self.keyring()? {
+            Some(keyring) if keyring.enabled => (*keyring).clone(),
+            _ => anyhow::bail!("Checkpoint encryption is not enabled"),
+        };
+
+        let key = StoredKey::generate();
+        keyring.active_key_id = key.id.clone();
+        keyring.keys.push(key);
+        self.save_keyring(&keyring)?;
+
+        let files_resealed = self.reseal_all(&keyring)?;
+
+        let active_key_id = keyring.active_key_id.clone();
+        let retired_key_ids = keyring
+            .keys
+            .iter()
+            .filter(|k| k.id != active_key_id)
+            .map(|k| k.id.clone())
+            .collect();
+        keyring.keys.retain(|k| k.id == active_key_id);
+        keyring.fully_sealed = true;
+        self.save_keyring(&keyring)?;
+
+        log::info!(
+            "Rotated checkpoint encryption to key {}, rewrote {} file(s)",
+            active_key_id,
+            files_resealed
+        );
+        Ok(KeyRotationResult {
+            active_key_id: Some(active_key_id),
+            files_resealed,
+            retired_key_ids,
+        })
+    }
+
+    /// Bring every blob and message file of one project in line with the keyring
+    pub(super) fn reseal_project(&self, project_id: &str) -> Result<usize> {
+        let _lock = self.lock_keys(false)?;
+        match self.keyring()? {
+            Some(keyring) => {
+                let timelines_dir = self
+                    .claude_dir
+                    .join("projects")
+                    .join(project_id)
+                    .join(".timelines");
+                self.reseal_files(&keyring, &sealable_files(&timelines_dir)?)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn reseal_all(&self, keyring: &Keyring) -> Result<usize> {
+        let projects_dir = self.claude_dir.join("projects");
+        if !projects_dir.exists() {
+            return Ok(0);
+        }
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&projects_dir)? {
+            let timelines_dir = entry?.path().join(".timelines");
+            if timelines_dir.is_dir() {
+                files.extend(sealable_files(&timelines_dir)?);
+            }
+        }
+        self.reseal_files(keyring, &files)
+    }
+
+    /// Rewrite files that aren't encrypted the way the keyring says they should be
+    fn reseal_files(&self, keyring: &Keyring, files: &[(PathBuf, String)]) -> Result<usize> {
+        let mut resealed = 0;
+        for (path, context) in files {
+            let data =
+                fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let up_to_date = match (is_sealed(&data), keyring.enabled) {
+                (true, true) => data[8..8 + KEY_ID_LEN] == *keyring.active_key_id.as_bytes(),
+                (sealed, enabled) => sealed == enabled,
+            };
+            if up_to_date {
+                continue;
+            }
+
+            let plain = if is_sealed(&data) {
+                self.unseal(data, context)
+                    .with_context(|| format!("Failed to decrypt {}", path.display()))?
+            } else {
+                data
+            };
+            let rewritten = if keyring.enabled {
+                Self::seal_with(keyring, &plain, context)?
+            } else {
+                plain
+            };
+            replace_file(path, &rewritten)?;
+            resealed += 1;
+        }
+        Ok(resealed)
+    }
+}
+
+/// Every blob and message file under a project's `.timelines` directory, with the
+/// context each is sealed under
+fn sealable_files(timelines_dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut files = Vec::new();
+    let mut add_blobs = |pool_dir: &Path| -> Result<()> {
+        if !pool_dir.is_dir() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(pool_dir)? {
+            let path = entry?.path();
+            let Some(hash) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if is_content_hash(hash) {
+                let context = blob_context(hash);
+                files.push((path, context));
+            }
+        }
+        Ok(())
+    };
+
+    add_blobs(&timelines_dir.join("content_pool"))?;
+    let mut sessions = Vec::new();
+    for entry in fs::read_dir(timelines_dir)? {
+        let session_dir = entry?.path();
+        if session_dir.join("timeline.json").is_file() {
+            sessions.push(session_dir);
+        }
+    }
+    for session_dir in &sessions {
+        // Session pools that haven't been moved into the project pool yet
+        add_blobs(&session_dir.join("files").join("content_pool"))?;
+    }
+
+    for session_dir in &sessions {
+        let checkpoints_dir = session_dir.join("checkpoints");
+        if !checkpoints_dir.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&checkpoints_dir)? {
+            let checkpoint_dir = entry?.path();
+            let messages_file = checkpoint_dir.join("messages.jsonl");
+            let Some(checkpoint_id) = checkpoint_dir.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if messages_file.is_file() {
+                files.push((messages_file, messages_context(checkpoint_id)));
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_unseal_rejects_tampered_data() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = CheckpointStorage::new(temp_dir.path().to_path_buf());
+        storage.set_encryption(true).unwrap();
+
+        let sealed = storage.seal(b"secret".to_vec(), "blob:a").unwrap();
+        assert_eq!(storage.unseal(sealed.clone(), "blob:a").unwrap(), b"secret");
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let err = storage.unseal(tampered, "blob:a").unwrap_err();
+        assert!(err.downcast_ref::<MissingKeyError>().is_none());
+
+        // Sealed data moved to another context fails the same way
+        assert!(storage.unseal(sealed, "blob:b").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keyfile_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let storage = CheckpointStorage::new(temp_dir.path().to_path_buf());
+        // A leftover from an interrupted save must not lend the keyfile its mode
+        let tmp = storage.keyfile_path().with_extension("json.tmp");
+        fs::write(&tmp, "{}").unwrap();
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o644)).unwrap();
+
+        storage.set_encryption(true).unwrap();
+        let mode = fs::metadata(storage.keyfile_path())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_unseal_reports_missing_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = CheckpointStorage::new(temp_dir.path().to_path_buf());
+        storage.set_encryption(true).unwrap();
+
+        // Not on disk, so rotation can't rewrite it before retiring its key
+        let sealed = storage.seal(b"secret".to_vec(), "blob:a").unwrap();
+        let rotation = storage.rotate_encryption_key().unwrap();
+        assert_eq!(rotation.retired_key_ids.len(), 1);
+
+        let err = storage.unseal(sealed, "blob:a").unwrap_err();
+        let missing = err.downcast_ref::<MissingKeyError>().unwrap();
+        assert_eq!(missing.key_id, rotation.retired_key_ids[0]);
+    }
+
+    #[test]
+    fn test_unseal_refuses_plain_data_once_everything_is_sealed() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = CheckpointStorage::new(temp_dir.path().to_path_buf());
+        assert_eq!(
+            storage.unseal(b"plain".to_vec(), "blob:a").unwrap(),
+            b"plain"
+        );
+
+        storage.set_encryption(true).unwrap();
+        assert!(storage.unseal(b"plain".to_vec(), "blob:a").is_err());
+
+        storage.set_encryption(false).unwrap();
+        assert_eq!(
+            storage.unseal(b"plain".to_vec(), "blob:a").unwrap(),
+            b"plain"
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use anyhow::Result;
+use similar::{ChangeTag, TextDiff};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{
+    storage::CheckpointStorage, Checkpoint, CheckpointPaths, DiffHunk, FileDiff, FileKind,
+    FileSnapshot,
+};
+
+/// Number of unchanged lines shown around each hunk when none is requested
+pub const DEFAULT_CONTEXT_LINES: usize = 3;
+
+/// Identifier used in place of a checkpoint ID when diffing against the working tree
+pub const WORKING_TREE_ID: &str = "working-tree";
+
+/// Files that differ between two sets of snapshots
+pub struct SnapshotDiff {
+    pub modified_files: Vec<FileDiff>,
+    pub added_files: Vec<PathBuf>,
+    pub deleted_files: Vec<PathBuf>,
+}
+
+/// Build a line-level unified diff between two versions of a file
+///
+/// Binary content (anything that is not valid UTF-8) is reported without a line diff.
+pub fn diff_file(path: &Path, old: &[u8], new: &[u8], context_lines: usize) -> FileDiff {
+    let (old, new) = match (std::str::from_utf8(old), std::str::from_utf8(new)) {
+        (Ok(old), Ok(new)) => (old, new),
+        _ => {
+            return FileDiff {
+                path: path.to_path_buf(),
+                additions: 0,
+                deletions: 0,
+                diff_content: None,
+                is_binary: true,
+                hunks: Vec::new(),
+            }
+        }
+    };
+
+    let diff = TextDiff::from_lines(old, new);
+
+    // Count only the lines that actually changed
+    let mut additions = 0;
+    let mut deletions = 0;
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => additions += 1,
+            ChangeTag::Delete => deletions += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+
+    let hunks = diff
+        .grouped_ops(context_lines)
+        .iter()
+        .filter_map(|group| {
+            let first = group.first()?;
+            let last = group.last()?;
+            let old_lines = last.old_range().end - first.old_range().start;
+            let new_lines = last.new_range().end - first.new_range().start;
+
+            // Unified diff convention: an empty range points at the line before it
+            Some(DiffHunk {
+                old_start: first.old_range().start + usize::from(old_lines > 0),
+                old_lines,
+                new_start: first.new_range().start + usize::from(new_lines > 0),
+                new_lines,
+            })
+        })
+        .collect();
+
+    let display_path = path.to_string_lossy();
+    let diff_content = diff
+        .unified_diff()
+        .context_radius(context_lines)
+        .header(
+            &format!("a/{}", display_path),
+            &format!("b/{}", display_path),
+        )
+        .to_string();
+
+    FileDiff {
+        path: path.to_path_buf(),
+        additions,
+        deletions,
+        diff_content: Some(diff_content),
+        is_binary: false,
+        hunks,
+    }
+}
+
+/// Compare two sets of file snapshots, treating deleted snapshots as absent
+pub fn diff_snapshots(
+    from_files: &[FileSnapshot],
+    to_files: &[FileSnapshot],
+    context_lines: usize,
+) -> SnapshotDiff {
+    // Empty directories have no content to compare
+    let from_map: HashMap<&PathBuf, &FileSnapshot> = from_files
+        .iter()
+        .filter(|s| !s.is_deleted && s.kind != FileKind::Directory)
+        .map(|s| (&s.file_path, s))
+        .collect();
+    let to_map: HashMap<&PathBuf, &FileSnapshot> = to_files
+        .iter()
+        .filter(|s| !s.is_deleted && s.kind != FileKind::Directory)
+        .map(|s| (&s.file_path, s))
+        .collect();
+
+    let mut modified_files = Vec::new();
+    let mut added_files = Vec::new();
+    let mut deleted_files = Vec::new();
+
+    for (path, from_file) in &from_map {
+        match to_map.get(path) {
+            Some(to_file) if from_file.hash != to_file.hash => {
+                modified_files.push(diff_file(
+                    path,
+                    &from_file.content,
+                    &to_file.content,
+                    context_lines,
+                ));
+            }
+            Some(_) => {}
+            None => deleted_files.push((*path).clone()),
+        }
+    }
+
+    for path in to_map.keys() {
+        if !from_map.contains_key(path) {
+            added_files.push((*path).clone());
+        }
+    }
+
+    // Keep output stable for the UI
+    modified_files.sort_by(|a, b| a.path.cmp(&b.path));
+    added_files.sort();
+    deleted_files.sort();
+
+    SnapshotDiff {
+        modified_files,
+        added_files,
+        deleted_files,
+    }
+}
+
+impl CheckpointStorage {
+    /// Load two checkpoints of a session and diff their files
+    ///
+    /// When both were made with the git backend, only the blobs git reports as changed
+    /// between the two trees are read.
+    pub fn diff_checkpoints(
+        &self,
+        project_id: &str,
+        session_id: &str,
+        from_checkpoint_id: &str,
+        to_checkpoint_id: &str,
+        context_lines: usize,
+    ) -> Result<(Checkpoint, Checkpoint, SnapshotDiff)> {
+        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
+        let from_checkpoint = self.load_checkpoint_metadata(&paths, from_checkpoint_id)?;
+        let to_checkpoint = self.load_checkpoint_metadata(&paths, to_checkpoint_id)?;
+
+        if let (Some(from_commit), Some(to_commit)) =
+            (&from_checkpoint.git_commit, &to_checkpoint.git_commit)
+        {
+            let repo = self.git_repository(&paths)?;
+            let changes = repo.diff_trees(from_commit, to_commit)?;
+            let oids: Vec<String> = changes
+                .iter()
+                .flat_map(|c| [c.old_oid.clone(), c.new_oid.clone()])
+                .flatten()
+                .collect();
+            let blobs = repo.read_blobs(&oids)?;
+            let content = |oid: &String| blobs.get(oid).map(Vec::as_slice).unwrap_or_default();
+
+            let mut snapshot_diff = SnapshotDiff {
+                modified_files: Vec::new(),
+                added_files: Vec::new(),
+                deleted_files: Vec::new(),
+            };
+            for change in changes {
+                match (&change.old_oid, &change.new_oid) {
+                    (Some(old), Some(new)) => snapshot_diff.modified_files.push(diff_file(
+                        &change.path,
+                        content(old),
+                        content(new),
+                        context_lines,
+                    )),
+                    (None, Some(_)) => snapshot_diff.added_files.push(change.path),
+                    (Some(_), None) => snapshot_diff.deleted_files.push(change.path),
+                    (None, None) => {}
+                }
+            }
+            return Ok((from_checkpoint, to_checkpoint, snapshot_diff));
+        }
+
+        // Checkpoints only record what changed, so compare the whole trees
+        let from_files = self.load_checkpoint_files(project_id, session_id, from_checkpoint_id)?;
+        let to_files = self.load_checkpoint_files(project_id, session_id, to_checkpoint_id)?;
+        let snapshot_diff = diff_snapshots(&from_files, &to_files, context_lines);
+
+        Ok((from_checkpoint, to_checkpoint, snapshot_diff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(path: &str, content: &str) -> FileSnapshot {
+        FileSnapshot {
+            checkpoint_id: "test".to_string(),
+            file_path: PathBuf::from(path),
+            content: content.as_bytes().to_vec(),
+            hash: CheckpointStorage::calculate_file_hash(content.as_bytes()),
+            is_deleted: false,
+            permissions: None,
+            size: content.len() as u64,
+            kind: FileKind::File,
+            is_unrecoverable: false,
+        }
+    }
+
+    #[test]
+    fn test_diff_file_counts_changed_lines_and_hunks() {
+        let old: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        let new = old
+            .replace("line 2\n", "line two\n")
+            .replace("line 18\n", "");
+
+        let diff = diff_file(Path::new("src/lib.rs"), old.as_bytes(), new.as_bytes(), 1);
+        assert!(!diff.is_binary);
+        assert_eq!(diff.additions, 1);
+        assert_eq!(diff.deletions, 2);
+
+        // Far enough apart for one hunk each with a single line of context
+        let hunks: Vec<_> = diff
+            .hunks
+            .iter()
+            .map(|h| (h.old_start, h.old_lines, h.new_start, h.new_lines))
+            .collect();
+        assert_eq!(hunks, vec![(1, 3, 1, 3), (17, 3, 17, 2)]);
+
+        let content = diff.diff_content.unwrap();
+        assert!(content.starts_with("--- a/src/lib.rs\n+++ b/src/lib.rs\n"));
+        assert!(content.contains("@@ -1,3 +1,3 @@\n line 1\n-line 2\n+line two\n line 3\n"));
+        assert!(content.contains("@@ -17,3 +17,2 @@\n line 17\n-line 18\n line 19\n"));
+    }
+
+    #[test]
+    fn test_diff_file_points_empty_ranges_at_the_line_before() {
+        let diff = diff_file(Path::new("new.txt"), b"", b"one\ntwo\n", 3);
+        assert_eq!(diff.additions, 2);
+        assert_eq!(diff.hunks.len(), 1);
+        let hunk = &diff.hunks[0];
+        assert_eq!(
+            (
+                hunk.old_start,
+                hunk.old_lines,
+                hunk.new_start,
+                hunk.new_lines
+            ),
+            (0, 0, 1, 2)
+        );
+    }
+
+    #[test]
+    fn test_diff_file_reports_binary_content_without_lines() {
+        let diff = diff_file(Path::new("image.png"), b"text\n", &[0xff, 0xfe, 0x00], 3);
+        assert!(diff.is_binary);
+        assert_eq!((diff.additions, diff.deletions), (0, 0));
+        assert!(diff.diff_content.is_none());
+        assert!(diff.hunks.is_empty());
+    }
+
+    #[test]
+    fn test_diff_snapshots_sorts_changes_and_skips_deleted_and_directories() {
+        let mut removed = snapshot("removed.txt", "");
+        removed.is_deleted = true;
+        let mut dir = snapshot("empty", "");
+        dir.kind = FileKind::Directory;
+
+        let from = vec![
+            snapshot("b.txt", "b\n"),
+            snapshot("a.txt", "a\n"),
+            snapshot("same.txt", "same\n"),
+            snapshot("gone.txt", "gone\n"),
+            snapshot("removed-later.txt", "x\n"),
+        ];
+        let mut removed_later = snapshot("removed-later.txt", "");
+        removed_later.is_deleted = true;
+        let to = vec![
+            snapshot("a.txt", "A\n"),
+            snapshot("b.txt", "B\n"),
+            snapshot("same.txt", "same\n"),
+            snapshot("new.txt", "new\n"),
+            removed,
+            removed_later,
+            dir,
+        ];
+
+        let diff = diff_snapshots(&from, &to, DEFAULT_CONTEXT_LINES);
+        let modified: Vec<_> = diff.modified_files.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(
+            modified,
+            vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")]
+        );
+        assert_eq!(diff.added_files, vec![PathBuf::from("new.txt")]);
+        assert_eq!(
+            diff.deleted_files,
+            vec![
+                PathBuf::from("gone.txt"),
+                PathBuf::from("removed-later.txt")
+            ]
+        );
+    }
+}
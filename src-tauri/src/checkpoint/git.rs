@@ -0,0 +1,499 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use super::{
+    storage::CheckpointStorage, walk, CheckpointPaths, FileKind, FileSnapshot, SessionTimeline,
+};
+
+/// Namespace for the hidden refs that hold git-backed checkpoints
+pub const REF_NAMESPACE: &str = "refs/opcode";
+
+/// Identity recorded on checkpoint commits, so no user git config is required
+const COMMIT_NAME: &str = "opcode";
+const COMMIT_EMAIL: &str = "opcode@localhost";
+
+/// Modes git records for regular files, executable files and symbolic links
+const REGULAR_MODE: u32 = 0o100644;
+const EXECUTABLE_MODE: u32 = 0o100755;
+const SYMLINK_MODE: u32 = 0o120000;
+
+/// Hidden ref holding a checkpoint's commit
+pub fn checkpoint_ref(session_id: &str, checkpoint_id: &str) -> String {
+    format!("{}/{}/{}", REF_NAMESPACE, session_id, checkpoint_id)
+}
+
+/// A file recorded in a checkpoint commit's tree
+#[derive(Debug, Clone)]
+pub struct TreeEntry {
+    /// Path relative to the project root
+    pub path: PathBuf,
+    /// Git file mode, e.g. `0o100644`
+    pub mode: u32,
+    /// Blob object ID
+    pub oid: String,
+}
+
+/// A file that differs between two checkpoint commits
+#[derive(Debug, Clone)]
+pub struct TreeChange {
+    /// Path relative to the project root
+    pub path: PathBuf,
+    /// Blob in the older commit, if the file existed there
+    pub old_oid: Option<String>,
+    /// Blob in the newer commit, if the file exists there
+    pub new_oid: Option<String>,
+}
+
+/// A project directory inside a git repository, driven through the `git` CLI
+///
+/// Checkpoint commits are built in a throwaway index so the user's index, HEAD and
+/// branches are never touched. Each commit's tree is the project directory itself, so
+/// paths in it are relative to the project root even when the project is a subdirectory.
+pub struct GitRepository {
+    /// Top level of the repository's work tree
+    top_level: PathBuf,
+    /// Project directory relative to the top level, with a trailing `/`, or empty
+    prefix: String,
+}
+
+impl GitRepository {
+    /// Open the repository containing `project_path`
+    pub fn open(project_path: &Path) -> Result<Self> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(project_path)
+            .args(["rev-parse", "--show-toplevel", "--show-prefix"])
+            .output()
+            .context("Failed to run git")?;
+        if !output.status.success() {
+            anyhow::bail!("{} is not inside a git repository", project_path.display());
+        }
+
+        let stdout = String::from_utf8(output.stdout).context("Invalid UTF-8 from git")?;
+        let mut lines = stdout.lines();
+        let top_level = PathBuf::from(lines.next().unwrap_or_default());
+        let prefix = lines.next().unwrap_or_default().to_string();
+
+        Ok(Self { top_level, prefix })
+    }
+
+    /// Record `files`, relative to the project root, as a commit pointed to by `ref_name`
+    pub fn commit_snapshot(
+        &self,
+        files: &[PathBuf],
+        parent: Option<&str>,
+        message: &str,
+        ref_name: &str,
+    ) -> Result<String> {
+        let index_dir = tempfile::tempdir().context("Failed to create temporary index")?;
+        let index_file = index_dir.path().join("index");
+
+        let tree = if files.is_empty() {
+            let mut hash_empty = self.command();
+            hash_empty.args(["hash-object", "-t", "tree", "-w", "--stdin"]);
+            self.run_text(hash_empty, Some(Vec::new()))?
+        } else {
+            // Hash the files without clean filters, so autocrlf, eol and LFS rules can't
+            // change what a restore writes back
+            let project_dir = self.top_level.join(&self.prefix);
+            let mut entries = Vec::new();
+            let mut hash_paths = String::new();
+            for file in files {
+                let full_path = project_dir.join(file);
+                let Ok(metadata) = std::fs::symlink_metadata(&full_path) else {
+                    continue;
+                };
+                let mode = match walk::kind_of(&metadata.file_type()) {
+                    FileKind::Directory => continue,
+                    FileKind::Symlink => SYMLINK_MODE,
+                    FileKind::File => match walk::permission_bits(&metadata) {
+                        Some(bits) if bits & 0o111 != 0 => EXECUTABLE_MODE,
+                        _ => REGULAR_MODE,
+                    },
+                };
+
+                // A link's blob is its target, and --stdin-paths reads one path per line, so
+                // those two are staged as plain files first
+                let hash_path = match full_path.to_str() {
+                    Some(path) if mode != SYMLINK_MODE && !path.contains('\n') => path.to_string(),
+                    _ => {
+                        let staged = index_dir.path().join(entries.len().to_string());
+                        let entry = walk::read_entry(&full_path)
+                            .with_context(|| format!("Failed to read {}", full_path.display()))?;
+                        std::fs::write(&staged, entry.content)
+                            .context("Failed to stage file for checkpoint")?;
+                        staged.to_string_lossy().into_owned()
+                    }
+                };
+                hash_paths.push_str(&hash_path);
+                hash_paths.push('\n');
+                entries.push((mode, self.repo_path(file)));
+            }
+
+            let mut hash_objects = self.command();
+            hash_objects.args(["hash-object", "-w", "--no-filters", "--stdin-paths"]);
+            let oids = self
+                .run_text(hash_objects, Some(hash_paths.into_bytes()))
+                .context("Failed to hash files for checkpoint")?;
+
+            // <mode> SP <object> TAB <path> NUL
+            let mut index_info = Vec::new();
+            for ((mode, path), oid) in entries.iter().zip(oids.lines()) {
+                index_info.extend_from_slice(format!("{:o} {}\t{}", mode, oid, path).as_bytes());
+                index_info.push(0);
+            }
+
+            let mut update_index = self.command();
+            update_index.env("GIT_INDEX_FILE", &index_file).args([
+                "update-index",
+                "-z",
+                "--index-info",
+            ]);
+            self.run(update_index, Some(index_info))
+                .context("Failed to add files to checkpoint index")?;
+
+            let mut write_tree = self.command();
+            write_tree
+                .env("GIT_INDEX_FILE", &index_file)
+                .arg("write-tree");
+            if !self.prefix.is_empty() {
+                write_tree.arg(format!("--prefix={}", self.prefix));
+            }
+            self.run_text(write_tree, None)?
+        };
+
+        let mut commit_tree = self.command();
+        commit_tree
+            .env("GIT_AUTHOR_NAME", COMMIT_NAME)
+            .env("GIT_AUTHOR_EMAIL", COMMIT_EMAIL)
+            .env("GIT_COMMITTER_NAME", COMMIT_NAME)
+            .env("GIT_COMMITTER_EMAIL", COMMIT_EMAIL)
+            .args(["commit-tree", &tree]);
+        if let Some(parent) = parent {
+            commit_tree.args(["-p", parent]);
+        }
+        commit_tree.args(["-m", message]);
+        let commit = self.run_text(commit_tree, None)?;
+
+        let mut update_ref = self.command();
+        update_ref.args(["update-ref", ref_name, &commit]);
+        self.run(update_ref, None)?;
+
+        Ok(commit)
+    }
+
+    /// List the files in a checkpoint commit
+    pub fn read_tree(&self, commit: &str) -> Result<Vec<TreeEntry>> {
+        let mut ls_tree = self.command();
+        ls_tree.args(["ls-tree", "-r", "-z", "--full-tree", commit]);
+        let output = self.run(ls_tree, None)?;
+
+        let mut entries = Vec::new();
+        for record in output.split(|b| *b == 0).filter(|r| !r.is_empty()) {
+            // <mode> SP <type> SP <object> TAB <path>
+            let record = String::from_utf8_lossy(record);
+            let Some((info, path)) = record.split_once('\t') else {
+                continue;
+            };
+            let mut fields = info.split(' ');
+            let (Some(mode), Some(kind), Some(oid)) = (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if kind != "blob" {
+                continue;
+            }
+
+            entries.push(TreeEntry {
+                path: PathBuf::from(path),
+                mode: u32::from_str_radix(mode, 8).unwrap_or(REGULAR_MODE),
+                oid: oid.to_string(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Read blob contents, keyed by object ID; missing objects are left out
+    pub fn read_blobs(&self, oids: &[String]) -> Result<HashMap<String, Vec<u8>>> {
+        if oids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let mut input = oids.join("\n").into_bytes();
+        input.push(b'\n');
+
+        let mut cat_file = self.command();
+        cat_file.args(["cat-file", "--batch"]);
+        let output = self.run(cat_file, Some(input))?;
+
+        // Each object is "<oid> <type> <size>\n<content>\n", or "<oid> missing\n"
+        let mut blobs = HashMap::new();
+        let mut rest = &output[..];
+        while let Some(newline) = rest.iter().position(|b| *b == b'\n') {
+            let header = String::from_utf8_lossy(&rest[..newline]).to_string();
+            rest = &rest[newline + 1..];
+
+            let fields: Vec<&str> = header.split(' ').collect();
+            if fields.len() != 3 {
+                log::warn!("Git object not found: {}", header);
+                continue;
+            }
+            let size: usize = fields[2].parse().context("Invalid object size from git")?;
+            if rest.len() < size {
+                anyhow::bail!("Truncated object {} from git", fields[0]);
+            }
+            blobs.insert(fields[0].to_string(), rest[..size].to_vec());
+            rest = rest.get(size + 1..).unwrap_or_default();
+        }
+
+        Ok(blobs)
+    }
+
+    /// Load a checkpoint commit as file snapshots
+    ///
+    /// Git has no empty directories, so a git-backed checkpoint never records any. It only
+    /// records whether a file is executable, so a file's mode on disk is kept unless that
+    /// differs.
+    pub fn load_snapshots(&self, commit: &str, checkpoint_id: &str) -> Result<Vec<FileSnapshot>> {
+        let project_dir = self.top_level.join(&self.prefix);
+        let entries = self.read_tree(commit)?;
+        let oids: Vec<String> = entries.iter().map(|e| e.oid.clone()).collect();
+        let mut blobs = self.read_blobs(&oids)?;
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| {
+                let content = blobs.remove(&entry.oid);
+                let is_unrecoverable = content.is_none();
+                let content = content.unwrap_or_default();
+                // A link's blob holds its target
+                let (kind, permissions) = if entry.mode == SYMLINK_MODE {
+                    (FileKind::Symlink, None)
+                } else {
+                    let current = std::fs::symlink_metadata(project_dir.join(&entry.path))
+                        .ok()
+                        .filter(|m| m.is_file())
+                        .and_then(|m| walk::permission_bits(&m));
+                    (FileKind::File, Some(file_mode(entry.mode, current)))
+                };
+                FileSnapshot {
+                    checkpoint_id: checkpoint_id.to_string(),
+                    hash: CheckpointStorage::calculate_file_hash(&content),
+                    size: content.len() as u64,
+                    file_path: entry.path,
+                    content,
+                    is_deleted: false,
+                    permissions,
+                    kind,
+                    is_unrecoverable,
+                }
+            })
+            .collect())
+    }
+
+    /// Files that differ between two checkpoint commits
+    pub fn diff_trees(&self, from: &str, to: &str) -> Result<Vec<TreeChange>> {
+        let mut diff_tree = self.command();
+        diff_tree.args(["diff-tree", "-r", "-z", "--no-renames", from, to]);
+        let output = self.run(diff_tree, None)?;
+
+        // ":<old mode> <new mode> <old oid> <new oid> <status>" NUL "<path>" NUL
+        let mut changes = Vec::new();
+        let mut records = output.split(|b| *b == 0).filter(|r| !r.is_empty());
+        while let (Some(info), Some(path)) = (records.next(), records.next()) {
+            let info = String::from_utf8_lossy(info);
+            let fields: Vec<&str> = info.trim_start_matches(':').split(' ').collect();
+            if fields.len() < 4 {
+                continue;
+            }
+            let present = |oid: &str| (!oid.bytes().all(|b| b == b'0')).then(|| oid.to_string());
+
+            changes.push(TreeChange {
+                path: PathBuf::from(String::from_utf8_lossy(path).as_ref()),
+                old_oid: present(fields[2]),
+                new_oid: present(fields[3]),
+            });
+        }
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+
+    /// Delete a checkpoint ref; its objects become unreachable and git gc reclaims them
+    pub fn delete_ref(&self, ref_name: &str) -> Result<()> {
+        let mut update_ref = self.command();
+        update_ref.args(["update-ref", "-d", ref_name]);
+        self.run(update_ref, None).map(|_| ())
+    }
+
+    /// Path of a project file relative to the repository top level, using `/` separators
+    fn repo_path(&self, rel_path: &Path) -> String {
+        format!(
+            "{}{}",
+            self.prefix,
+            rel_path.to_string_lossy().replace('\\', "/")
+        )
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.top_level);
+        command
+    }
+
+    /// Run a git command, feeding `input` on stdin, and return its stdout
+    fn run(&self, mut command: Command, input: Option<Vec<u8>>) -> Result<Vec<u8>> {
+        command
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = command.spawn().context("Failed to run git")?;
+
+        // Write from another thread so a large output can't stall the child
+        let writer = match (input, child.stdin.take()) {
+            (Some(input), Some(mut stdin)) => {
+                Some(std::thread::spawn(move || stdin.write_all(&input)))
+            }
+            _ => None,
+        };
+
+        let output = child.wait_with_output().context("Failed to wait for git")?;
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "git failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.stdout)
+    }
+
+    fn run_text(&self, command: Command, input: Option<Vec<u8>>) -> Result<String> {
+        let stdout = self.run(command, input)?;
+        Ok(String::from_utf8(stdout)
+            .context("Invalid UTF-8 from git")?
+            .trim()
+            .to_string())
+    }
+}
+
+/// Permission bits to restore for a file git recorded with `git_mode`
+///
+/// The mode on disk is kept if it agrees on being executable; otherwise the execute bits
+/// follow its read bits, as `chmod +x` would.
+fn file_mode(git_mode: u32, current: Option<u32>) -> u32 {
+    let executable = git_mode & 0o111 != 0;
+    match current {
+        Some(current) if (current & 0o100 != 0) == executable => current,
+        Some(current) if executable => current | ((current & 0o444) >> 2),
+        Some(current) => current & !0o111,
+        None => git_mode & walk::PERMISSION_BITS,
+    }
+}
+
+impl CheckpointStorage {
+    /// Repository holding a session's git-backed checkpoints
+    pub(super) fn git_repository(&self, paths: &CheckpointPaths) -> Result<GitRepository> {
+        timeline_repository(&self.load_timeline(&paths.timeline_file)?)
+    }
+}
+
+/// Repository holding a timeline's git-backed checkpoints, for use under the timeline lock
+pub(super) fn timeline_repository(timeline: &SessionTimeline) -> Result<GitRepository> {
+    let project_path = timeline
+        .git_repository
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Session has no repository for git checkpoints"))?;
+    GitRepository::open(project_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_snapshots_skip_clean_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        git(project, &["init", "-q"]);
+        git(project, &["config", "core.autocrlf", "true"]);
+        fs::write(project.join(".gitattributes"), "*.txt text eol=crlf\n").unwrap();
+        fs::write(project.join("crlf.txt"), "one\r\ntwo\r\n").unwrap();
+
+        let repo = GitRepository::open(project).unwrap();
+        let files = [PathBuf::from(".gitattributes"), PathBuf::from("crlf.txt")];
+        let commit = repo
+            .commit_snapshot(&files, None, "test", &checkpoint_ref("session", "one"))
+            .unwrap();
+
+        let snapshots = repo.load_snapshots(&commit, "one").unwrap();
+        let crlf = snapshots
+            .iter()
+            .find(|s| s.file_path == Path::new("crlf.txt"))
+            .unwrap();
+        assert_eq!(crlf.content, b"one\r\ntwo\r\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_snapshots_keep_modes_git_doesnt_record() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        git(project, &["init", "-q"]);
+        for (name, mode) in [("secret", 0o600), ("script", 0o750)] {
+            fs::write(project.join(name), name).unwrap();
+            fs::set_permissions(project.join(name), fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        let repo = GitRepository::open(project).unwrap();
+        let files = [PathBuf::from("script"), PathBuf::from("secret")];
+        let commit = repo
+            .commit_snapshot(&files, None, "test", &checkpoint_ref("session", "one"))
+            .unwrap();
+        let modes: Vec<u32> = repo
+            .read_tree(&commit)
+            .unwrap()
+            .iter()
+            .map(|e| e.mode)
+            .collect();
+        assert_eq!(modes, vec![EXECUTABLE_MODE, REGULAR_MODE]);
+
+        // The script lost its execute bits since, and the secret is unchanged
+        fs::set_permissions(project.join("script"), fs::Permissions::from_mode(0o640)).unwrap();
+        let snapshots = repo.load_snapshots(&commit, "one").unwrap();
+        let mode = |name: &str| {
+            snapshots
+                .iter()
+                .find(|s| s.file_path == Path::new(name))
+                .and_then(|s| s.permissions)
+        };
+        assert_eq!(mode("secret"), Some(0o600));
+        assert_eq!(mode("script"), Some(0o750));
+    }
+}
@@ -0,0 +1,420 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use zstd::stream::decode_all;
+
+use super::{
+    crypto::{self, MissingKeyError},
+    storage::{collect_tree_parents, CheckpointStorage},
+    Checkpoint, CheckpointPaths,
+};
+
+/// A file reference whose content blob is missing or damaged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlobIssue {
+    /// Checkpoint holding the reference
+    pub checkpoint_id: String,
+    /// File the reference describes
+    pub file_path: PathBuf,
+    /// Content hash the reference points at
+    pub hash: String,
+}
+
+/// Findings of a storage integrity check
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    /// Number of timeline nodes inspected
+    pub checkpoints_checked: usize,
+    /// Number of distinct content blobs whose hash was recomputed
+    pub blobs_checked: usize,
+    /// References whose blob is absent from the content pool
+    pub missing_blobs: Vec<BlobIssue>,
+    /// References whose blob can't be decompressed or doesn't match its hash
+    pub corrupted_blobs: Vec<BlobIssue>,
+    /// Checkpoint IDs with refs or checkpoint directories but no timeline node
+    pub orphaned_refs: Vec<String>,
+    /// Timeline nodes whose metadata or messages are missing or unreadable
+    pub dangling_nodes: Vec<String>,
+    /// Whether repairs were applied
+    pub repaired: bool,
+    /// Checkpoints removed from the timeline during repair
+    pub pruned_checkpoints: Vec<String>,
+    /// Checkpoint count recorded in the timeline after the check
+    pub total_checkpoints: usize,
+}
+
+impl IntegrityReport {
+    /// Whether the check found nothing wrong
+    pub fn is_healthy(&self) -> bool {
+        self.missing_blobs.is_empty()
+            && self.corrupted_blobs.is_empty()
+            && self.orphaned_refs.is_empty()
+            && self.dangling_nodes.is_empty()
+    }
+}
+
+impl CheckpointStorage {
+    /// Check a session's timeline, refs and content pool against each other
+    ///
+    /// Every referenced blob is decompressed and rehashed. With `repair`, dangling nodes are
+    /// pruned (their children move up to the nearest surviving ancestor), references to
+    /// missing or corrupted blobs are flagged unrecoverable so restore leaves those files
+    /// alone, corrupted blobs no other session uses are quarantined, orphaned directories are
+    /// deleted along with their reference counts, and `total_checkpoints` is recomputed.
+    pub fn verify_integrity(
+        &self,
+        project_id: &str,
+        session_id: &str,
+        repair: bool,
+    ) -> Result<IntegrityReport> {
+        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
+        // Hold the timeline for the whole check so a repair can't undo a concurrent write
+        let lock = self.lock_timeline(&paths.timeline_file)?;
+        let mut timeline = lock.read()?;
+        let mut report = IntegrityReport::default();
+
+        // Map each node to its parent in the tree
+        let mut tree_parents: HashMap<String, Option<String>> = HashMap::new();
+        if let Some(root) = &timeline.root_node {
+            collect_tree_parents(root, None, &mut tree_parents);
+        }
+        report.checkpoints_checked = tree_parents.len();
+
+        // Checkpoint metadata and messages
+        for checkpoint_id in tree_parents.keys() {
+            if !self.checkpoint_files_readable(&paths, checkpoint_id)? {
+                report.dangling_nodes.push(checkpoint_id.clone());
+            }
+        }
+
+        // File references and the blobs behind them
+        let mut blob_health: HashMap<String, bool> = HashMap::new();
+        let mut damaged_refs: Vec<PathBuf> = Vec::new();
+
+        for checkpoint_id in tree_parents.keys() {
+            let refs_dir = paths.files_dir.join("refs").join(checkpoint_id);
+            if !refs_dir.exists() {
+                continue;
+            }
+            for entry in fs::read_dir(&refs_dir)? {
+                let ref_path = entry?.path();
+                if ref_path.extension().and_then(|e| e.to_str()) != Some("json") {
+                    continue;
+                }
+                let ref_metadata: serde_json::Value = match fs::read_to_string(&ref_path)
+                    .ok()
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                {
+                    Some(value) => value,
+                    None => {
+                        log::warn!("Unreadable file reference: {}", ref_path.display());
+                        continue;
+                    }
+                };
+
+                let hash = ref_metadata["hash"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                if hash.is_empty()
+                    || ref_metadata["is_deleted"].as_bool().unwrap_or(false)
+                    || ref_metadata["is_unrecoverable"].as_bool().unwrap_or(false)
+                {
+                    continue;
+                }
+
+                let issue = BlobIssue {
+                    checkpoint_id: checkpoint_id.clone(),
+                    file_path: PathBuf::from(ref_metadata["path"].as_str().unwrap_or("")),
+                    hash: hash.clone(),
+                };
+
+                let content_file = self.blob_path(&paths, &hash);
+                if !content_file.is_file() {
+                    report.missing_blobs.push(issue);
+                    damaged_refs.push(ref_path);
+                    continue;
+                }
+
+                let healthy = match blob_health.get(&hash) {
+                    Some(healthy) => *healthy,
+                    None => {
+                        let healthy = match self.read_blob(&paths, &hash) {
+                            Ok(content) => Self::calculate_file_hash(&content) == hash,
+                            Err(e) => missing_key(e)?,
+                        };
+                        blob_health.insert(hash.clone(), healthy);
+                        healthy
+                    }
+                };
+                if !healthy {
+                    report.corrupted_blobs.push(issue);
+                    damaged_refs.push(ref_path);
+                }
+            }
+        }
+        report.blobs_checked = blob_health.len();
+
+        // Directories left behind by checkpoints that are no longer in the timeline
+        let mut orphaned_dirs = Vec::new();
+        for dir in [paths.files_dir.join("refs"), paths.checkpoints_dir.clone()] {
+            if !dir.exists() {
+                continue;
+            }
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let id = match path.file_name().and_then(|n| n.to_str()) {
+                    Some(id) if path.is_dir() => id.to_string(),
+                    _ => continue,
+                };
+                if !tree_parents.contains_key(&id) {
+                    if !report.orphaned_refs.contains(&id) {
+                        report.orphaned_refs.push(id);
+                    }
+                    orphaned_dirs.push(path);
+                }
+            }
+        }
+
+        report.missing_blobs.sort_by(|a, b| a.hash.cmp(&b.hash));
+        report.corrupted_blobs.sort_by(|a, b| a.hash.cmp(&b.hash));
+        report.orphaned_refs.sort();
+        report.dangling_nodes.sort();
+
+        let count_matches = timeline.total_checkpoints == report.checkpoints_checked;
+        if !repair || (report.is_healthy() && count_matches) {
+            report.total_checkpoints = timeline.total_checkpoints;
+            return Ok(report);
+        }
+
+        // Flag references to lost content so restore skips those files
+        for ref_path in &damaged_refs {
+            let ref_json = fs::read_to_string(ref_path).context("Failed to read file reference")?;
+            let mut ref_metadata: serde_json::Value =
+                serde_json::from_str(&ref_json).context("Failed to parse file reference")?;
+            ref_metadata["is_unrecoverable"] = serde_json::Value::Bool(true);
+            fs::write(ref_path, serde_json::to_string_pretty(&ref_metadata)?)
+                .context("Failed to write file reference")?;
+        }
+        // Damaged blobs other sessions still point at are theirs to repair
+        let shared = match self.blobs_used_by_other_sessions(&paths) {
+            Ok(shared) => Some(shared),
+            Err(e) => {
+                log::warn!("Not quarantining damaged blobs: {}", e);
+                None
+            }
+        };
+        for (hash, healthy) in &blob_health {
+            if !healthy && shared.as_ref().is_some_and(|shared| !shared.contains(hash)) {
+                if let Err(e) = self.quarantine_blob(&paths, hash) {
+                    log::warn!("Failed to quarantine blob {}: {}", hash, e);
+                }
+            }
+        }
+
+        let refs_root = paths.files_dir.join("refs");
+        for dir in &orphaned_dirs {
+            if dir.parent() == Some(refs_root.as_path()) {
+                let id = dir.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+                let hashes = self.checkpoint_ref_hashes(&paths, id);
+                if let Err(e) = self.adjust_refcounts(&paths, &hashes, -1) {
+                    log::warn!("Failed to update content reference counts: {}", e);
+                }
+            }
+            if let Err(e) = fs::remove_dir_all(dir) {
+                log::warn!("Failed to remove orphaned {}: {}", dir.display(), e);
+            }
+        }
+
+        // Prune dangling nodes, moving their children up the tree
+        let doomed: HashSet<String> = report.dangling_nodes.iter().cloned().collect();
+        self.prune_checkpoints(&paths, &mut timeline, &doomed)?;
+        report.pruned_checkpoints = report.dangling_nodes.clone();
+
+        let mut remaining = Vec::new();
+        if let Some(root) = &timeline.root_node {
+            Self::collect_checkpoints(root, &mut remaining);
+        }
+        timeline.total_checkpoints = remaining.len();
+        lock.write(&timeline)?;
+
+        report.repaired = true;
+        report.total_checkpoints = timeline.total_checkpoints;
+        Ok(report)
+    }
+
+    /// Whether a checkpoint's metadata and messages can be read back
+    fn checkpoint_files_readable(
+        &self,
+        paths: &CheckpointPaths,
+        checkpoint_id: &str,
+    ) -> Result<bool> {
+        let metadata_ok = fs::read_to_string(paths.checkpoint_metadata_file(checkpoint_id))
+            .ok()
+            .and_then(|json| serde_json::from_str::<Checkpoint>(&json).ok())
+            .is_some();
+        let compressed = match fs::read(paths.checkpoint_messages_file(checkpoint_id)) {
+            Ok(sealed) => match self.unseal(sealed, &crypto::messages_context(checkpoint_id)) {
+                Ok(compressed) => Some(compressed),
+                Err(e) => missing_key(e)?,
+            },
+            Err(_) => None,
+        };
+        let messages_ok = compressed
+            .and_then(|compressed| decode_all(&compressed[..]).ok())
+            .and_then(|messages| String::from_utf8(messages).ok())
+            .is_some();
+        Ok(metadata_ok && messages_ok)
+    }
+}
+
+/// Treat unreadable data as damaged, unless only its encryption key is missing
+///
+/// Verification stops rather than report such data, so a repair can't delete it.
+fn missing_key<T: Default>(e: anyhow::Error) -> Result<T> {
+    if e.downcast_ref::<MissingKeyError>().is_some() {
+        return Err(e);
+    }
+    Ok(T::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkpoint::{manager::CheckpointManager, pool::QUARANTINE_DIR};
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    async fn manager(temp_dir: &TempDir, session_id: &str) -> CheckpointManager {
+        let project_path = temp_dir.path().join("project");
+        fs::create_dir_all(&project_path).unwrap();
+        CheckpointManager::new(
+            "test-project".to_string(),
+            session_id.to_string(),
+            project_path,
+            temp_dir.path().join("claude"),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn paths(storage: &CheckpointStorage, session_id: &str) -> CheckpointPaths {
+        CheckpointPaths::new(&storage.claude_dir, "test-project", session_id)
+    }
+
+    #[tokio::test]
+    async fn test_repair_flags_and_quarantines_corrupted_blobs() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = manager(&temp_dir, "test-session").await;
+        fs::write(temp_dir.path().join("project/a.txt"), "a").unwrap();
+        let checkpoint = manager.create_checkpoint(None, None).await.unwrap();
+
+        let storage = &manager.storage;
+        assert!(storage
+            .verify_integrity("test-project", "test-session", false)
+            .unwrap()
+            .is_healthy());
+
+        let hash = CheckpointStorage::calculate_file_hash(b"a");
+        let paths = paths(storage, "test-session");
+        fs::write(paths.content_pool_dir.join(&hash), b"not zstd").unwrap();
+
+        let report = storage
+            .verify_integrity("test-project", "test-session", false)
+            .unwrap();
+        assert_eq!(report.corrupted_blobs.len(), 1);
+        assert_eq!(report.corrupted_blobs[0].hash, hash);
+        assert!(!report.repaired);
+
+        let report = storage
+            .verify_integrity("test-project", "test-session", true)
+            .unwrap();
+        assert!(report.repaired);
+        assert!(!paths.content_pool_dir.join(&hash).exists());
+        assert_eq!(
+            fs::read(paths.content_pool_dir.join(QUARANTINE_DIR).join(&hash)).unwrap(),
+            b"not zstd"
+        );
+
+        // The file is left alone on restore, and the session checks out healthy again
+        let files = storage
+            .load_checkpoint_files("test-project", "test-session", &checkpoint.checkpoint.id)
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].is_unrecoverable);
+        assert!(storage
+            .verify_integrity("test-project", "test-session", false)
+            .unwrap()
+            .is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_repair_leaves_blobs_other_sessions_use() {
+        let temp_dir = TempDir::new().unwrap();
+        let ours = manager(&temp_dir, "test-session").await;
+        let theirs = manager(&temp_dir, "other-session").await;
+        fs::write(temp_dir.path().join("project/a.txt"), "a").unwrap();
+        ours.create_checkpoint(None, None).await.unwrap();
+        theirs.create_checkpoint(None, None).await.unwrap();
+
+        let hash = CheckpointStorage::calculate_file_hash(b"a");
+        let paths = paths(&ours.storage, "test-session");
+        fs::write(paths.content_pool_dir.join(&hash), b"not zstd").unwrap();
+
+        let report = ours
+            .storage
+            .verify_integrity("test-project", "test-session", true)
+            .unwrap();
+        assert_eq!(report.corrupted_blobs.len(), 1);
+        assert!(paths.content_pool_dir.join(&hash).exists());
+    }
+
+    #[tokio::test]
+    async fn test_repair_prunes_dangling_nodes_and_orphaned_refs() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = manager(&temp_dir, "test-session").await;
+        let project_file = temp_dir.path().join("project/a.txt");
+        let mut ids = Vec::new();
+        for content in ["a1", "a2", "a3"] {
+            fs::write(&project_file, content).unwrap();
+            ids.push(
+                manager
+                    .create_checkpoint(None, None)
+                    .await
+                    .unwrap()
+                    .checkpoint
+                    .id,
+            );
+        }
+
+        let storage = &manager.storage;
+        let paths = paths(storage, "test-session");
+        fs::remove_file(paths.checkpoint_messages_file(&ids[1])).unwrap();
+        let orphan = paths.files_dir.join("refs").join("orphan");
+        fs::create_dir_all(&orphan).unwrap();
+
+        let report = storage
+            .verify_integrity("test-project", "test-session", true)
+            .unwrap();
+        assert_eq!(report.dangling_nodes, vec![ids[1].clone()]);
+        assert_eq!(report.orphaned_refs, vec!["orphan".to_string()]);
+        assert_eq!(report.pruned_checkpoints, vec![ids[1].clone()]);
+        assert_eq!(report.total_checkpoints, 2);
+        assert!(!orphan.exists());
+
+        // The last checkpoint moves up to the first and still has its own content
+        let timeline = storage.load_timeline(&paths.timeline_file).unwrap();
+        let last = timeline.find_checkpoint(&ids[2]).unwrap();
+        assert_eq!(
+            last.checkpoint.parent_checkpoint_id.as_deref(),
+            Some(ids[0].as_str())
+        );
+        let files = storage
+            .load_checkpoint_files("test-project", "test-session", &ids[2])
+            .unwrap();
+        assert_eq!(files[0].file_path, Path::new("a.txt"));
+        assert_eq!(files[0].content, b"a3");
+    }
+}
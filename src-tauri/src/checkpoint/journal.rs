@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::{storage::CheckpointStorage, SessionTimeline};
+
+/// Exclusive hold on a session's `timeline.json`, released when dropped
+///
+/// Every read and write of a timeline goes through one of these, so managers in other
+/// windows or processes never interleave with each other. The lock lives in its own file
+/// because the timeline itself is replaced by a rename on every write. Locks aren't
+/// reentrant: don't load or save a timeline while holding its lock.
+pub struct TimelineLock {
+    timeline_path: PathBuf,
+    _lock_file: File,
+}
+
+impl TimelineLock {
+    /// Wait until the timeline at `timeline_path` can be locked
+    pub fn acquire(timeline_path: &Path) -> Result<Self> {
+        let lock_file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(timeline_path.with_extension("lock"))
+            .context("Failed to open timeline lock")?;
+        lock_file.lock().context("Failed to lock timeline")?;
+
+        Ok(Self {
+            timeline_path: timeline_path.to_path_buf(),
+            _lock_file: lock_file,
+        })
+    }
+
+    /// Read the timeline, first finishing or discarding a write that was interrupted
+    pub fn read(&self) -> Result<SessionTimeline> {
+        self.recover()?;
+        let timeline_json =
+            fs::read_to_string(&self.timeline_path).context("Failed to read timeline")?;
+        serde_json::from_str(&timeline_json).context("Failed to parse timeline")
+    }
+
+    /// Replace the timeline
+    ///
+    /// The new contents are journaled first, then written to a temporary file that is
+    /// renamed over the timeline, so a crash at any point leaves either the old or the new
+    /// timeline readable.
+    pub fn write(&self, timeline: &SessionTimeline) -> Result<()> {
+        let timeline_json =
+            serde_json::to_string_pretty(timeline).context("Failed to serialize timeline")?;
+        let journal = self.journal_path();
+        write_synced(&journal, timeline_json.as_bytes())
+            .context("Failed to write timeline journal")?;
+        self.replace(timeline_json.as_bytes())?;
+        fs::remove_file(&journal).context("Failed to clear timeline journal")
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.timeline_path.with_extension("journal")
+    }
+
+    /// Atomically swap in new timeline contents
+    fn replace(&self, contents: &[u8]) -> Result<()> {
+        let tmp_file = self.timeline_path.with_extension("json.tmp");
+        write_synced(&tmp_file, contents).context("Failed to write timeline")?;
+        fs::rename(&tmp_file, &self.timeline_path).context("Failed to replace timeline")?;
+
+        // Make the rename itself durable; not every platform can sync a directory
+        if let Some(dir) = self.timeline_path.parent() {
+            let _ = File::open(dir).and_then(|d| d.sync_all());
+        }
+        Ok(())
+    }
+
+    /// Roll a complete journal forward, or drop one that was cut off
+    fn recover(&self) -> Result<()> {
+        let journal = self.journal_path();
+        let journal_json = match fs::read(&journal) {
+            Ok(journal_json) => journal_json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).context("Failed to read timeline journal"),
+        };
+
+        // A journal that doesn't parse never got as far as touching the timeline
+        if serde_json::from_slice::<SessionTimeline>(&journal_json).is_ok() {
+            log::warn!(
+                "Recovering interrupted write of {}",
+                self.timeline_path.display()
+            );
+            self.replace(&journal_json)?;
+        } else {
+            log::warn!(
+                "Discarding incomplete timeline journal {}",
+                journal.display()
+            );
+            let _ = fs::remove_file(self.timeline_path.with_extension("json.tmp"));
+        }
+        fs::remove_file(&journal).context("Failed to clear timeline journal")
+    }
+}
+
+/// Write a file and flush it to disk
+fn write_synced(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+impl CheckpointStorage {
+    /// Lock a session's timeline for a sequence of reads and writes
+    pub fn lock_timeline(&self, timeline_path: &Path) -> Result<TimelineLock> {
+        TimelineLock::acquire(timeline_path)
+    }
+
+    /// Load, change and save a timeline without letting another writer in between
+    pub fn update_timeline<T>(
+        &self,
+        timeline_path: &Path,
+        update: impl FnOnce(&mut SessionTimeline) -> Result<T>,
+    ) -> Result<T> {
+        let lock = self.lock_timeline(timeline_path)?;
+        let mut timeline = lock.read()?;
+        let result = update(&mut timeline)?;
+        lock.write(&timeline)?;
+        Ok(result)
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use std::fs;
+
+use super::{storage::CheckpointStorage, Checkpoint, CheckpointPaths};
+
+/// Longest tag accepted, in characters
+pub const MAX_TAG_LENGTH: usize = 64;
+
+/// Trim tags and drop empty and duplicate ones, keeping the first spelling of each
+pub fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            anyhow::bail!("Tag is longer than {} characters: {}", MAX_TAG_LENGTH, tag);
+        }
+        if !normalized.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            normalized.push(tag.to_string());
+        }
+    }
+    Ok(normalized)
+}
+
+impl CheckpointStorage {
+    /// Rename a checkpoint and/or replace its tags, saving the timeline
+    ///
+    /// `None` leaves a field as it is. A blank description clears it. The change is made to
+    /// the timeline on disk under its lock, so concurrent writers aren't undone.
+    pub fn update_checkpoint_labels(
+        &self,
+        project_id: &str,
+        session_id: &str,
+        checkpoint_id: &str,
+        description: Option<String>,
+        tags: Option<Vec<String>>,
+    ) -> Result<Checkpoint> {
+        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
+        let tags = tags.map(normalize_tags).transpose()?;
+
+        self.update_timeline(&paths.timeline_file, |timeline| {
+            let node = timeline
+                .find_checkpoint_mut(checkpoint_id)
+                .ok_or_else(|| anyhow::anyhow!("Checkpoint not found: {}", checkpoint_id))?;
+            if let Some(description) = description {
+                let description = description.trim();
+                node.checkpoint.description =
+                    (!description.is_empty()).then(|| description.to_string());
+                // A description set by hand is the user's own
+                node.checkpoint.auto_generated = false;
+            }
+            if let Some(tags) = tags {
+                node.checkpoint.tags = tags;
+            }
+            let checkpoint = node.checkpoint.clone();
+
+            // Keep the checkpoint's own metadata in step with the timeline
+            let metadata_json = serde_json::to_string_pretty(&checkpoint)
+                .context("Failed to serialize checkpoint metadata")?;
+            fs::write(paths.checkpoint_metadata_file(checkpoint_id), metadata_json)
+                .context("Failed to write checkpoint metadata")?;
+
+            Ok(checkpoint)
+        })
+    }
+}
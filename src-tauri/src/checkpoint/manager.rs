@@ -1,16 +1,26 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, TimeZone, Utc};
 use log;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::SystemTime;
 use tokio::sync::RwLock;
 
 use super::{
+    diff,
+    git::{self, GitRepository},
+    integrity::IntegrityReport,
+    merge::MergeResult,
+    retention::{RetentionPolicy, RetentionResult},
+    rules::{CompiledSmartRules, SmartRules},
     storage::{self, CheckpointStorage},
-    Checkpoint, CheckpointMetadata, CheckpointPaths, CheckpointResult, CheckpointStrategy,
-    FileSnapshot, FileState, FileTracker, SessionTimeline,
+    walk::{self, collect_project_files},
+    watcher::ProjectWatcher,
+    Checkpoint, CheckpointBackend, CheckpointDiff, CheckpointMetadata, CheckpointPaths,
+    CheckpointResult, CheckpointStrategy, ConflictResolution, ExternalChange, ExternalChangeKind,
+    FileKind, FileSnapshot, FileState, FileTracker, RestorePlan, SessionTimeline,
 };
 
 /// Manages checkpoint operations for a session
@@ -22,6 +32,11 @@ pub struct CheckpointManager {
     pub storage: Arc<CheckpointStorage>,
     timeline: Arc<RwLock<SessionTimeline>>,
     current_messages: Arc<RwLock<Vec<String>>>, // JSONL messages
+    watcher: std::sync::Mutex<Option<ProjectWatcher>>,
+    /// Ignore rules seen by the latest walk of the project
+    ignore: std::sync::Mutex<walk::IgnoreMatcher>,
+    /// Smart rules as compiled from the rules file with the given modification time
+    smart_rules: std::sync::Mutex<Option<(Option<SystemTime>, Arc<CompiledSmartRules>)>>,
 }
 
 impl CheckpointManager {
@@ -47,7 +62,9 @@ impl CheckpointManager {
 
         let file_tracker = FileTracker {
             tracked_files: HashMap::new(),
+            has_baseline: false,
         };
+        let ignore = walk::build_ignore_matcher(&project_path);
 
         Ok(Self {
             project_id,
@@ -57,9 +74,83 @@ impl CheckpointManager {
             storage,
             timeline: Arc::new(RwLock::new(timeline)),
             current_messages: Arc::new(RwLock::new(Vec::new())),
+            watcher: std::sync::Mutex::new(None),
+            ignore: std::sync::Mutex::new(ignore),
+            smart_rules: std::sync::Mutex::new(None),
         })
     }
 
+    /// Whether the project's ignore rules exclude a tracked path from checkpoints
+    fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        // Tool paths may be absolute; only the part inside the project is matched
+        let rel_path = match path.strip_prefix(&self.project_path) {
+            Ok(rel_path) => rel_path,
+            Err(_) if path.is_absolute() => return false,
+            Err(_) => path,
+        };
+        self.ignore
+            .lock()
+            .map(|ignore| walk::is_excluded(rel_path, &ignore, is_dir))
+            .unwrap_or(false)
+    }
+
+    /// Remember the ignore rules a fresh walk of the project applied
+    fn update_ignore(&self, ignore: &walk::IgnoreMatcher) {
+        if let Ok(mut current) = self.ignore.lock() {
+            *current = ignore.clone();
+        }
+    }
+
+    /// Walk the project, remembering the ignore rules the walk applied
+    async fn walk_project(&self) -> walk::ProjectFiles {
+        let max_file_size = self.timeline.read().await.max_file_size;
+        let project_files = collect_project_files(&self.project_path, max_file_size);
+        self.update_ignore(&project_files.ignore);
+        project_files
+    }
+
+    /// Drop snapshots of paths ignored as of the latest walk, so restoring leaves them alone
+    fn without_excluded(&self, mut snapshots: Vec<FileSnapshot>) -> Vec<FileSnapshot> {
+        snapshots.retain(|s| !self.is_excluded(&s.file_path, s.kind == FileKind::Directory));
+        snapshots
+    }
+
+    /// Start recording file changes in the project with a filesystem watcher
+    ///
+    /// While the watcher runs, Bash commands no longer mark every tracked file as modified.
+    pub async fn start_watching(&self) -> Result<()> {
+        if self.is_watching() {
+            return Ok(());
+        }
+
+        let max_file_size = self.timeline.read().await.max_file_size;
+        let watcher = ProjectWatcher::start(
+            self.project_path.clone(),
+            Arc::clone(&self.file_tracker),
+            max_file_size,
+        )?;
+
+        if let Ok(mut slot) = self.watcher.lock() {
+            *slot = Some(watcher);
+        }
+        Ok(())
+    }
+
+    /// Stop the filesystem watcher, if one is running
+    pub fn stop_watching(&self) {
+        if let Ok(mut slot) = self.watcher.lock() {
+            *slot = None;
+        }
+    }
+
+    /// Whether a filesystem watcher is recording changes for this session
+    pub fn is_watching(&self) -> bool {
+        self.watcher
+            .lock()
+            .map(|slot| slot.is_some())
+            .unwrap_or(false)
+    }
+
     /// Track a new message in the session
     pub async fn track_message(&self, jsonl_message: String) -> Result<()> {
         let mut messages = self.current_messages.write().await;
@@ -94,7 +185,10 @@ impl CheckpointManager {
                 }
             }
             "bash" => {
-                // Try to detect file modifications from bash commands
+                // The watcher records exact changes; fall back to guessing without one
+                if self.is_watching() {
+                    return Ok(());
+                }
                 if let Some(command) = input.get("command").and_then(|c| c.as_str()) {
                     self.track_bash_side_effects(command).await?;
                 }
@@ -109,28 +203,34 @@ impl CheckpointManager {
         let mut tracker = self.file_tracker.write().await;
         let full_path = self.project_path.join(file_path);
 
-        // Read current file state
-        let (hash, exists, _size, modified) = if full_path.exists() {
-            let content = fs::read_to_string(&full_path).unwrap_or_default();
-            let metadata = fs::metadata(&full_path)?;
-            let modified = metadata
-                .modified()
-                .ok()
-                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                .map(|d| {
-                    Utc.timestamp_opt(d.as_secs() as i64, d.subsec_nanos())
-                        .unwrap()
-                })
-                .unwrap_or_else(Utc::now);
+        // Ignored files aren't checkpointed, even when a tool edits them
+        if self.is_excluded(Path::new(file_path), full_path.is_dir()) {
+            tracker.tracked_files.remove(&PathBuf::from(file_path));
+            return Ok(());
+        }
 
-            (
-                storage::CheckpointStorage::calculate_file_hash(&content),
-                true,
-                metadata.len(),
-                modified,
-            )
-        } else {
-            (String::new(), false, 0, Utc::now())
+        // Read current file state, without following symbolic links
+        let (hash, exists, kind, permissions, modified) = match walk::read_entry(&full_path) {
+            Ok(entry) => {
+                let modified = fs::symlink_metadata(&full_path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| {
+                        Utc.timestamp_opt(d.as_secs() as i64, d.subsec_nanos())
+                            .unwrap()
+                    })
+                    .unwrap_or_else(Utc::now);
+
+                (
+                    storage::CheckpointStorage::calculate_file_hash(&entry.content),
+                    true,
+                    entry.kind,
+                    entry.permissions,
+                    modified,
+                )
+            }
+            Err(_) => (String::new(), false, FileKind::File, None, Utc::now()),
         };
 
         // Check if file has actually changed
@@ -139,9 +239,12 @@ impl CheckpointManager {
                 // File is modified if:
                 // 1. Hash has changed
                 // 2. Existence state has changed
-                // 3. It was already marked as modified
+                // 3. It became a different kind of entry or its mode changed
+                // 4. It was already marked as modified
                 existing_state.last_hash != hash
                     || existing_state.exists != exists
+                    || existing_state.kind != kind
+                    || existing_state.permissions != permissions
                     || existing_state.is_modified
             } else {
                 // New file is always considered modified
@@ -155,6 +258,8 @@ impl CheckpointManager {
                 is_modified,
                 last_modified: modified,
                 exists,
+                kind,
+                permissions,
             },
         );
 
@@ -189,6 +294,28 @@ impl CheckpointManager {
         &self,
         description: Option<String>,
         parent_checkpoint_id: Option<String>,
+    ) -> Result<CheckpointResult> {
+        self.save_checkpoint(description, parent_checkpoint_id, false)
+            .await
+    }
+
+    /// Create a checkpoint the user didn't ask for, with a generated description
+    ///
+    /// Retention doesn't count the description as the user's, so it doesn't protect it.
+    pub async fn create_auto_checkpoint(
+        &self,
+        description: String,
+        parent_checkpoint_id: Option<String>,
+    ) -> Result<CheckpointResult> {
+        self.save_checkpoint(Some(description), parent_checkpoint_id, true)
+            .await
+    }
+
+    async fn save_checkpoint(
+        &self,
+        description: Option<String>,
+        parent_checkpoint_id: Option<String>,
+        auto_generated: bool,
     ) -> Result<CheckpointResult> {
         let messages = self.current_messages.read().await;
         let message_index = messages.len().saturating_sub(1);
@@ -198,36 +325,34 @@ impl CheckpointManager {
             self.extract_checkpoint_metadata(&messages).await?;
 
         // Ensure every file in the project is tracked so new checkpoints include all files
-        // Recursively walk the project directory and track each file
-        fn collect_files(
-            dir: &std::path::Path,
-            base: &std::path::Path,
-            files: &mut Vec<std::path::PathBuf>,
-        ) -> Result<(), std::io::Error> {
-            for entry in std::fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    // Skip hidden directories like .git
-                    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                        if name.starts_with('.') {
-                            continue;
-                        }
-                    }
-                    collect_files(&path, base, files)?;
-                } else if path.is_file() {
-                    // Compute relative path from project root
-                    if let Ok(rel) = path.strip_prefix(base) {
-                        files.push(rel.to_path_buf());
-                    }
-                }
-            }
-            Ok(())
-        }
-        let mut all_files = Vec::new();
-        let project_dir = &self.project_path;
-        let _ = collect_files(project_dir.as_path(), project_dir.as_path(), &mut all_files);
-        for rel in all_files {
+        // that aren't ignored or over the size cap
+        let max_file_size = self.timeline.read().await.max_file_size;
+        let project_files = self.walk_project().await;
+        let mut oversized: std::collections::BTreeMap<PathBuf, u64> =
+            project_files.oversized.into_iter().collect();
+
+        // Stop snapshotting files that have become ignored since they were tracked
+        self.file_tracker
+            .write()
+            .await
+            .tracked_files
+            .retain(|path, state| {
+                !self.is_excluded(path, state.exists && state.kind == FileKind::Directory)
+            });
+
+        // Re-read tracked paths the walk didn't find too, so a deletion that no tool or
+        // watcher reported is still recorded
+        let mut paths: BTreeSet<PathBuf> = self
+            .file_tracker
+            .read()
+            .await
+            .tracked_files
+            .keys()
+            .cloned()
+            .collect();
+        paths.extend(project_files.files.iter().cloned());
+        paths.extend(project_files.empty_dirs.iter().cloned());
+        for rel in &paths {
             if let Some(p) = rel.to_str() {
                 // Track each file for snapshot
                 let _ = self.track_file_modification(p).await;
@@ -237,8 +362,48 @@ impl CheckpointManager {
         // Generate checkpoint ID early so snapshots reference it
         let checkpoint_id = storage::CheckpointStorage::generate_checkpoint_id();
 
-        // Create file snapshots
-        let file_snapshots = self.create_file_snapshots(&checkpoint_id).await?;
+        let parent_checkpoint_id = match parent_checkpoint_id {
+            Some(parent_id) => Some(parent_id),
+            // Perform an asynchronous read to avoid blocking within the runtime
+            None => self.timeline.read().await.current_checkpoint_id.clone(),
+        };
+        let (backend, parent_commit) = {
+            let timeline = self.timeline.read().await;
+            let parent_commit = parent_checkpoint_id
+                .as_deref()
+                .and_then(|id| timeline.find_checkpoint(id))
+                .and_then(|node| node.checkpoint.git_commit.clone());
+            (timeline.backend, parent_commit)
+        };
+
+        // Create file snapshots, or a commit holding every file with the git backend
+        let (file_snapshots, git_commit, file_changes) = match backend {
+            CheckpointBackend::Native => {
+                let (file_snapshots, skipped) = self
+                    .create_file_snapshots(&checkpoint_id, max_file_size)
+                    .await?;
+                oversized.extend(skipped);
+                let file_changes = file_snapshots.len();
+                (file_snapshots, None, file_changes)
+            }
+            CheckpointBackend::Git => {
+                let repo = GitRepository::open(&self.project_path)?;
+                let message = description
+                    .clone()
+                    .unwrap_or_else(|| format!("Checkpoint {}", checkpoint_id));
+                let commit = repo.commit_snapshot(
+                    &project_files.files,
+                    parent_commit.as_deref(),
+                    &message,
+                    &git::checkpoint_ref(&self.session_id, &checkpoint_id),
+                )?;
+                let file_changes = match &parent_commit {
+                    Some(parent) => repo.diff_trees(parent, &commit)?.len(),
+                    None => project_files.files.len(),
+                };
+                (Vec::new(), Some(commit), file_changes)
+            }
+        };
 
         // Generate checkpoint struct
         let checkpoint = Checkpoint {
@@ -248,36 +413,41 @@ impl CheckpointManager {
             message_index,
             timestamp: Utc::now(),
             description,
-            parent_checkpoint_id: {
-                if let Some(parent_id) = parent_checkpoint_id {
-                    Some(parent_id)
-                } else {
-                    // Perform an asynchronous read to avoid blocking within the runtime
-                    let timeline = self.timeline.read().await;
-                    timeline.current_checkpoint_id.clone()
-                }
-            },
+            parent_checkpoint_id,
             metadata: CheckpointMetadata {
                 total_tokens,
                 model_used,
                 user_prompt,
-                file_changes: file_snapshots.len(),
-                snapshot_size: storage::CheckpointStorage::estimate_checkpoint_size(
-                    &messages.join("\n"),
-                    &file_snapshots,
-                ),
+                file_changes,
+                // Filled in by storage once the checkpoint is on disk
+                snapshot_size: 0,
             },
+            git_commit,
+            merge_parent_id: None,
+            tags: Vec::new(),
+            auto_generated,
         };
 
+        let deleted: Vec<PathBuf> = file_snapshots
+            .iter()
+            .filter(|s| s.is_deleted)
+            .map(|s| s.file_path.clone())
+            .collect();
+
         // Save checkpoint
         let messages_content = messages.join("\n");
-        let result = self.storage.save_checkpoint(
+        let mut result = self.storage.save_checkpoint(
             &self.project_id,
             &self.session_id,
             &checkpoint,
             file_snapshots,
             &messages_content,
         )?;
+        result.warnings.extend(
+            oversized
+                .iter()
+                .map(|(path, size)| walk::oversized_warning(path, *size, max_file_size)),
+        );
 
         // Reload timeline from disk so in-memory timeline has updated nodes and total_checkpoints
         let claude_dir = self.storage.claude_dir.clone();
@@ -292,11 +462,44 @@ impl CheckpointManager {
         let mut timeline = self.timeline.write().await;
         timeline.current_checkpoint_id = Some(checkpoint_id);
 
-        // Reset file tracker
+        // Reset file tracker, remembering which files the checkpoint found deleted
         let mut tracker = self.file_tracker.write().await;
         for (_, state) in tracker.tracked_files.iter_mut() {
             state.is_modified = false;
         }
+        for path in &deleted {
+            if let Some(state) = tracker.tracked_files.get_mut(path) {
+                state.exists = false;
+            }
+        }
+        tracker.has_baseline = true;
+        drop(tracker);
+
+        // Thin out older checkpoints now that a new one exists
+        if timeline.retention.enabled {
+            let retention = self
+                .storage
+                .apply_retention(&self.project_id, &self.session_id);
+            match retention {
+                Ok(retention) if !retention.removed_checkpoints.is_empty() => {
+                    log::info!(
+                        "Retention removed {} checkpoints from session {}",
+                        retention.removed_checkpoints.len(),
+                        self.session_id
+                    );
+                    match self.storage.load_timeline(&paths.timeline_file) {
+                        Ok(pruned) => *timeline = pruned,
+                        Err(e) => result
+                            .warnings
+                            .push(format!("Failed to reload timeline after retention: {}", e)),
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => result
+                    .warnings
+                    .push(format!("Failed to apply retention policy: {}", e)),
+            }
+        }
 
         Ok(result)
     }
@@ -398,9 +601,16 @@ impl CheckpointManager {
     }
 
     /// Create file snapshots for all tracked modified files
-    async fn create_file_snapshots(&self, checkpoint_id: &str) -> Result<Vec<FileSnapshot>> {
+    ///
+    /// Files over `max_file_size` are skipped and returned alongside their size.
+    async fn create_file_snapshots(
+        &self,
+        checkpoint_id: &str,
+        max_file_size: u64,
+    ) -> Result<(Vec<FileSnapshot>, Vec<(PathBuf, u64)>)> {
         let tracker = self.file_tracker.read().await;
         let mut snapshots = Vec::new();
+        let mut oversized = Vec::new();
 
         for (rel_path, state) in &tracker.tracked_files {
             // Skip files that haven't been modified
@@ -410,149 +620,269 @@ impl CheckpointManager {
 
             let full_path = self.project_path.join(rel_path);
 
-            let (content, exists, permissions, size, current_hash) = if full_path.exists() {
-                let content = fs::read_to_string(&full_path).unwrap_or_default();
-                let current_hash = storage::CheckpointStorage::calculate_file_hash(&content);
-
-                // Don't skip based on hash - if is_modified is true, we should snapshot it
-                // The hash check in track_file_modification already determined if it changed
+            if let Ok(metadata) = fs::symlink_metadata(&full_path) {
+                if metadata.is_file() && metadata.len() > max_file_size {
+                    oversized.push((rel_path.clone(), metadata.len()));
+                    continue;
+                }
+            }
 
-                let metadata = fs::metadata(&full_path)?;
-                let permissions = {
-                    #[cfg(unix)]
-                    {
-                        use std::os::unix::fs::PermissionsExt;
-                        Some(metadata.permissions().mode())
-                    }
-                    #[cfg(not(unix))]
-                    {
-                        None
-                    }
-                };
-                (content, true, permissions, metadata.len(), current_hash)
-            } else {
-                (String::new(), false, None, 0, String::new())
+            // Don't skip based on hash - if is_modified is true, we should snapshot it
+            // The hash check in track_file_modification already determined if it changed
+            let entry = match walk::read_entry(&full_path) {
+                Ok(entry) => Some(entry),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    log::warn!(
+                        "Failed to read {}, not snapshotted: {}",
+                        rel_path.display(),
+                        e
+                    );
+                    continue;
+                }
             };
 
-            snapshots.push(FileSnapshot {
-                checkpoint_id: checkpoint_id.to_string(),
-                file_path: rel_path.clone(),
-                content,
-                hash: current_hash,
-                is_deleted: !exists,
-                permissions,
-                size,
+            snapshots.push(match entry {
+                Some(entry) => FileSnapshot {
+                    checkpoint_id: checkpoint_id.to_string(),
+                    file_path: rel_path.clone(),
+                    hash: storage::CheckpointStorage::calculate_file_hash(&entry.content),
+                    size: entry.content.len() as u64,
+                    content: entry.content,
+                    is_deleted: false,
+                    permissions: entry.permissions,
+                    kind: entry.kind,
+                    is_unrecoverable: false,
+                },
+                None => FileSnapshot {
+                    checkpoint_id: checkpoint_id.to_string(),
+                    file_path: rel_path.clone(),
+                    content: Vec::new(),
+                    hash: String::new(),
+                    is_deleted: true,
+                    permissions: None,
+                    size: 0,
+                    kind: FileKind::File,
+                    is_unrecoverable: false,
+                },
             });
         }
 
-        Ok(snapshots)
+        Ok((snapshots, oversized))
     }
 
-    /// Restore a checkpoint
-    pub async fn restore_checkpoint(&self, checkpoint_id: &str) -> Result<CheckpointResult> {
-        // Load checkpoint data
-        let (checkpoint, file_snapshots, messages) =
-            self.storage
-                .load_checkpoint(&self.project_id, &self.session_id, checkpoint_id)?;
+    /// Seed the file tracker from the current checkpoint after a restart
+    ///
+    /// A fresh manager has no record of the project, so the full tree of the checkpoint the
+    /// timeline points at stands in for it. Edits made after that checkpoint in an earlier run
+    /// are therefore reported as external.
+    async fn rebuild_baseline(&self) -> Result<()> {
+        if self.file_tracker.read().await.has_baseline {
+            return Ok(());
+        }
+        let Some(checkpoint_id) = self.timeline.read().await.current_checkpoint_id.clone() else {
+            return Ok(());
+        };
+        let snapshots = self.storage.load_checkpoint_files(
+            &self.project_id,
+            &self.session_id,
+            &checkpoint_id,
+        )?;
 
-        // First, collect all files currently in the project to handle deletions
-        fn collect_all_project_files(
-            dir: &std::path::Path,
-            base: &std::path::Path,
-            files: &mut Vec<std::path::PathBuf>,
-        ) -> Result<(), std::io::Error> {
-            for entry in std::fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    // Skip hidden directories like .git
-                    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                        if name.starts_with('.') {
-                            continue;
-                        }
-                    }
-                    collect_all_project_files(&path, base, files)?;
-                } else if path.is_file() {
-                    // Compute relative path from project root
-                    if let Ok(rel) = path.strip_prefix(base) {
-                        files.push(rel.to_path_buf());
+        let mut tracker = self.file_tracker.write().await;
+        for snapshot in snapshots {
+            if snapshot.is_deleted || snapshot.is_unrecoverable {
+                continue;
+            }
+            tracker
+                .tracked_files
+                .entry(snapshot.file_path)
+                .or_insert(FileState {
+                    last_hash: snapshot.hash,
+                    is_modified: false,
+                    last_modified: Utc::now(),
+                    exists: true,
+                    kind: snapshot.kind,
+                    permissions: snapshot.permissions.map(|m| m & walk::PERMISSION_BITS),
+                });
+        }
+        tracker.has_baseline = true;
+        Ok(())
+    }
+
+    /// Find files changed on disk by something other than this session
+    ///
+    /// On-disk hashes are compared with what the file tracker recorded at the last checkpoint
+    /// or tool edit. Without either, the baseline is rebuilt from the current checkpoint; a
+    /// session with no checkpoints reports nothing.
+    pub async fn detect_external_changes(&self) -> Vec<ExternalChange> {
+        if let Err(e) = self.rebuild_baseline().await {
+            log::warn!("Failed to rebuild file baseline: {}", e);
+        }
+        let max_file_size = self.timeline.read().await.max_file_size;
+        let tracker = self.file_tracker.read().await;
+        if !tracker.has_baseline {
+            return Vec::new();
+        }
+
+        let project_files = collect_project_files(&self.project_path, max_file_size);
+        let on_disk: Vec<PathBuf> = project_files
+            .files
+            .into_iter()
+            .chain(project_files.empty_dirs)
+            .collect();
+        let mut changes = Vec::new();
+
+        for rel_path in &on_disk {
+            let kind = match tracker.tracked_files.get(rel_path) {
+                Some(state) if state.exists => {
+                    let unchanged =
+                        walk::read_entry(&self.project_path.join(rel_path)).is_ok_and(|entry| {
+                            entry.kind == state.kind
+                                && entry.permissions == state.permissions
+                                && CheckpointStorage::calculate_file_hash(&entry.content)
+                                    == state.last_hash
+                        });
+                    if unchanged {
+                        continue;
                     }
+                    ExternalChangeKind::Modified
                 }
+                _ => ExternalChangeKind::Created,
+            };
+            changes.push(ExternalChange {
+                path: rel_path.clone(),
+                kind,
+            });
+        }
+
+        let on_disk: std::collections::HashSet<&PathBuf> = on_disk.iter().collect();
+        for (rel_path, state) in &tracker.tracked_files {
+            if state.exists
+                && !on_disk.contains(rel_path)
+                && fs::symlink_metadata(self.project_path.join(rel_path)).is_err()
+            {
+                changes.push(ExternalChange {
+                    path: rel_path.clone(),
+                    kind: ExternalChangeKind::Deleted,
+                });
             }
-            Ok(())
         }
 
-        let mut current_files = Vec::new();
-        let _ =
-            collect_all_project_files(&self.project_path, &self.project_path, &mut current_files);
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        changes
+    }
 
-        // Create a set of files that should exist after restore
-        let mut checkpoint_files = std::collections::HashSet::new();
-        for snapshot in &file_snapshots {
-            if !snapshot.is_deleted {
-                checkpoint_files.insert(snapshot.file_path.clone());
+    /// Restore a checkpoint, deciding what to do with external edits first
+    pub async fn restore_checkpoint_with_resolution(
+        &self,
+        checkpoint_id: &str,
+        resolution: ConflictResolution,
+    ) -> Result<CheckpointResult> {
+        let changes = self.detect_external_changes().await;
+        if changes.is_empty() {
+            return self.restore_checkpoint(checkpoint_id).await;
+        }
+
+        match resolution {
+            ConflictResolution::Force => self.restore_checkpoint(checkpoint_id).await,
+            ConflictResolution::Abort => {
+                let paths: Vec<String> = changes
+                    .iter()
+                    .map(|c| c.path.display().to_string())
+                    .collect();
+                anyhow::bail!(
+                    "{} file(s) were modified outside the session: {}",
+                    changes.len(),
+                    paths.join(", ")
+                )
+            }
+            ConflictResolution::Stash => {
+                let stash = self
+                    .create_auto_checkpoint("Pre-restore: external edits".to_string(), None)
+                    .await
+                    .context("Failed to stash external edits")?;
+                log::info!(
+                    "Stashed {} external edit(s) in checkpoint {}",
+                    changes.len(),
+                    stash.checkpoint.id
+                );
+
+                let mut result = self.restore_checkpoint(checkpoint_id).await?;
+                result.warnings.push(format!(
+                    "Stashed {} externally modified file(s) in checkpoint {}",
+                    changes.len(),
+                    stash.checkpoint.id
+                ));
+                Ok(result)
             }
         }
+    }
+
+    /// Restore a checkpoint
+    pub async fn restore_checkpoint(&self, checkpoint_id: &str) -> Result<CheckpointResult> {
+        // Load checkpoint data
+        let (checkpoint, file_snapshots, messages) =
+            self.storage
+                .load_checkpoint_tree(&self.project_id, &self.session_id, checkpoint_id)?;
+        let project_files = self.walk_project().await;
+        let file_snapshots = self.without_excluded(file_snapshots);
+
+        // Work out which files need to go before touching anything
+        let plan = self
+            .build_restore_plan(
+                checkpoint.clone(),
+                &file_snapshots,
+                &messages,
+                project_files,
+                None,
+            )
+            .await;
 
         // Delete files that exist now but shouldn't exist in the checkpoint
         let mut warnings = Vec::new();
         let mut files_processed = 0;
 
-        for current_file in current_files {
-            if !checkpoint_files.contains(&current_file) {
-                // This file exists now but not in the checkpoint, so delete it
-                let full_path = self.project_path.join(&current_file);
-                match fs::remove_file(&full_path) {
-                    Ok(_) => {
-                        files_processed += 1;
-                        log::info!("Deleted file not in checkpoint: {:?}", current_file);
-                    }
-                    Err(e) => {
-                        warnings.push(format!(
-                            "Failed to delete {}: {}",
-                            current_file.display(),
-                            e
-                        ));
-                    }
+        for current_file in &plan.files_to_delete {
+            // This file exists now but not in the checkpoint, so delete it
+            let full_path = self.project_path.join(current_file);
+            match walk::remove_entry(&full_path) {
+                Ok(_) => {
+                    files_processed += 1;
+                    log::info!("Deleted file not in checkpoint: {:?}", current_file);
+                }
+                Err(e) => {
+                    warnings.push(format!(
+                        "Failed to delete {}: {}",
+                        current_file.display(),
+                        e
+                    ));
                 }
             }
         }
 
-        // Clean up empty directories
-        fn remove_empty_dirs(
-            dir: &std::path::Path,
-            base: &std::path::Path,
-        ) -> Result<bool, std::io::Error> {
-            if dir == base {
-                return Ok(false); // Don't remove the base directory
-            }
-
-            let mut is_empty = true;
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    if !remove_empty_dirs(&path, base)? {
-                        is_empty = false;
-                    }
-                } else {
-                    is_empty = false;
+        // Clean up directories emptied by the deletions, keeping those the checkpoint has
+        let kept_dirs = checkpoint_dirs(&file_snapshots);
+        for current_file in &plan.files_to_delete {
+            for dir in current_file.ancestors().skip(1) {
+                if dir.as_os_str().is_empty()
+                    || kept_dirs.contains(dir)
+                    || fs::remove_dir(self.project_path.join(dir)).is_err()
+                {
+                    break;
                 }
             }
-
-            if is_empty {
-                fs::remove_dir(dir)?;
-                Ok(true)
-            } else {
-                Ok(false)
-            }
         }
 
-        // Clean up any empty directories left after file deletion
-        let _ = remove_empty_dirs(&self.project_path, &self.project_path);
-
         // Restore files from checkpoint
         for snapshot in &file_snapshots {
+            if snapshot.is_unrecoverable {
+                warnings.push(format!(
+                    "Skipped {}: snapshot content is unrecoverable, leaving file untouched",
+                    snapshot.file_path.display()
+                ));
+                continue;
+            }
             match self.restore_file_snapshot(snapshot).await {
                 Ok(_) => files_processed += 1,
                 Err(e) => warnings.push(format!(
@@ -577,6 +907,7 @@ impl CheckpointManager {
         // Update file tracker
         let mut tracker = self.file_tracker.write().await;
         tracker.tracked_files.clear();
+        tracker.has_baseline = true;
         for snapshot in &file_snapshots {
             if !snapshot.is_deleted {
                 tracker.tracked_files.insert(
@@ -586,6 +917,8 @@ impl CheckpointManager {
                         is_modified: false,
                         last_modified: Utc::now(),
                         exists: true,
+                        kind: snapshot.kind,
+                        permissions: snapshot.permissions.map(|m| m & walk::PERMISSION_BITS),
                     },
                 );
             }
@@ -598,29 +931,310 @@ impl CheckpointManager {
         })
     }
 
+    /// Preview a restore without modifying any files or session state
+    ///
+    /// When `paths` is given, the preview covers a selective restore of those paths only.
+    pub async fn plan_restore(
+        &self,
+        checkpoint_id: &str,
+        paths: Option<Vec<PathBuf>>,
+    ) -> Result<RestorePlan> {
+        let (checkpoint, file_snapshots, messages) =
+            self.storage
+                .load_checkpoint_tree(&self.project_id, &self.session_id, checkpoint_id)?;
+        let project_files = self.walk_project().await;
+        let file_snapshots = self.without_excluded(file_snapshots);
+
+        let filters = paths
+            .map(|paths| self.normalize_path_filters(paths))
+            .transpose()?;
+        Ok(self
+            .build_restore_plan(
+                checkpoint,
+                &file_snapshots,
+                &messages,
+                project_files,
+                filters.as_deref(),
+            )
+            .await)
+    }
+
+    /// Compare a checkpoint's snapshots with the project directory as `project_files` found it
+    ///
+    /// With `filters`, only files at or below one of the given relative paths are considered
+    /// and the conversation is left as is.
+    async fn build_restore_plan(
+        &self,
+        checkpoint: Checkpoint,
+        file_snapshots: &[FileSnapshot],
+        messages: &str,
+        project_files: walk::ProjectFiles,
+        filters: Option<&[PathBuf]>,
+    ) -> RestorePlan {
+        let in_scope = |path: &Path| filters.is_none_or(|f| matches_path_filters(path, f));
+
+        // Create a set of files that should exist after restore
+        let checkpoint_files: std::collections::HashSet<&PathBuf> = file_snapshots
+            .iter()
+            .filter(|s| !s.is_deleted)
+            .map(|s| &s.file_path)
+            .collect();
+
+        let kept_dirs = checkpoint_dirs(file_snapshots);
+
+        // Git can't record empty directories, so git-backed checkpoints leave them alone
+        let empty_dirs = match checkpoint.git_commit {
+            Some(_) => Vec::new(),
+            None => project_files.empty_dirs,
+        };
+        let mut files_to_delete: Vec<PathBuf> = project_files
+            .files
+            .into_iter()
+            .chain(empty_dirs)
+            .filter(|f| in_scope(f) && !checkpoint_files.contains(f) && !kept_dirs.contains(f))
+            .collect();
+
+        let mut files_to_create = Vec::new();
+        let mut files_to_overwrite = Vec::new();
+        let mut files_unchanged = Vec::new();
+        let mut warnings = Vec::new();
+
+        for snapshot in file_snapshots
+            .iter()
+            .filter(|s| !s.is_deleted && in_scope(&s.file_path))
+        {
+            let full_path = self.project_path.join(&snapshot.file_path);
+
+            if snapshot.is_unrecoverable {
+                warnings.push(format!(
+                    "{} has unrecoverable snapshot content and would be left untouched",
+                    snapshot.file_path.display()
+                ));
+                files_unchanged.push(snapshot.file_path.clone());
+                continue;
+            }
+
+            match walk::read_entry(&full_path) {
+                Ok(entry) => {
+                    let same_mode = match (snapshot.permissions, entry.permissions) {
+                        (Some(mode), Some(current)) => mode & walk::PERMISSION_BITS == current,
+                        _ => true,
+                    };
+                    if entry.kind == snapshot.kind
+                        && same_mode
+                        && CheckpointStorage::calculate_file_hash(&entry.content) == snapshot.hash
+                    {
+                        files_unchanged.push(snapshot.file_path.clone());
+                    } else {
+                        files_to_overwrite.push(snapshot.file_path.clone());
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    files_to_create.push(snapshot.file_path.clone())
+                }
+                Err(e) => {
+                    warnings.push(format!(
+                        "Failed to read {}: {}",
+                        snapshot.file_path.display(),
+                        e
+                    ));
+                    files_to_overwrite.push(snapshot.file_path.clone());
+                }
+            }
+        }
+
+        files_to_delete.sort();
+        files_to_create.sort();
+        files_to_overwrite.sort();
+        files_unchanged.sort();
+
+        let current_message_count = self.current_messages.read().await.len();
+
+        // A selective restore leaves the conversation untouched
+        let (message_index, restored_message_count) = if filters.is_some() {
+            (
+                current_message_count.saturating_sub(1),
+                current_message_count,
+            )
+        } else {
+            (checkpoint.message_index, messages.lines().count())
+        };
+
+        RestorePlan {
+            checkpoint,
+            files_to_delete,
+            files_to_create,
+            files_to_overwrite,
+            files_unchanged,
+            message_index,
+            current_message_count,
+            restored_message_count,
+            warnings,
+        }
+    }
+
+    /// Restore only the given files or directories from a checkpoint
+    ///
+    /// Paths are relative to the project root (absolute paths inside the project are
+    /// accepted too). Files under a directory filter that aren't part of the checkpoint are
+    /// deleted. Unlike `restore_checkpoint`, the conversation and the timeline's current
+    /// checkpoint are left unchanged; restored files count as modifications for the next
+    /// checkpoint.
+    pub async fn restore_paths(
+        &self,
+        checkpoint_id: &str,
+        paths: Vec<PathBuf>,
+    ) -> Result<CheckpointResult> {
+        let filters = self.normalize_path_filters(paths)?;
+        if filters.is_empty() {
+            anyhow::bail!("No paths given for selective restore");
+        }
+
+        let (checkpoint, file_snapshots, messages) =
+            self.storage
+                .load_checkpoint_tree(&self.project_id, &self.session_id, checkpoint_id)?;
+        let project_files = self.walk_project().await;
+        let file_snapshots = self.without_excluded(file_snapshots);
+
+        let plan = self
+            .build_restore_plan(
+                checkpoint,
+                &file_snapshots,
+                &messages,
+                project_files,
+                Some(&filters),
+            )
+            .await;
+
+        let mut warnings = plan.warnings;
+        let mut files_processed = 0;
+
+        for rel_path in &plan.files_to_delete {
+            match walk::remove_entry(&self.project_path.join(rel_path)) {
+                Ok(_) => {
+                    files_processed += 1;
+                    log::info!("Deleted file not in checkpoint: {:?}", rel_path);
+                }
+                Err(e) => {
+                    warnings.push(format!("Failed to delete {}: {}", rel_path.display(), e));
+                }
+            }
+        }
+
+        let to_write: std::collections::HashSet<&PathBuf> = plan
+            .files_to_create
+            .iter()
+            .chain(plan.files_to_overwrite.iter())
+            .collect();
+
+        let mut restored = Vec::new();
+        for snapshot in file_snapshots
+            .iter()
+            .filter(|s| to_write.contains(&s.file_path))
+        {
+            match self.restore_file_snapshot(snapshot).await {
+                Ok(_) => {
+                    files_processed += 1;
+                    restored.push(snapshot);
+                }
+                Err(e) => warnings.push(format!(
+                    "Failed to restore {}: {}",
+                    snapshot.file_path.display(),
+                    e
+                )),
+            }
+        }
+
+        // Record the changes so the next checkpoint picks them up
+        let mut tracker = self.file_tracker.write().await;
+        for rel_path in &plan.files_to_delete {
+            if let Some(state) = tracker.tracked_files.get_mut(rel_path) {
+                state.exists = false;
+                state.is_modified = true;
+                state.last_modified = Utc::now();
+            }
+        }
+        for snapshot in restored {
+            tracker.tracked_files.insert(
+                snapshot.file_path.clone(),
+                FileState {
+                    last_hash: snapshot.hash.clone(),
+                    is_modified: true,
+                    last_modified: Utc::now(),
+                    exists: true,
+                    kind: snapshot.kind,
+                    permissions: snapshot.permissions.map(|m| m & walk::PERMISSION_BITS),
+                },
+            );
+        }
+
+        Ok(CheckpointResult {
+            checkpoint: plan.checkpoint,
+            files_processed,
+            warnings,
+        })
+    }
+
+    /// Turn user-supplied paths into clean paths relative to the project root
+    fn normalize_path_filters(&self, paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        paths
+            .iter()
+            .map(|path| normalize_path_filter(&self.project_path, path))
+            .collect()
+    }
+
     /// Restore a single file from snapshot
+    ///
+    /// Symbolic links are recreated rather than written through, and anything of a
+    /// different kind already at the path is replaced.
     async fn restore_file_snapshot(&self, snapshot: &FileSnapshot) -> Result<()> {
         let full_path = self.project_path.join(&snapshot.file_path);
+        let existing = fs::symlink_metadata(&full_path).ok();
 
         if snapshot.is_deleted {
             // Delete the file if it exists
-            if full_path.exists() {
-                fs::remove_file(&full_path).context("Failed to delete file")?;
+            if existing.is_some() {
+                walk::remove_entry(&full_path).context("Failed to delete file")?;
             }
-        } else {
-            // Create parent directories if needed
-            if let Some(parent) = full_path.parent() {
-                fs::create_dir_all(parent).context("Failed to create parent directories")?;
+            return Ok(());
+        }
+
+        // Create parent directories if needed
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create parent directories")?;
+        }
+
+        // Clear the way unless the entry can be updated in place
+        if let Some(existing) = existing {
+            let kind = walk::kind_of(&existing.file_type());
+            let in_place = kind == snapshot.kind
+                && match kind {
+                    FileKind::File => !existing.permissions().readonly(),
+                    FileKind::Directory => true,
+                    FileKind::Symlink => false,
+                };
+            if !in_place {
+                walk::remove_entry(&full_path).context("Failed to replace existing entry")?;
             }
+        }
 
-            // Write file content
-            fs::write(&full_path, &snapshot.content).context("Failed to write file")?;
+        match snapshot.kind {
+            FileKind::File => {
+                fs::write(&full_path, &snapshot.content).context("Failed to write file")?
+            }
+            FileKind::Symlink => walk::create_symlink(&snapshot.content, &full_path)
+                .context("Failed to create symbolic link")?,
+            FileKind::Directory => {
+                fs::create_dir_all(&full_path).context("Failed to create directory")?
+            }
+        }
 
-            // Restore permissions if available
-            #[cfg(unix)]
-            if let Some(mode) = snapshot.permissions {
+        // Restore permissions if available; links have none of their own
+        #[cfg(unix)]
+        if let Some(mode) = snapshot.permissions {
+            if snapshot.kind != FileKind::Symlink {
                 use std::os::unix::fs::PermissionsExt;
-                let permissions = std::fs::Permissions::from_mode(mode);
+                let permissions = std::fs::Permissions::from_mode(mode & walk::PERMISSION_BITS);
                 fs::set_permissions(&full_path, permissions)
                     .context("Failed to set file permissions")?;
             }
@@ -646,6 +1260,60 @@ impl CheckpointManager {
         checkpoints
     }
 
+    /// Diff a checkpoint against the current contents of the project directory
+    pub async fn diff_with_working_tree(
+        &self,
+        checkpoint_id: &str,
+        context_lines: usize,
+    ) -> Result<CheckpointDiff> {
+        let claude_dir = self.storage.claude_dir.clone();
+        let paths = CheckpointPaths::new(&claude_dir, &self.project_id, &self.session_id);
+        let checkpoint = self
+            .storage
+            .load_checkpoint_metadata(&paths, checkpoint_id)?;
+        let file_snapshots = self.storage.load_checkpoint_files(
+            &self.project_id,
+            &self.session_id,
+            checkpoint_id,
+        )?;
+
+        // Snapshot the working tree in memory so both sides are compared the same way
+        let max_file_size = self.timeline.read().await.max_file_size;
+        let working_files: Vec<FileSnapshot> =
+            collect_project_files(&self.project_path, max_file_size)
+                .files
+                .into_iter()
+                .filter_map(|rel| {
+                    let entry = walk::read_entry(&self.project_path.join(&rel)).ok()?;
+                    Some(FileSnapshot {
+                        checkpoint_id: diff::WORKING_TREE_ID.to_string(),
+                        hash: CheckpointStorage::calculate_file_hash(&entry.content),
+                        size: entry.content.len() as u64,
+                        file_path: rel,
+                        content: entry.content,
+                        is_deleted: false,
+                        permissions: entry.permissions,
+                        kind: entry.kind,
+                        is_unrecoverable: false,
+                    })
+                })
+                .collect();
+
+        let snapshot_diff = diff::diff_snapshots(&file_snapshots, &working_files, context_lines);
+
+        let messages = self.current_messages.read().await;
+        let (_, _, current_tokens) = self.extract_checkpoint_metadata(&messages).await?;
+
+        Ok(CheckpointDiff {
+            from_checkpoint_id: checkpoint.id,
+            to_checkpoint_id: diff::WORKING_TREE_ID.to_string(),
+            modified_files: snapshot_diff.modified_files,
+            added_files: snapshot_diff.added_files,
+            deleted_files: snapshot_diff.deleted_files,
+            token_delta: (current_tokens as i64) - (checkpoint.metadata.total_tokens as i64),
+        })
+    }
+
     /// Recursively collect checkpoints from timeline tree
     fn collect_checkpoints_from_node(
         node: &super::TimelineNode,
@@ -672,11 +1340,18 @@ impl CheckpointManager {
         self.restore_checkpoint(checkpoint_id).await?;
 
         // Create a new checkpoint with the fork
-        let fork_description =
-            description.unwrap_or_else(|| format!("Fork from checkpoint {}", &checkpoint_id[..8]));
-
-        self.create_checkpoint(Some(fork_description), Some(checkpoint_id.to_string()))
-            .await
+        let parent_checkpoint_id = Some(checkpoint_id.to_string());
+        match description {
+            Some(description) => {
+                self.create_checkpoint(Some(description), parent_checkpoint_id)
+                    .await
+            }
+            None => {
+                let description = format!("Fork from checkpoint {}", &checkpoint_id[..8]);
+                self.create_auto_checkpoint(description, parent_checkpoint_id)
+                    .await
+            }
+        }
     }
 
     /// Check if auto-checkpoint should be triggered
@@ -716,54 +1391,231 @@ impl CheckpointManager {
                 }
             }
             CheckpointStrategy::Smart => {
-                // Smart strategy: checkpoint after destructive operations
-                if let Ok(msg) = serde_json::from_str::<serde_json::Value>(message) {
-                    if let Some(content) = msg
-                        .get("message")
-                        .and_then(|m| m.get("content"))
-                        .and_then(|c| c.as_array())
-                    {
-                        content.iter().any(|item| {
-                            if item.get("type").and_then(|t| t.as_str()) == Some("tool_use") {
-                                let tool_name =
-                                    item.get("name").and_then(|n| n.as_str()).unwrap_or("");
-                                matches!(
-                                    tool_name.to_lowercase().as_str(),
-                                    "write" | "edit" | "multiedit" | "bash" | "rm" | "delete"
-                                )
-                            } else {
-                                false
-                            }
-                        })
-                    } else {
-                        false
+                // Smart strategy: checkpoint after operations matching the project's rules
+                let Ok(msg) = serde_json::from_str::<serde_json::Value>(message) else {
+                    return false;
+                };
+                let rules = self.smart_rules();
+
+                let changed_files = self
+                    .file_tracker
+                    .read()
+                    .await
+                    .tracked_files
+                    .values()
+                    .filter(|state| state.is_modified)
+                    .count();
+                if !rules.matches_message(&msg, changed_files) {
+                    return false;
+                }
+
+                // Hold off until the minimum interval since the last checkpoint has passed
+                let last_checkpoint = timeline
+                    .current_checkpoint_id
+                    .as_ref()
+                    .and_then(|id| timeline.find_checkpoint(id))
+                    .map(|node| node.checkpoint.timestamp);
+                match last_checkpoint {
+                    Some(timestamp) => {
+                        Utc::now().signed_duration_since(timestamp).num_seconds()
+                            >= rules.min_interval_secs as i64
                     }
-                } else {
-                    false
+                    None => true,
                 }
             }
         }
     }
 
+    /// The project's Smart rules, compiled again only when the rules file has changed
+    ///
+    /// Keyed on the file's modification time, so a save from any session or process is
+    /// picked up on the next check.
+    fn smart_rules(&self) -> Arc<CompiledSmartRules> {
+        let modified = fs::metadata(self.storage.smart_rules_file(&self.project_id))
+            .and_then(|m| m.modified())
+            .ok();
+        if let Ok(cache) = self.smart_rules.lock() {
+            if let Some((cached_modified, rules)) = cache.as_ref() {
+                if *cached_modified == modified {
+                    return rules.clone();
+                }
+            }
+        }
+
+        let rules = match self.storage.load_smart_rules(&self.project_id) {
+            Ok(rules) => rules,
+            Err(e) => {
+                log::warn!("Falling back to default Smart rules: {}", e);
+                SmartRules::default()
+            }
+        };
+        let compiled = Arc::new(rules.compile());
+        if let Ok(mut cache) = self.smart_rules.lock() {
+            *cache = Some((modified, compiled.clone()));
+        }
+        compiled
+    }
+
     /// Update checkpoint settings
     pub async fn update_settings(
         &self,
         auto_checkpoint_enabled: bool,
         checkpoint_strategy: CheckpointStrategy,
+        max_file_size: Option<u64>,
     ) -> Result<()> {
         let mut timeline = self.timeline.write().await;
         timeline.auto_checkpoint_enabled = auto_checkpoint_enabled;
         timeline.checkpoint_strategy = checkpoint_strategy;
+        if let Some(max_file_size) = max_file_size {
+            timeline.max_file_size = max_file_size;
+        }
+
+        // Save only these settings, so checkpoints written by another manager survive
+        let claude_dir = self.storage.claude_dir.clone();
+        let paths = CheckpointPaths::new(&claude_dir, &self.project_id, &self.session_id);
+        self.storage
+            .update_timeline(&paths.timeline_file, |saved| {
+                saved.auto_checkpoint_enabled = timeline.auto_checkpoint_enabled;
+                saved.checkpoint_strategy = timeline.checkpoint_strategy.clone();
+                saved.max_file_size = timeline.max_file_size;
+                Ok(())
+            })?;
+
+        Ok(())
+    }
+
+    /// Choose where new checkpoints store file contents
+    ///
+    /// Switching to the git backend requires the project to be inside a git repository.
+    /// Existing checkpoints keep working whichever backend made them.
+    pub async fn set_backend(&self, backend: CheckpointBackend) -> Result<()> {
+        if backend == CheckpointBackend::Git {
+            GitRepository::open(&self.project_path)?;
+        }
+
+        let mut timeline = self.timeline.write().await;
+        timeline.backend = backend;
+        if backend == CheckpointBackend::Git {
+            timeline.git_repository = Some(self.project_path.clone());
+        }
+
+        let claude_dir = self.storage.claude_dir.clone();
+        let paths = CheckpointPaths::new(&claude_dir, &self.project_id, &self.session_id);
+        self.storage
+            .update_timeline(&paths.timeline_file, |saved| {
+                saved.backend = timeline.backend;
+                saved.git_repository = timeline.git_repository.clone();
+                Ok(())
+            })?;
+
+        Ok(())
+    }
+
+    /// Apply the session's retention policy now, whether or not it runs automatically
+    pub async fn apply_retention(&self) -> Result<RetentionResult> {
+        let mut timeline = self.timeline.write().await;
+        let result = self
+            .storage
+            .apply_retention(&self.project_id, &self.session_id)?;
+
+        let claude_dir = self.storage.claude_dir.clone();
+        let paths = CheckpointPaths::new(&claude_dir, &self.project_id, &self.session_id);
+        *timeline = self.storage.load_timeline(&paths.timeline_file)?;
+        Ok(result)
+    }
+
+    /// Replace the session's retention policy
+    pub async fn update_retention_policy(&self, policy: RetentionPolicy) -> Result<()> {
+        let mut timeline = self.timeline.write().await;
+        timeline.retention = policy;
 
-        // Save updated timeline
         let claude_dir = self.storage.claude_dir.clone();
         let paths = CheckpointPaths::new(&claude_dir, &self.project_id, &self.session_id);
         self.storage
-            .save_timeline(&paths.timeline_file, &timeline)?;
+            .update_timeline(&paths.timeline_file, |saved| {
+                saved.retention = timeline.retention.clone();
+                Ok(())
+            })?;
+
+        Ok(())
+    }
+
+    /// Three-way merge the `theirs` checkpoint into `ours` as a new checkpoint
+    pub async fn merge_checkpoints(
+        &self,
+        ours_id: &str,
+        theirs_id: &str,
+        description: Option<String>,
+        allow_conflicts: bool,
+    ) -> Result<MergeResult> {
+        // Hold the timeline lock so no checkpoint is written mid-merge
+        let mut timeline = self.timeline.write().await;
+        let result = self.storage.merge_checkpoints(
+            &self.project_id,
+            &self.session_id,
+            ours_id,
+            theirs_id,
+            description,
+            allow_conflicts,
+        )?;
+
+        if result.checkpoint.is_some() {
+            let claude_dir = self.storage.claude_dir.clone();
+            let paths = CheckpointPaths::new(&claude_dir, &self.project_id, &self.session_id);
+            *timeline = self.storage.load_timeline(&paths.timeline_file)?;
+        }
+
+        Ok(result)
+    }
+
+    /// Rename a checkpoint and/or replace its tags
+    pub async fn update_checkpoint_labels(
+        &self,
+        checkpoint_id: &str,
+        description: Option<String>,
+        tags: Option<Vec<String>>,
+    ) -> Result<Checkpoint> {
+        let mut timeline = self.timeline.write().await;
+        let checkpoint = self.storage.update_checkpoint_labels(
+            &self.project_id,
+            &self.session_id,
+            checkpoint_id,
+            description,
+            tags,
+        )?;
+
+        let claude_dir = self.storage.claude_dir.clone();
+        let paths = CheckpointPaths::new(&claude_dir, &self.project_id, &self.session_id);
+        *timeline = self.storage.load_timeline(&paths.timeline_file)?;
+        Ok(checkpoint)
+    }
 
+    /// Reload the timeline after storage was changed behind the manager's back
+    pub async fn reload_timeline(&self) -> Result<()> {
+        let claude_dir = self.storage.claude_dir.clone();
+        let paths = CheckpointPaths::new(&claude_dir, &self.project_id, &self.session_id);
+        let timeline = self.storage.load_timeline(&paths.timeline_file)?;
+        *self.timeline.write().await = timeline;
         Ok(())
     }
 
+    /// Verify this session's checkpoint storage, optionally repairing what it can
+    pub async fn verify_storage(&self, repair: bool) -> Result<IntegrityReport> {
+        // Hold the timeline lock so no checkpoint is written mid-check
+        let mut timeline = self.timeline.write().await;
+        let report = self
+            .storage
+            .verify_integrity(&self.project_id, &self.session_id, repair)?;
+
+        if report.repaired {
+            let claude_dir = self.storage.claude_dir.clone();
+            let paths = CheckpointPaths::new(&claude_dir, &self.project_id, &self.session_id);
+            *timeline = self.storage.load_timeline(&paths.timeline_file)?;
+        }
+
+        Ok(report)
+    }
+
     /// Get files modified since a given timestamp
     pub async fn get_files_modified_since(&self, since: DateTime<Utc>) -> Vec<PathBuf> {
         let tracker = self.file_tracker.read().await;
@@ -785,3 +1637,313 @@ impl CheckpointManager {
             .max()
     }
 }
+
+/// Directories a checkpoint needs: its empty directories and the parents of every entry
+fn checkpoint_dirs(file_snapshots: &[FileSnapshot]) -> std::collections::HashSet<PathBuf> {
+    let mut dirs = std::collections::HashSet::new();
+    for snapshot in file_snapshots.iter().filter(|s| !s.is_deleted) {
+        // An empty directory needs itself as well as its parents
+        let skip = if snapshot.kind == FileKind::Directory {
+            0
+        } else {
+            1
+        };
+        for dir in snapshot.file_path.ancestors().skip(skip) {
+            if dir.as_os_str().is_empty() || !dirs.insert(dir.to_path_buf()) {
+                break;
+            }
+        }
+    }
+    dirs
+}
+
+/// Turn a user-supplied path into a clean path relative to the project root
+///
+/// Absolute paths must lie inside the project. Paths that climb out of it with `..`, or
+/// that name the project root itself, are rejected rather than reinterpreted.
+fn normalize_path_filter(project_path: &Path, path: &Path) -> Result<PathBuf> {
+    let rel_path = if path.has_root() {
+        path.strip_prefix(project_path)
+            .map_err(|_| anyhow::anyhow!("{} is outside the project", path.display()))?
+    } else {
+        path
+    };
+
+    let mut clean = PathBuf::new();
+    for component in rel_path.components() {
+        match component {
+            std::path::Component::Normal(part) => clean.push(part),
+            std::path::Component::CurDir => {}
+            _ => anyhow::bail!(
+                "{} must be a path inside the project without '..'",
+                path.display()
+            ),
+        }
+    }
+    if clean.as_os_str().is_empty() {
+        anyhow::bail!(
+            "{} doesn't name a file or directory in the project",
+            path.display()
+        );
+    }
+    Ok(clean)
+}
+
+/// Whether a relative path is one of the filters or lies below one of them
+fn matches_path_filters(path: &Path, filters: &[PathBuf]) -> bool {
+    filters.iter().any(|filter| path.starts_with(filter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    /// A manager for a session of an empty project inside `temp_dir`
+    async fn test_manager(temp_dir: &TempDir) -> CheckpointManager {
+        let project_path = temp_dir.path().join("project");
+        fs::create_dir_all(&project_path).unwrap();
+        CheckpointManager::new(
+            "test-project".to_string(),
+            "test-session".to_string(),
+            project_path,
+            temp_dir.path().join("claude"),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn test_path_filters_stay_inside_project() {
+        let project = Path::new("/work/project");
+
+        assert_eq!(
+            normalize_path_filter(project, Path::new("src/./main.rs")).unwrap(),
+            PathBuf::from("src/main.rs")
+        );
+        assert_eq!(
+            normalize_path_filter(project, Path::new("/work/project/src")).unwrap(),
+            PathBuf::from("src")
+        );
+
+        for rejected in [
+            "../other/file",
+            "src/../../other",
+            "/etc/passwd",
+            "/work/project-other/file",
+            "/work/project",
+            ".",
+            "",
+        ] {
+            assert!(
+                normalize_path_filter(project, Path::new(rejected)).is_err(),
+                "{} should be rejected",
+                rejected
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_after_pruning_incremental_checkpoints() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = test_manager(&temp_dir).await;
+        let project_path = manager.project_path.clone();
+
+        fs::write(project_path.join("a.txt"), "a1").unwrap();
+        fs::write(project_path.join("b.txt"), "b1").unwrap();
+        fs::write(project_path.join("c.txt"), "c1").unwrap();
+        let first = manager.create_checkpoint(None, None).await.unwrap();
+
+        fs::write(project_path.join("a.txt"), "a2").unwrap();
+        fs::remove_file(project_path.join("c.txt")).unwrap();
+        manager.track_file_modification("c.txt").await.unwrap();
+        let second = manager.create_checkpoint(None, None).await.unwrap();
+
+        // The newest checkpoint records only b.txt itself
+        fs::write(project_path.join("b.txt"), "b3").unwrap();
+        let third = manager.create_checkpoint(None, None).await.unwrap();
+        assert_eq!(third.checkpoint.metadata.file_changes, 1);
+
+        let paths =
+            CheckpointPaths::new(&manager.storage.claude_dir, "test-project", "test-session");
+        for doomed in [&second, &first] {
+            let doomed = HashSet::from([doomed.checkpoint.id.clone()]);
+            manager
+                .storage
+                .update_timeline(&paths.timeline_file, |timeline| {
+                    manager.storage.prune_checkpoints(&paths, timeline, &doomed)
+                })
+                .unwrap();
+
+            fs::write(project_path.join("a.txt"), "edited").unwrap();
+            fs::write(project_path.join("c.txt"), "recreated").unwrap();
+            manager
+                .restore_checkpoint(&third.checkpoint.id)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                fs::read_to_string(project_path.join("a.txt")).unwrap(),
+                "a2"
+            );
+            assert_eq!(
+                fs::read_to_string(project_path.join("b.txt")).unwrap(),
+                "b3"
+            );
+            assert!(!project_path.join("c.txt").exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_label_updates_and_retention_keep_each_other() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = test_manager(&temp_dir).await;
+
+        // Undescribed checkpoints are for retention to remove, described ones get relabeled
+        let mut victims = Vec::new();
+        let mut targets = Vec::new();
+        for i in 0..4 {
+            victims.push(manager.create_checkpoint(None, None).await.unwrap());
+            let description = format!("target {} rev 0", i);
+            targets.push(
+                manager
+                    .create_checkpoint(Some(description), None)
+                    .await
+                    .unwrap(),
+            );
+        }
+        manager
+            .update_retention_policy(RetentionPolicy {
+                enabled: false,
+                keep_recent_hours: 0,
+                keep_daily: false,
+                keep_described: true,
+                max_project_bytes: None,
+            })
+            .await
+            .unwrap();
+
+        let storage = manager.storage.clone();
+        std::thread::scope(|scope| {
+            for (i, target) in targets.iter().enumerate() {
+                let storage = &storage;
+                scope.spawn(move || {
+                    for rev in 1..=20 {
+                        storage
+                            .update_checkpoint_labels(
+                                "test-project",
+                                "test-session",
+                                &target.checkpoint.id,
+                                Some(format!("target {} rev {}", i, rev)),
+                                None,
+                            )
+                            .unwrap();
+                    }
+                });
+            }
+            scope.spawn(|| {
+                let result = storage
+                    .apply_retention("test-project", "test-session")
+                    .unwrap();
+                assert_eq!(result.removed_checkpoints.len(), victims.len());
+            });
+        });
+
+        let paths = CheckpointPaths::new(&storage.claude_dir, "test-project", "test-session");
+        let timeline = storage.load_timeline(&paths.timeline_file).unwrap();
+        for victim in &victims {
+            assert!(timeline.find_checkpoint(&victim.checkpoint.id).is_none());
+        }
+        for (i, target) in targets.iter().enumerate() {
+            let node = timeline.find_checkpoint(&target.checkpoint.id).unwrap();
+            assert_eq!(
+                node.checkpoint.description.as_deref(),
+                Some(format!("target {} rev 20", i).as_str())
+            );
+        }
+        assert_eq!(timeline.total_checkpoints, targets.len());
+    }
+
+    #[tokio::test]
+    async fn test_smart_rules_reload_after_save() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = test_manager(&temp_dir).await;
+        manager
+            .update_settings(true, CheckpointStrategy::Smart, None)
+            .await
+            .unwrap();
+
+        let bash = serde_json::json!({
+            "message": {"content": [
+                {"type": "tool_use", "name": "Bash", "input": {"command": "rm notes.txt"}}
+            ]}
+        })
+        .to_string();
+        assert!(manager.should_auto_checkpoint(&bash).await);
+
+        let rules: SmartRules =
+            serde_json::from_value(serde_json::json!({"rules": [{"tool": "write"}]})).unwrap();
+        manager
+            .storage
+            .save_smart_rules("test-project", &rules)
+            .unwrap();
+        assert!(!manager.should_auto_checkpoint(&bash).await);
+    }
+
+    #[tokio::test]
+    async fn test_restore_after_session_deletes_a_file_sees_no_external_edits() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = test_manager(&temp_dir).await;
+        let project_path = manager.project_path.clone();
+
+        fs::write(project_path.join("a.txt"), "a").unwrap();
+        fs::write(project_path.join("b.txt"), "b").unwrap();
+        let before = manager.create_checkpoint(None, None).await.unwrap();
+
+        // Without a watcher, the Bash command marks every tracked file as modified
+        fs::remove_file(project_path.join("b.txt")).unwrap();
+        let rm = serde_json::json!({
+            "message": {"content": [
+                {"type": "tool_use", "name": "Bash", "input": {"command": "rm b.txt"}}
+            ]}
+        });
+        manager.track_message(rm.to_string()).await.unwrap();
+        manager.create_checkpoint(None, None).await.unwrap();
+
+        assert!(manager.detect_external_changes().await.is_empty());
+        manager
+            .restore_checkpoint_with_resolution(&before.checkpoint.id, ConflictResolution::Abort)
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(project_path.join("b.txt")).unwrap(), "b");
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_records_deletions_nothing_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = test_manager(&temp_dir).await;
+        let project_path = manager.project_path.clone();
+
+        fs::write(project_path.join("a.txt"), "a").unwrap();
+        fs::write(project_path.join("b.txt"), "b").unwrap();
+        let before = manager.create_checkpoint(None, None).await.unwrap();
+
+        // No tool use or watcher event tells the tracker about this
+        fs::remove_file(project_path.join("b.txt")).unwrap();
+        let after = manager.create_checkpoint(None, None).await.unwrap();
+        assert!(manager.detect_external_changes().await.is_empty());
+
+        manager
+            .restore_checkpoint_with_resolution(&before.checkpoint.id, ConflictResolution::Abort)
+            .await
+            .unwrap();
+        assert!(project_path.join("b.txt").exists());
+        manager
+            .restore_checkpoint_with_resolution(&after.checkpoint.id, ConflictResolution::Abort)
+            .await
+            .unwrap();
+        assert!(!project_path.join("b.txt").exists());
+        assert_eq!(fs::read_to_string(project_path.join("a.txt")).unwrap(), "a");
+    }
+}
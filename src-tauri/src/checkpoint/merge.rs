@@ -0,0 +1,547 @@
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use super::{
+    git, storage::CheckpointStorage, walk, Checkpoint, CheckpointMetadata, CheckpointPaths,
+    FileKind, FileSnapshot, SessionTimeline,
+};
+
+/// Why a file couldn't be merged cleanly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeConflictKind {
+    /// Both branches changed the same lines; the merged file holds conflict markers
+    Content,
+    /// One branch changed the file and the other deleted it; the changed version is kept
+    ModifyDelete,
+    /// Both branches changed a binary file, link or entry kind; the first branch wins
+    Unmergeable,
+}
+
+/// A file the merge couldn't resolve on its own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeConflict {
+    /// Path relative to the project root
+    pub path: PathBuf,
+    /// How the merge resolved it
+    pub kind: MergeConflictKind,
+}
+
+/// Outcome of merging two checkpoints
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeResult {
+    /// The checkpoint recording the merge, unless conflicts kept it from being created
+    pub checkpoint: Option<Checkpoint>,
+    /// Common ancestor the merge was computed against
+    pub base_checkpoint_id: Option<String>,
+    /// Files that differ from the first branch after merging, conflicted ones included
+    pub merged_files: Vec<PathBuf>,
+    /// Files that needed a decision the merge made on its own
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl CheckpointStorage {
+    /// Three-way merge the `theirs` checkpoint into `ours`
+    ///
+    /// The result is a new child of `ours` whose second parent is `theirs`, recording every
+    /// file of the merged tree. With conflicts and without `allow_conflicts`, only the report
+    /// is returned. The timeline's current checkpoint is left where it was; restore the
+    /// merge checkpoint to bring its files into the project.
+    pub fn merge_checkpoints(
+        &self,
+        project_id: &str,
+        session_id: &str,
+        ours_id: &str,
+        theirs_id: &str,
+        description: Option<String>,
+        allow_conflicts: bool,
+    ) -> Result<MergeResult> {
+        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
+        let timeline = self.load_timeline(&paths.timeline_file)?;
+
+        let mut checkpoints = Vec::new();
+        if let Some(root) = &timeline.root_node {
+            Self::collect_checkpoints(root, &mut checkpoints);
+        }
+        let by_id: HashMap<&str, &Checkpoint> =
+            checkpoints.iter().map(|c| (c.id.as_str(), c)).collect();
+        let ours_checkpoint = *by_id
+            .get(ours_id)
+            .ok_or_else(|| anyhow::anyhow!("Checkpoint not found: {}", ours_id))?;
+        let theirs_checkpoint = *by_id
+            .get(theirs_id)
+            .ok_or_else(|| anyhow::anyhow!("Checkpoint not found: {}", theirs_id))?;
+
+        let base_id = merge_base(&by_id, ours_id, theirs_id);
+        let base_files = match &base_id {
+            Some(id) => self.load_file_state(&paths, &timeline, id)?,
+            None => BTreeMap::new(),
+        };
+        let ours_files = self.load_file_state(&paths, &timeline, ours_id)?;
+        let theirs_files = self.load_file_state(&paths, &timeline, theirs_id)?;
+
+        let checkpoint_id = Self::generate_checkpoint_id();
+        let ours_label = format!("checkpoint {}", short_id(ours_id));
+        let theirs_label = format!("checkpoint {}", short_id(theirs_id));
+
+        let all_paths: BTreeSet<&PathBuf> = base_files
+            .keys()
+            .chain(ours_files.keys())
+            .chain(theirs_files.keys())
+            .collect();
+
+        let mut merged: Vec<FileSnapshot> = Vec::new();
+        let mut conflicts = Vec::new();
+        for path in all_paths {
+            let base = base_files.get(path);
+            let ours = ours_files.get(path);
+            let theirs = theirs_files.get(path);
+
+            let resolved = if same_version(ours, theirs) || same_version(base, theirs) {
+                ours.cloned()
+            } else if same_version(base, ours) {
+                theirs.cloned()
+            } else {
+                match (ours, theirs) {
+                    (Some(ours), Some(theirs)) => {
+                        match merge_file(base, ours, theirs, &ours_label, &theirs_label) {
+                            Some((snapshot, clean)) => {
+                                if !clean {
+                                    conflicts.push(MergeConflict {
+                                        path: path.clone(),
+                                        kind: MergeConflictKind::Content,
+                                    });
+                                }
+                                Some(snapshot)
+                            }
+                            None => {
+                                conflicts.push(MergeConflict {
+                                    path: path.clone(),
+                                    kind: MergeConflictKind::Unmergeable,
+                                });
+                                Some(ours.clone())
+                            }
+                        }
+                    }
+                    // One side deleted what the other changed; keep the change
+                    (ours, theirs) => {
+                        conflicts.push(MergeConflict {
+                            path: path.clone(),
+                            kind: MergeConflictKind::ModifyDelete,
+                        });
+                        ours.or(theirs).cloned()
+                    }
+                }
+            };
+
+            if let Some(mut snapshot) = resolved {
+                snapshot.checkpoint_id = checkpoint_id.clone();
+                merged.push(snapshot);
+            }
+        }
+
+        let merged_paths: HashSet<&PathBuf> = merged.iter().map(|s| &s.file_path).collect();
+        let mut merged_files: Vec<PathBuf> = merged
+            .iter()
+            .filter(|s| !same_version(ours_files.get(&s.file_path), Some(s)))
+            .map(|s| s.file_path.clone())
+            .chain(
+                ours_files
+                    .keys()
+                    .filter(|path| !merged_paths.contains(path))
+                    .cloned(),
+            )
+            .collect();
+        merged_files.sort();
+
+        let mut result = MergeResult {
+            checkpoint: None,
+            base_checkpoint_id: base_id,
+            merged_files,
+            conflicts,
+        };
+        if !result.conflicts.is_empty() && !allow_conflicts {
+            return Ok(result);
+        }
+
+        // Record the whole merged tree, plus deletions of files only the first branch had
+        let deletions: Vec<FileSnapshot> = ours_files
+            .keys()
+            .filter(|path| !merged_paths.contains(path))
+            .map(|path| FileSnapshot {
+                checkpoint_id: checkpoint_id.clone(),
+                file_path: path.clone(),
+                content: Vec::new(),
+                hash: String::new(),
+                is_deleted: true,
+                permissions: None,
+                size: 0,
+                kind: FileKind::File,
+                is_unrecoverable: false,
+            })
+            .collect();
+        merged.extend(deletions);
+
+        let auto_generated = description.is_none();
+        let checkpoint = Checkpoint {
+            id: checkpoint_id,
+            session_id: session_id.to_string(),
+            project_id: project_id.to_string(),
+            message_index: ours_checkpoint.message_index,
+            timestamp: Utc::now(),
+            description: Some(
+                description
+                    .unwrap_or_else(|| format!("Merge {} into {}", theirs_label, ours_label)),
+            ),
+            parent_checkpoint_id: Some(ours_checkpoint.id.clone()),
+            metadata: CheckpointMetadata {
+                total_tokens: ours_checkpoint.metadata.total_tokens,
+                model_used: ours_checkpoint.metadata.model_used.clone(),
+                user_prompt: ours_checkpoint.metadata.user_prompt.clone(),
+                file_changes: result.merged_files.len(),
+                snapshot_size: 0,
+            },
+            git_commit: None,
+            merge_parent_id: Some(theirs_checkpoint.id.clone()),
+            tags: Vec::new(),
+            auto_generated,
+        };
+
+        // The conversation continues from the first branch
+        let (_, _, messages) = self.load_checkpoint(project_id, session_id, ours_id)?;
+        let saved = self.save_checkpoint(project_id, session_id, &checkpoint, merged, &messages)?;
+
+        // Saving moved the current checkpoint to the merge; put it back
+        self.update_timeline(&paths.timeline_file, |updated| {
+            updated.current_checkpoint_id = timeline.current_checkpoint_id.clone();
+            Ok(())
+        })?;
+
+        result.checkpoint = Some(saved.checkpoint);
+        Ok(result)
+    }
+
+    /// Every file in the project as of a checkpoint, keyed by path
+    ///
+    /// Native checkpoints record only what changed, so snapshots are layered from the
+    /// nearest checkpoint holding a full tree (a git-backed one, or the root) downwards.
+    pub(super) fn load_file_state(
+        &self,
+        paths: &CheckpointPaths,
+        timeline: &SessionTimeline,
+        checkpoint_id: &str,
+    ) -> Result<BTreeMap<PathBuf, FileSnapshot>> {
+        let mut chain = Vec::new();
+        let mut next = Some(checkpoint_id.to_string());
+        while let Some(id) = next {
+            let node = timeline
+                .find_checkpoint(&id)
+                .ok_or_else(|| anyhow::anyhow!("Checkpoint not found: {}", id))?;
+            let is_full_tree = node.checkpoint.git_commit.is_some();
+            next = node.checkpoint.parent_checkpoint_id.clone();
+            chain.push(node.checkpoint.clone());
+            if is_full_tree {
+                break;
+            }
+        }
+
+        let mut files = BTreeMap::new();
+        for checkpoint in chain.iter().rev() {
+            let snapshots = match &checkpoint.git_commit {
+                Some(commit) => {
+                    files.clear();
+                    git::timeline_repository(timeline)?.load_snapshots(commit, &checkpoint.id)?
+                }
+                None => self.load_file_snapshots(paths, &checkpoint.id)?,
+            };
+            for snapshot in snapshots {
+                if snapshot.is_deleted {
+                    files.remove(&snapshot.file_path);
+                } else {
+                    files.insert(snapshot.file_path.clone(), snapshot);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+/// Newest checkpoint that both checkpoints descend from, following merge parents too
+fn merge_base(by_id: &HashMap<&str, &Checkpoint>, ours: &str, theirs: &str) -> Option<String> {
+    let ours_ancestors = ancestors(by_id, ours);
+    ancestors(by_id, theirs)
+        .into_iter()
+        .filter(|id| ours_ancestors.contains(id))
+        .filter_map(|id| by_id.get(id.as_str()))
+        .max_by_key(|c| c.timestamp)
+        .map(|c| c.id.clone())
+}
+
+/// A checkpoint and everything it descends from
+fn ancestors(by_id: &HashMap<&str, &Checkpoint>, id: &str) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    let mut pending = VecDeque::from([id.to_string()]);
+    while let Some(id) = pending.pop_front() {
+        let Some(checkpoint) = by_id.get(id.as_str()) else {
+            continue;
+        };
+        if !seen.insert(id) {
+            continue;
+        }
+        pending.extend(checkpoint.parent_checkpoint_id.iter().cloned());
+        pending.extend(checkpoint.merge_parent_id.iter().cloned());
+    }
+    seen
+}
+
+/// Whether two versions of a path are the same, absence included
+fn same_version(a: Option<&FileSnapshot>, b: Option<&FileSnapshot>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => {
+            let mode = |s: &FileSnapshot| s.permissions.map(|m| m & walk::PERMISSION_BITS);
+            a.kind == b.kind && a.hash == b.hash && mode(a) == mode(b)
+        }
+        _ => false,
+    }
+}
+
+/// Merge two changed versions of a text file, or `None` if it can't be merged by line
+///
+/// Returns the merged snapshot and whether it merged without conflicts.
+fn merge_file(
+    base: Option<&FileSnapshot>,
+    ours: &FileSnapshot,
+    theirs: &FileSnapshot,
+    ours_label: &str,
+    theirs_label: &str,
+) -> Option<(FileSnapshot, bool)> {
+    let is_text_file = |s: &FileSnapshot| s.kind == FileKind::File && !s.is_unrecoverable;
+    if !is_text_file(ours) || !is_text_file(theirs) || !base.is_none_or(is_text_file) {
+        return None;
+    }
+    let base_text = match base {
+        Some(base) => std::str::from_utf8(&base.content).ok()?,
+        None => "",
+    };
+    let ours_text = std::str::from_utf8(&ours.content).ok()?;
+    let theirs_text = std::str::from_utf8(&theirs.content).ok()?;
+
+    let (content, clean) = merge_text(base_text, ours_text, theirs_text, ours_label, theirs_label);
+
+    // Keep a mode change made on either side
+    let base_mode = base.and_then(|b| b.permissions);
+    let permissions = if ours.permissions == base_mode {
+        theirs.permissions
+    } else {
+        ours.permissions
+    };
+
+    let content = content.into_bytes();
+    Some((
+        FileSnapshot {
+            checkpoint_id: ours.checkpoint_id.clone(),
+            file_path: ours.file_path.clone(),
+            hash: CheckpointStorage::calculate_file_hash(&content),
+            size: content.len() as u64,
+            content,
+            is_deleted: false,
+            permissions,
+            kind: FileKind::File,
+            is_unrecoverable: false,
+        },
+        clean,
+    ))
+}
+
+/// Three-way merge of text by line, marking overlapping changes like git does
+///
+/// Returns the merged text and whether it merged without conflicts.
+pub fn merge_text(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    ours_label: &str,
+    theirs_label: &str,
+) -> (String, bool) {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+    let ours_hunks = changed_hunks(&base, &ours);
+    let theirs_hunks = changed_hunks(&base, &theirs);
+
+    let mut merged = String::new();
+    let mut clean = true;
+    let mut base_pos = 0;
+    let (mut i, mut j) = (0, 0);
+
+    while i < ours_hunks.len() || j < theirs_hunks.len() {
+        // Start a region at the earliest remaining change and grow it while changes touch
+        let (oi, tj) = (i, j);
+        let starts_with_ours = j == theirs_hunks.len()
+            || (i < ours_hunks.len() && ours_hunks[i].0.start <= theirs_hunks[j].0.start);
+        let (start, mut end) = if starts_with_ours {
+            i += 1;
+            (ours_hunks[oi].0.start, ours_hunks[oi].0.end)
+        } else {
+            j += 1;
+            (theirs_hunks[tj].0.start, theirs_hunks[tj].0.end)
+        };
+        loop {
+            if i < ours_hunks.len() && ours_hunks[i].0.start <= end {
+                end = end.max(ours_hunks[i].0.end);
+                i += 1;
+            } else if j < theirs_hunks.len() && theirs_hunks[j].0.start <= end {
+                end = end.max(theirs_hunks[j].0.end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        merged.extend(base[base_pos..start].iter().copied());
+        base_pos = end;
+
+        let ours_region = apply_hunks(&base, &ours, &ours_hunks[oi..i], start..end);
+        let theirs_region = apply_hunks(&base, &theirs, &theirs_hunks[tj..j], start..end);
+        if oi == i || ours_region == theirs_region {
+            merged.extend(theirs_region);
+        } else if tj == j {
+            merged.extend(ours_region);
+        } else {
+            clean = false;
+            push_conflict_side(
+                &mut merged,
+                &format!("<<<<<<< {}\n", ours_label),
+                &ours_region,
+            );
+            push_conflict_side(&mut merged, "=======\n", &theirs_region);
+            merged.push_str(&format!(">>>>>>> {}\n", theirs_label));
+        }
+    }
+    merged.extend(base[base_pos..].iter().copied());
+
+    (merged, clean)
+}
+
+/// Ranges of `base` replaced in `new`, paired with their replacements
+fn changed_hunks(base: &[&str], new: &[&str]) -> Vec<(Range<usize>, Range<usize>)> {
+    capture_diff_slices(Algorithm::Myers, base, new)
+        .into_iter()
+        .map(|op| op.as_tag_tuple())
+        .filter(|(tag, _, _)| *tag != DiffTag::Equal)
+        .map(|(_, old, new)| (old, new))
+        .collect()
+}
+
+/// One side's version of `region` of the base, given that side's hunks inside it
+fn apply_hunks<'a>(
+    base: &[&'a str],
+    new: &[&'a str],
+    hunks: &[(Range<usize>, Range<usize>)],
+    region: Range<usize>,
+) -> Vec<&'a str> {
+    let mut lines = Vec::new();
+    let mut pos = region.start;
+    for (old, replacement) in hunks {
+        lines.extend(&base[pos..old.start]);
+        lines.extend(&new[replacement.clone()]);
+        pos = old.end;
+    }
+    lines.extend(&base[pos..region.end]);
+    lines
+}
+
+/// Append a marker line and one side of a conflict, ending it with a newline
+fn push_conflict_side(merged: &mut String, marker: &str, lines: &[&str]) {
+    merged.push_str(marker);
+    for line in lines {
+        merged.push_str(line);
+    }
+    if !merged.ends_with('\n') {
+        merged.push('\n');
+    }
+}
+
+/// Leading part of a checkpoint ID, for labels
+fn short_id(id: &str) -> &str {
+    id.get(..8).unwrap_or(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, ours: &str, theirs: &str) -> (String, bool) {
+        merge_text(base, ours, theirs, "ours", "theirs")
+    }
+
+    #[test]
+    fn test_merge_text_combines_separate_changes() {
+        let base = "a\nb\nc\nd\ne\n";
+        assert_eq!(
+            merge(base, "a\nB\nc\nd\ne\n", "a\nb\nc\nD\ne\n"),
+            ("a\nB\nc\nD\ne\n".to_string(), true)
+        );
+        assert_eq!(
+            merge("a\nb\nc\n", "b\nc\n", "a\nb\nc\nd\n"),
+            ("b\nc\nd\n".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_merge_text_takes_one_sided_and_identical_changes() {
+        let base = "a\nb\nc\n";
+        assert_eq!(
+            merge(base, base, "a\nB\nc\n"),
+            ("a\nB\nc\n".to_string(), true)
+        );
+        assert_eq!(
+            merge(base, "a\nB\nc\n", base),
+            ("a\nB\nc\n".to_string(), true)
+        );
+        assert_eq!(
+            merge(base, "a\nB\nc\nd\n", "a\nB\nc\nd\n"),
+            ("a\nB\nc\nd\n".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_merge_text_marks_overlapping_changes() {
+        assert_eq!(
+            merge("a\nb\nc\n", "a\nX\nc\n", "a\nY\nc\n"),
+            (
+                "a\n<<<<<<< ours\nX\n=======\nY\n>>>>>>> theirs\nc\n".to_string(),
+                false
+            )
+        );
+
+        // Changes to neighbouring lines conflict, as they do in git
+        let (merged, clean) = merge("a\nb\nc\nd\n", "a\nB\nc\nd\n", "a\nb\nC\nd\n");
+        assert!(!clean);
+        assert_eq!(
+            merged,
+            "a\n<<<<<<< ours\nB\nc\n=======\nb\nC\n>>>>>>> theirs\nd\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_text_ends_conflict_sides_with_newlines() {
+        assert_eq!(
+            merge("a\nb", "a\nX", "a\nY"),
+            (
+                "a\n<<<<<<< ours\nX\n=======\nY\n>>>>>>> theirs\n".to_string(),
+                false
+            )
+        );
+    }
+}
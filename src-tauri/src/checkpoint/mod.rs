@@ -3,9 +3,24 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+pub mod blob;
+pub mod bundle;
+pub mod crypto;
+pub mod diff;
+pub mod git;
+pub mod integrity;
+pub mod journal;
+pub mod labels;
 pub mod manager;
+pub mod merge;
+pub mod pool;
+pub mod retention;
+pub mod rules;
+pub mod search;
 pub mod state;
 pub mod storage;
+pub mod walk;
+pub mod watcher;
 
 /// Represents a checkpoint in the session timeline
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +42,18 @@ pub struct Checkpoint {
     pub parent_checkpoint_id: Option<String>,
     /// Metadata about the checkpoint
     pub metadata: CheckpointMetadata,
+    /// Commit holding the file contents, for checkpoints made with the git backend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_commit: Option<String>,
+    /// Second parent of a checkpoint that merged two branches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_parent_id: Option<String>,
+    /// User-assigned labels such as "tests-green"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Made without the user asking, so any description it has was generated
+    #[serde(default)]
+    pub auto_generated: bool,
 }
 
 /// Metadata associated with a checkpoint
@@ -53,8 +80,8 @@ pub struct FileSnapshot {
     pub checkpoint_id: String,
     /// Relative path from project root
     pub file_path: PathBuf,
-    /// Full content of the file (will be compressed)
-    pub content: String,
+    /// Raw bytes of the file (will be compressed)
+    pub content: Vec<u8>,
     /// SHA-256 hash for integrity verification
     pub hash: String,
     /// Whether this file was deleted at this checkpoint
@@ -63,6 +90,25 @@ pub struct FileSnapshot {
     pub permissions: Option<u32>,
     /// File size in bytes
     pub size: u64,
+    /// Whether this is a file, symbolic link or empty directory
+    #[serde(default)]
+    pub kind: FileKind,
+    /// Content could not be recovered (missing blob or legacy non-UTF-8 snapshot)
+    #[serde(default)]
+    pub is_unrecoverable: bool,
+}
+
+/// What kind of entry a snapshot records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    /// A regular file; the snapshot content is the file's bytes
+    #[default]
+    File,
+    /// A symbolic link; the snapshot content is the link target
+    Symlink,
+    /// A directory with nothing else in it to snapshot
+    Directory,
 }
 
 /// Represents a node in the timeline tree
@@ -93,6 +139,21 @@ pub struct SessionTimeline {
     pub checkpoint_strategy: CheckpointStrategy,
     /// Total number of checkpoints in timeline
     pub total_checkpoints: usize,
+    /// On-disk storage format version of this timeline
+    #[serde(default = "storage::legacy_format_version")]
+    pub format_version: u32,
+    /// Files larger than this many bytes are left out of checkpoints
+    #[serde(default = "walk::default_max_file_size")]
+    pub max_file_size: u64,
+    /// Which checkpoints are kept when old ones are cleaned up
+    #[serde(default)]
+    pub retention: retention::RetentionPolicy,
+    /// Where new checkpoints store file contents
+    #[serde(default)]
+    pub backend: CheckpointBackend,
+    /// Project directory whose git repository holds git-backed checkpoints
+    #[serde(default)]
+    pub git_repository: Option<PathBuf>,
 }
 
 /// Strategy for automatic checkpoint creation
@@ -105,15 +166,28 @@ pub enum CheckpointStrategy {
     PerPrompt,
     /// Create checkpoint after each tool use
     PerToolUse,
-    /// Create checkpoint after destructive operations
+    /// Create checkpoint after tool uses matching the project's Smart rules
     Smart,
 }
 
+/// Where checkpoint file contents are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckpointBackend {
+    /// The session's own content pool
+    #[default]
+    Native,
+    /// Commits on hidden refs in the project's git repository
+    Git,
+}
+
 /// Tracks the state of files for checkpointing
 #[derive(Debug, Clone)]
 pub struct FileTracker {
     /// Map of file paths to their current state
     pub tracked_files: HashMap<PathBuf, FileState>,
+    /// Whether every project file has been seen, i.e. after a checkpoint or restore
+    pub has_baseline: bool,
 }
 
 /// State of a tracked file
@@ -127,6 +201,10 @@ pub struct FileState {
     pub last_modified: DateTime<Utc>,
     /// Whether the file currently exists
     pub exists: bool,
+    /// Last known kind of the entry
+    pub kind: FileKind,
+    /// Last known Unix permission bits
+    pub permissions: Option<u32>,
 }
 
 /// Result of a checkpoint operation
@@ -140,6 +218,60 @@ pub struct CheckpointResult {
     pub warnings: Vec<String>,
 }
 
+/// Effect a restore would have on disk, computed without touching any files
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestorePlan {
+    /// The checkpoint that would be restored
+    pub checkpoint: Checkpoint,
+    /// Files present now that are not part of the checkpoint
+    pub files_to_delete: Vec<PathBuf>,
+    /// Files in the checkpoint that don't exist on disk
+    pub files_to_create: Vec<PathBuf>,
+    /// Files whose on-disk content differs from the checkpoint
+    pub files_to_overwrite: Vec<PathBuf>,
+    /// Files that already match the checkpoint
+    pub files_unchanged: Vec<PathBuf>,
+    /// Message index the conversation would be truncated to
+    pub message_index: usize,
+    /// Number of messages currently tracked for the session
+    pub current_message_count: usize,
+    /// Number of messages the session would have after the restore
+    pub restored_message_count: usize,
+    /// Anything that would prevent an exact restore
+    pub warnings: Vec<String>,
+}
+
+/// How a file changed outside of the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExternalChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+/// A file edited outside the session since the file tracker last saw it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalChange {
+    /// Path relative to the project root
+    pub path: PathBuf,
+    /// What happened to the file
+    pub kind: ExternalChangeKind,
+}
+
+/// What to do with external edits when restoring a checkpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    /// Refuse to restore if anything was edited externally
+    #[default]
+    Abort,
+    /// Restore anyway, discarding external edits
+    Force,
+    /// Save external edits in a new checkpoint, then restore
+    Stash,
+}
+
 /// Diff between two checkpoints
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CheckpointDiff {
@@ -168,6 +300,25 @@ pub struct FileDiff {
     pub deletions: usize,
     /// Unified diff content (optional)
     pub diff_content: Option<String>,
+    /// Whether either version is binary, in which case no line diff is produced
+    #[serde(default)]
+    pub is_binary: bool,
+    /// Hunk ranges contained in the unified diff
+    #[serde(default)]
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// Line ranges covered by a single hunk of a unified diff
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffHunk {
+    /// First line of the hunk in the old file (1-based)
+    pub old_start: usize,
+    /// Number of old lines in the hunk
+    pub old_lines: usize,
+    /// First line of the hunk in the new file (1-based)
+    pub new_start: usize,
+    /// Number of new lines in the hunk
+    pub new_lines: usize,
 }
 
 impl Default for CheckpointStrategy {
@@ -186,6 +337,11 @@ impl SessionTimeline {
             auto_checkpoint_enabled: false,
             checkpoint_strategy: CheckpointStrategy::default(),
             total_checkpoints: 0,
+            format_version: storage::STORAGE_FORMAT_VERSION,
+            max_file_size: walk::DEFAULT_MAX_FILE_SIZE,
+            retention: retention::RetentionPolicy::default(),
+            backend: CheckpointBackend::default(),
+            git_repository: None,
         }
     }
 
@@ -196,6 +352,13 @@ impl SessionTimeline {
             .and_then(|root| Self::find_in_tree(root, checkpoint_id))
     }
 
+    /// Find a checkpoint by ID in the timeline tree, for modification
+    pub fn find_checkpoint_mut(&mut self, checkpoint_id: &str) -> Option<&mut TimelineNode> {
+        self.root_node
+            .as_mut()
+            .and_then(|root| Self::find_in_tree_mut(root, checkpoint_id))
+    }
+
     fn find_in_tree<'a>(node: &'a TimelineNode, checkpoint_id: &str) -> Option<&'a TimelineNode> {
         if node.checkpoint.id == checkpoint_id {
             return Some(node);
@@ -209,6 +372,19 @@ impl SessionTimeline {
 
         None
     }
+
+    fn find_in_tree_mut<'a>(
+        node: &'a mut TimelineNode,
+        checkpoint_id: &str,
+    ) -> Option<&'a mut TimelineNode> {
+        if node.checkpoint.id == checkpoint_id {
+            return Some(node);
+        }
+
+        node.children
+            .iter_mut()
+            .find_map(|child| Self::find_in_tree_mut(child, checkpoint_id))
+    }
 }
 
 /// Checkpoint storage paths
@@ -216,20 +392,23 @@ pub struct CheckpointPaths {
     pub timeline_file: PathBuf,
     pub checkpoints_dir: PathBuf,
     pub files_dir: PathBuf,
+    /// Content-addressed blobs shared by every session of the project
+    pub content_pool_dir: PathBuf,
 }
 
 impl CheckpointPaths {
     pub fn new(claude_dir: &PathBuf, project_id: &str, session_id: &str) -> Self {
-        let base_dir = claude_dir
+        let timelines_dir = claude_dir
             .join("projects")
             .join(project_id)
-            .join(".timelines")
-            .join(session_id);
+            .join(".timelines");
+        let base_dir = timelines_dir.join(session_id);
 
         Self {
             timeline_file: base_dir.join("timeline.json"),
             checkpoints_dir: base_dir.join("checkpoints"),
             files_dir: base_dir.join("files"),
+            content_pool_dir: timelines_dir.join("content_pool"),
         }
     }
 
@@ -248,7 +427,7 @@ impl CheckpointPaths {
     #[allow(dead_code)]
     pub fn file_snapshot_path(&self, _checkpoint_id: &str, file_hash: &str) -> PathBuf {
         // In content-addressable storage, files are stored by hash in the content pool
-        self.files_dir.join("content_pool").join(file_hash)
+        self.content_pool_dir.join(file_hash)
     }
 
     #[allow(dead_code)]
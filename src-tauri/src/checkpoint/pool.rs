@@ -0,0 +1,295 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::{storage::CheckpointStorage, CheckpointPaths};
+
+/// Reference counts for the project content pool, kept next to the blobs
+pub const REFCOUNT_FILE: &str = "refcounts.json";
+
+/// Lock file that serializes reference count updates from every session of the project
+const REFCOUNT_LOCK_FILE: &str = "refcounts.lock";
+
+/// Directory inside the content pool that damaged blobs are moved to by a repair
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// Blobs written or reused this recently are never collected, so a checkpoint being saved
+/// by another session can't lose content before its refs land on disk
+pub const GC_GRACE_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+/// Whether a pool file name is a content hash rather than bookkeeping
+pub(super) fn is_content_hash(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Per-session pool used before the pool moved up to the project
+fn legacy_pool_dir(paths: &CheckpointPaths) -> PathBuf {
+    paths.files_dir.join("content_pool")
+}
+
+impl CheckpointStorage {
+    /// Location of a blob, falling back to a session pool that hasn't been migrated yet
+    pub(super) fn blob_path(&self, paths: &CheckpointPaths, hash: &str) -> PathBuf {
+        let pooled = paths.content_pool_dir.join(hash);
+        if !pooled.exists() {
+            let legacy = legacy_pool_dir(paths).join(hash);
+            if legacy.exists() {
+                return legacy;
+            }
+        }
+        pooled
+    }
+
+    /// Content hashes referenced by one checkpoint's file refs, one entry per ref
+    pub(super) fn checkpoint_ref_hashes(
+        &self,
+        paths: &CheckpointPaths,
+        checkpoint_id: &str,
+    ) -> Vec<String> {
+        read_ref_hashes(&paths.files_dir.join("refs").join(checkpoint_id))
+    }
+
+    /// Add `delta` to the reference count of each hash
+    ///
+    /// Updates hold the project's refcount lock, so sessions saving or pruning at the same
+    /// time don't lose each other's changes. A crash can still leave counts off, so
+    /// `garbage_collect_content` recounts from the refs before deleting anything.
+    pub(super) fn adjust_refcounts(
+        &self,
+        paths: &CheckpointPaths,
+        hashes: &[String],
+        delta: i64,
+    ) -> Result<()> {
+        if hashes.is_empty() {
+            return Ok(());
+        }
+
+        let _lock = lock_refcounts(paths)?;
+        let mut counts = self.load_refcounts(paths);
+        for hash in hashes {
+            let count = counts.entry(hash.clone()).or_insert(0);
+            *count = count.saturating_add_signed(delta);
+            if *count == 0 {
+                counts.remove(hash);
+            }
+        }
+        self.save_refcounts(paths, &counts)
+    }
+
+    /// Reference counts as last recorded
+    pub fn load_refcounts(&self, paths: &CheckpointPaths) -> HashMap<String, u64> {
+        fs::read_to_string(paths.content_pool_dir.join(REFCOUNT_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_refcounts(&self, paths: &CheckpointPaths, counts: &HashMap<String, u64>) -> Result<()> {
+        fs::create_dir_all(&paths.content_pool_dir)
+            .context("Failed to create content pool directory")?;
+
+        // Write then rename so readers never see a partial file
+        let refcount_file = paths.content_pool_dir.join(REFCOUNT_FILE);
+        let tmp_file = refcount_file.with_extension("json.tmp");
+        fs::write(&tmp_file, serde_json::to_string(counts)?)
+            .context("Failed to write reference counts")?;
+        fs::rename(&tmp_file, &refcount_file).context("Failed to replace reference counts")
+    }
+
+    /// Count references to each blob across every session of the project
+    ///
+    /// When `skip_refs_dir` is given, that session's refs are left out of the count.
+    fn count_project_refs(
+        &self,
+        paths: &CheckpointPaths,
+        skip_refs_dir: Option<&Path>,
+    ) -> Result<HashMap<String, u64>> {
+        let mut counts = HashMap::new();
+        let timelines_dir = match paths.content_pool_dir.parent() {
+            Some(dir) if dir.exists() => dir,
+            _ => return Ok(counts),
+        };
+
+        for session_entry in fs::read_dir(timelines_dir)? {
+            let refs_dir = session_entry?.path().join("files").join("refs");
+            if !refs_dir.is_dir() || skip_refs_dir == Some(refs_dir.as_path()) {
+                continue;
+            }
+            for checkpoint_entry in fs::read_dir(&refs_dir)? {
+                let checkpoint_dir = checkpoint_entry?.path();
+                if checkpoint_dir.is_dir() {
+                    for hash in read_ref_hashes(&checkpoint_dir) {
+                        *counts.entry(hash).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(counts)
+    }
+
+    /// Garbage collect content that no session in the project references
+    ///
+    /// Reference counts are rebuilt from every session's refs first, so a blob is only
+    /// deleted when nothing points at it, directly or as the base of a delta. Blobs touched
+    /// within `GC_GRACE_PERIOD` are kept.
+    pub fn garbage_collect_content(&self, project_id: &str, session_id: &str) -> Result<usize> {
+        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
+        if !paths.content_pool_dir.exists() {
+            return Ok(0);
+        }
+
+        let counts = {
+            let _lock = lock_refcounts(&paths)?;
+            let counts = self.count_project_refs(&paths, None)?;
+            self.save_refcounts(&paths, &counts)?;
+            counts
+        };
+
+        // A referenced delta keeps every blob in its chain alive; if a chain can't be
+        // followed, nothing is collected
+        let live = self
+            .with_delta_bases(&paths, counts.keys().cloned())
+            .context("Failed to follow delta chains, not collecting content")?;
+
+        let grace_cutoff = SystemTime::now()
+            .checked_sub(GC_GRACE_PERIOD)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        // Remove unreferenced content
+        let mut removed_count = 0;
+        for entry in fs::read_dir(&paths.content_pool_dir)? {
+            let content_file = entry?.path();
+            let Some(hash) = content_file.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if !is_content_hash(hash) || live.contains(hash) {
+                continue;
+            }
+
+            let recently_used = fs::metadata(&content_file)
+                .and_then(|m| m.modified())
+                .map(|modified| modified > grace_cutoff)
+                .unwrap_or(true);
+            if !recently_used && fs::remove_file(&content_file).is_ok() {
+                removed_count += 1;
+            }
+        }
+
+        Ok(removed_count)
+    }
+
+    /// Blobs that other sessions of the project need, directly or as the base of a delta
+    pub(super) fn blobs_used_by_other_sessions(
+        &self,
+        paths: &CheckpointPaths,
+    ) -> Result<BTreeSet<String>> {
+        let own_refs = paths.files_dir.join("refs");
+        let counts = self.count_project_refs(paths, Some(&own_refs))?;
+        self.with_delta_bases(paths, counts.into_keys())
+    }
+
+    /// Move a damaged blob into the pool's quarantine directory
+    ///
+    /// The blob is kept for inspection rather than deleted; a later checkpoint of the same
+    /// content writes a fresh copy to the pool.
+    pub(super) fn quarantine_blob(&self, paths: &CheckpointPaths, hash: &str) -> Result<()> {
+        let blob = self.blob_path(paths, hash);
+        if !blob.exists() {
+            return Ok(());
+        }
+
+        let quarantine_dir = paths.content_pool_dir.join(QUARANTINE_DIR);
+        fs::create_dir_all(&quarantine_dir).context("Failed to create quarantine directory")?;
+        fs::rename(&blob, quarantine_dir.join(hash)).context("Failed to quarantine blob")
+    }
+
+    /// Move a session's own content pool into the project pool
+    ///
+    /// Blobs already in the project pool are dropped from the session, and every ref in the
+    /// session is counted. Does nothing once the session pool is gone.
+    pub(super) fn migrate_content_pool(&self, paths: &CheckpointPaths) -> Result<()> {
+        let legacy_dir = legacy_pool_dir(paths);
+        if !legacy_dir.exists() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&paths.content_pool_dir)
+            .context("Failed to create content pool directory")?;
+
+        let mut moved = 0;
+        for entry in fs::read_dir(&legacy_dir)? {
+            let blob = entry?.path();
+            let hash = match blob.file_name().and_then(|n| n.to_str()) {
+                Some(hash) if is_content_hash(hash) => hash,
+                _ => continue,
+            };
+            let dest = paths.content_pool_dir.join(hash);
+            if dest.exists() {
+                fs::remove_file(&blob).context("Failed to remove duplicate blob")?;
+            } else if fs::rename(&blob, &dest).is_err() {
+                // Different filesystem; fall back to copying
+                fs::copy(&blob, &dest).context("Failed to move blob to project pool")?;
+                fs::remove_file(&blob).context("Failed to remove migrated blob")?;
+            }
+            moved += 1;
+        }
+        fs::remove_dir_all(&legacy_dir).context("Failed to remove session content pool")?;
+
+        let refs_dir = paths.files_dir.join("refs");
+        let mut hashes = Vec::new();
+        if refs_dir.exists() {
+            for checkpoint_entry in fs::read_dir(&refs_dir)? {
+                let checkpoint_dir = checkpoint_entry?.path();
+                if checkpoint_dir.is_dir() {
+                    hashes.extend(read_ref_hashes(&checkpoint_dir));
+                }
+            }
+        }
+        self.adjust_refcounts(paths, &hashes, 1)?;
+
+        log::info!(
+            "Moved {} blob(s) into the project content pool at {}",
+            moved,
+            paths.content_pool_dir.display()
+        );
+        Ok(())
+    }
+}
+
+/// Wait for exclusive use of the project's reference counts, released when dropped
+fn lock_refcounts(paths: &CheckpointPaths) -> Result<fs::File> {
+    fs::create_dir_all(&paths.content_pool_dir)
+        .context("Failed to create content pool directory")?;
+    let lock_file = fs::File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(paths.content_pool_dir.join(REFCOUNT_LOCK_FILE))
+        .context("Failed to open refcount lock")?;
+    lock_file
+        .lock()
+        .context("Failed to lock reference counts")?;
+    Ok(lock_file)
+}
+
+/// Hashes of the live content referenced from one checkpoint's refs directory
+fn read_ref_hashes(checkpoint_refs_dir: &Path) -> Vec<String> {
+    let entries = match fs::read_dir(checkpoint_refs_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("json"))
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .filter(|ref_metadata| !ref_metadata["is_deleted"].as_bool().unwrap_or(false))
+        .filter_map(|ref_metadata| ref_metadata["hash"].as_str().map(str::to_string))
+        .filter(|hash| !hash.is_empty())
+        .collect()
+}
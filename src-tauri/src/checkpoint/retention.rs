@@ -0,0 +1,201 @@
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use super::{storage::CheckpointStorage, Checkpoint, CheckpointPaths};
+
+/// Rules deciding which checkpoints survive automatic cleanup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionPolicy {
+    /// Whether the policy runs after every new checkpoint
+    pub enabled: bool,
+    /// Every checkpoint younger than this many hours is kept
+    pub keep_recent_hours: u32,
+    /// Keep the newest checkpoint of each day past the recent window
+    pub keep_daily: bool,
+    /// Never remove checkpoints that were given tags or a description by hand
+    pub keep_described: bool,
+    /// Cap in bytes on checkpoint storage across all sessions of the project
+    pub max_project_bytes: Option<u64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keep_recent_hours: 24,
+            keep_daily: true,
+            keep_described: true,
+            max_project_bytes: None,
+        }
+    }
+}
+
+/// Outcome of applying a retention policy
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RetentionResult {
+    /// Checkpoints removed from the timeline
+    pub removed_checkpoints: Vec<String>,
+    /// Content blobs garbage collected afterwards
+    pub blobs_collected: usize,
+    /// Checkpoint storage used by the project once cleanup finished
+    pub project_bytes: u64,
+}
+
+impl CheckpointStorage {
+    /// Apply the session's retention policy to its timeline on disk
+    ///
+    /// The current checkpoint is always kept, as are checkpoints with tags or a hand-written
+    /// description when the policy says so. The disk quota can only be met by removing
+    /// checkpoints from this session; if that isn't enough, or a removal frees no content
+    /// because its blobs are still within the GC grace period, the quota is left exceeded
+    /// and a warning is logged. Each removal happens under the timeline lock, so concurrent
+    /// writers aren't undone.
+    pub fn apply_retention(&self, project_id: &str, session_id: &str) -> Result<RetentionResult> {
+        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
+        let mut result = RetentionResult::default();
+
+        let (doomed, quota, candidates) =
+            self.update_timeline(&paths.timeline_file, |timeline| {
+                let policy = timeline.retention.clone();
+
+                let mut checkpoints = Vec::new();
+                if let Some(root) = &timeline.root_node {
+                    Self::collect_checkpoints(root, &mut checkpoints);
+                }
+                checkpoints.sort_by_key(|c| c.timestamp);
+
+                let current_id = timeline.current_checkpoint_id.clone();
+                let is_protected = |checkpoint: &Checkpoint| {
+                    current_id.as_ref() == Some(&checkpoint.id)
+                        || (policy.keep_described
+                            && (!checkpoint.tags.is_empty() || has_written_description(checkpoint)))
+                };
+
+                // Thin everything outside the recent window
+                let cutoff = Utc::now() - Duration::hours(i64::from(policy.keep_recent_hours));
+                let mut newest_per_day: HashMap<NaiveDate, &str> = HashMap::new();
+                for checkpoint in checkpoints.iter().filter(|c| c.timestamp < cutoff) {
+                    // Sorted oldest first, so later entries win
+                    newest_per_day.insert(checkpoint.timestamp.date_naive(), &checkpoint.id);
+                }
+
+                let doomed: HashSet<String> = checkpoints
+                    .iter()
+                    .filter(|c| c.timestamp < cutoff && !is_protected(c))
+                    .filter(|c| {
+                        !policy.keep_daily
+                            || newest_per_day.get(&c.timestamp.date_naive()) != Some(&c.id.as_str())
+                    })
+                    .map(|c| c.id.clone())
+                    .collect();
+                self.prune_checkpoints(&paths, timeline, &doomed)?;
+
+                // Oldest first, for the quota
+                let candidates: Vec<String> = checkpoints
+                    .iter()
+                    .filter(|c| !doomed.contains(&c.id) && !is_protected(c))
+                    .map(|c| c.id.clone())
+                    .collect();
+                Ok((doomed, policy.max_project_bytes, candidates))
+            })?;
+
+        if !doomed.is_empty() {
+            result.blobs_collected += self.garbage_collect_content(project_id, session_id)?;
+            result.removed_checkpoints.extend(doomed.iter().cloned());
+        }
+
+        // Enforce the quota by dropping the oldest remaining checkpoints one at a time
+        let project_dir = self
+            .claude_dir
+            .join("projects")
+            .join(project_id)
+            .join(".timelines");
+        result.project_bytes = dir_size(&project_dir);
+        if let Some(quota) = quota {
+            let mut candidates = candidates.into_iter();
+
+            while result.project_bytes > quota {
+                let Some(checkpoint_id) = candidates.next() else {
+                    log::warn!(
+                        "Checkpoint storage for project {} is {} bytes, over its {} byte quota, \
+                         but no more checkpoints can be removed from session {}",
+                        project_id,
+                        result.project_bytes,
+                        quota,
+                        session_id
+                    );
+                    break;
+                };
+
+                // The checkpoint may have become current or gone away since the policy ran
+                let removed = self.update_timeline(&paths.timeline_file, |timeline| {
+                    if timeline.current_checkpoint_id.as_deref() == Some(checkpoint_id.as_str())
+                        || timeline.find_checkpoint(&checkpoint_id).is_none()
+                    {
+                        return Ok(false);
+                    }
+                    let single = HashSet::from([checkpoint_id.clone()]);
+                    self.prune_checkpoints(&paths, timeline, &single)?;
+                    Ok(true)
+                })?;
+                if !removed {
+                    continue;
+                }
+
+                let collected = self.garbage_collect_content(project_id, session_id)?;
+                result.blobs_collected += collected;
+                result.removed_checkpoints.push(checkpoint_id);
+                result.project_bytes = dir_size(&project_dir);
+
+                // Blobs inside the GC grace period survive collection, so removing more
+                // checkpoints now would lose history without freeing their content
+                if collected == 0 && result.project_bytes > quota {
+                    log::warn!(
+                        "Checkpoint storage for project {} is {} bytes, over its {} byte quota, \
+                         but removing a checkpoint from session {} freed no content; \
+                         stopping until its blobs can be collected",
+                        project_id,
+                        result.project_bytes,
+                        quota,
+                        session_id
+                    );
+                    break;
+                }
+            }
+        }
+
+        result.removed_checkpoints.sort();
+        Ok(result)
+    }
+}
+
+/// Whether a checkpoint has a description the user wrote rather than a generated one
+fn has_written_description(checkpoint: &Checkpoint) -> bool {
+    !checkpoint.auto_generated
+        && checkpoint
+            .description
+            .as_deref()
+            .is_some_and(|description| !description.trim().is_empty())
+}
+
+/// Total size in bytes of the files below `path`
+fn dir_size(path: &Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
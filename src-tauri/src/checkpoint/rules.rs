@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use super::storage::CheckpointStorage;
+
+/// Commands that change files or repository state, for the default Bash rule
+const DESTRUCTIVE_BASH_PATTERN: &str = concat!(
+    r"\b(rm|rmdir|mv|cp|dd|truncate|shred|unlink|ln|chmod|chown|touch|mkdir|tee|patch",
+    r"|sed\s+-i|perl\s+-i|npm|yarn|pnpm|bun|cargo|make",
+    r"|git\s+(checkout|switch|reset|restore|clean|stash|rebase|merge|pull|apply|rm|mv))\b",
+    // Output redirected into a file
+    r"|(^|\s)>>?\s*[^&\s]",
+);
+
+/// One condition under which the Smart strategy takes a checkpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartRule {
+    /// Tool name, case-insensitive, with `*` wildcards, e.g. `edit` or `mcp__github__*`
+    pub tool: String,
+    /// Regex the Bash command must match; any command matches when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_pattern: Option<String>,
+    /// Tracked files that must be modified since the last checkpoint for the rule to apply
+    ///
+    /// Counted when the rule is checked: agent runs check a tool use once its result is
+    /// back, so the tool's own changes count; a session checks when its prompt completes.
+    #[serde(default, alias = "minChangedFiles")]
+    pub min_files_changed_since_checkpoint: usize,
+}
+
+impl SmartRule {
+    fn new(tool: &str, command_pattern: Option<&str>, min_changed_files: usize) -> Self {
+        Self {
+            tool: tool.to_string(),
+            command_pattern: command_pattern.map(str::to_string),
+            min_files_changed_since_checkpoint: min_changed_files,
+        }
+    }
+
+    /// Parse the rule's patterns, or `None` with a warning if one is bad
+    fn compile(&self) -> Option<CompiledRule> {
+        let tool = match Pattern::new(&self.tool) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                log::warn!(
+                    "Ignoring Smart rule with bad tool pattern {}: {}",
+                    self.tool,
+                    e
+                );
+                return None;
+            }
+        };
+        let command = match self.command_pattern.as_deref().map(Regex::new) {
+            None => None,
+            Some(Ok(regex)) => Some(regex),
+            Some(Err(e)) => {
+                log::warn!(
+                    "Ignoring Smart rule with bad command pattern {}: {}",
+                    self.command_pattern.as_deref().unwrap_or_default(),
+                    e
+                );
+                return None;
+            }
+        };
+
+        Some(CompiledRule {
+            tool,
+            command,
+            min_changed_files: self.min_files_changed_since_checkpoint,
+        })
+    }
+}
+
+/// A Smart rule with its patterns parsed
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    tool: Pattern,
+    command: Option<Regex>,
+    min_changed_files: usize,
+}
+
+impl CompiledRule {
+    /// Whether a tool use satisfies this rule
+    fn matches(&self, tool_name: &str, input: &serde_json::Value, changed_files: usize) -> bool {
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::default()
+        };
+        if !self.tool.matches_with(tool_name, options) || changed_files < self.min_changed_files {
+            return false;
+        }
+
+        let Some(command) = &self.command else {
+            return true;
+        };
+        command.is_match(input.get("command").and_then(|c| c.as_str()).unwrap_or(""))
+    }
+}
+
+/// A project's rules for when the Smart strategy checkpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartRules {
+    /// A tool use triggers a checkpoint when any rule matches it
+    pub rules: Vec<SmartRule>,
+    /// Seconds that must pass after a checkpoint before the next automatic one
+    #[serde(default)]
+    pub min_interval_secs: u64,
+}
+
+impl Default for SmartRules {
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                SmartRule::new("write", None, 0),
+                SmartRule::new("edit", None, 0),
+                SmartRule::new("multiedit", None, 0),
+                SmartRule::new("notebookedit", None, 0),
+                SmartRule::new("bash", Some(DESTRUCTIVE_BASH_PATTERN), 0),
+                // MCP tools only count when the project has changes not yet checkpointed
+                SmartRule::new("mcp__*", None, 1),
+            ],
+            min_interval_secs: 0,
+        }
+    }
+}
+
+impl SmartRules {
+    /// Check every pattern, so bad rules are rejected when saved rather than skipped later
+    pub fn validate(&self) -> Result<()> {
+        for rule in &self.rules {
+            if rule.tool.trim().is_empty() {
+                anyhow::bail!("Smart rule has no tool pattern");
+            }
+            Pattern::new(&rule.tool)
+                .with_context(|| format!("Invalid tool pattern: {}", rule.tool))?;
+            if let Some(command_pattern) = &rule.command_pattern {
+                Regex::new(command_pattern)
+                    .with_context(|| format!("Invalid command pattern: {}", command_pattern))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse every rule's patterns once, for checking many messages
+    pub fn compile(&self) -> CompiledSmartRules {
+        CompiledSmartRules {
+            rules: self.rules.iter().filter_map(SmartRule::compile).collect(),
+            min_interval_secs: self.min_interval_secs,
+        }
+    }
+}
+
+/// Smart rules ready to be checked against messages
+#[derive(Debug, Clone)]
+pub struct CompiledSmartRules {
+    rules: Vec<CompiledRule>,
+    /// Seconds that must pass after a checkpoint before the next automatic one
+    pub min_interval_secs: u64,
+}
+
+impl CompiledSmartRules {
+    /// Whether any tool use in a JSONL message matches a rule
+    pub fn matches_message(&self, message: &serde_json::Value, changed_files: usize) -> bool {
+        let Some(content) = message
+            .get("message")
+            .and_then(|m| m.get("content"))
+            .and_then(|c| c.as_array())
+        else {
+            return false;
+        };
+
+        content.iter().any(|item| {
+            if item.get("type").and_then(|t| t.as_str()) != Some("tool_use") {
+                return false;
+            }
+            let tool_name = item.get("name").and_then(|n| n.as_str()).unwrap_or("");
+            let input = item.get("input").unwrap_or(&serde_json::Value::Null);
+            self.rules
+                .iter()
+                .any(|rule| rule.matches(tool_name, input, changed_files))
+        })
+    }
+}
+
+impl CheckpointStorage {
+    pub(super) fn smart_rules_file(&self, project_id: &str) -> PathBuf {
+        self.claude_dir
+            .join("projects")
+            .join(project_id)
+            .join(".timelines")
+            .join("smart_rules.json")
+    }
+
+    /// The project's Smart strategy rules, or the defaults if none were saved
+    pub fn load_smart_rules(&self, project_id: &str) -> Result<SmartRules> {
+        let rules_file = self.smart_rules_file(project_id);
+        if !rules_file.exists() {
+            return Ok(SmartRules::default());
+        }
+        let rules_json = fs::read_to_string(&rules_file).context("Failed to read Smart rules")?;
+        serde_json::from_str(&rules_json).context("Failed to parse Smart rules")
+    }
+
+    /// Replace the Smart strategy rules for every session of the project
+    pub fn save_smart_rules(&self, project_id: &str, rules: &SmartRules) -> Result<()> {
+        rules.validate()?;
+        let rules_file = self.smart_rules_file(project_id);
+        if let Some(dir) = rules_file.parent() {
+            fs::create_dir_all(dir).context("Failed to create timelines directory")?;
+        }
+        fs::write(&rules_file, serde_json::to_string_pretty(rules)?)
+            .context("Failed to write Smart rules")
+    }
+}
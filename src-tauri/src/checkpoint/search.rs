@@ -0,0 +1,215 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use super::{storage::CheckpointStorage, Checkpoint, CheckpointPaths, SessionTimeline};
+
+/// Criteria for finding checkpoints; a checkpoint must match every one that is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CheckpointQuery {
+    /// Carries this tag, ignoring case
+    pub tag: Option<String>,
+    /// Text found in the description or the user prompt, ignoring case
+    pub text: Option<String>,
+    /// Created at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Created at or before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Touched this file, or a file below this directory, relative to the project root
+    pub path: Option<PathBuf>,
+}
+
+/// A checkpoint found by a search
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckpointSearchHit {
+    /// The matching checkpoint; its `session_id` tells which session it belongs to
+    pub checkpoint: Checkpoint,
+    /// Files the checkpoint touched that match the query's path, if one was given
+    pub matched_files: Vec<PathBuf>,
+}
+
+impl CheckpointQuery {
+    /// Whether a checkpoint matches everything but the path
+    fn matches_labels(&self, checkpoint: &Checkpoint) -> bool {
+        let tag_ok = self
+            .tag
+            .as_deref()
+            .map(str::trim)
+            .is_none_or(|tag| checkpoint.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)));
+
+        let text_ok = self.text.as_deref().map(str::trim).is_none_or(|text| {
+            let needle = text.to_lowercase();
+            checkpoint
+                .description
+                .iter()
+                .chain(std::iter::once(&checkpoint.metadata.user_prompt))
+                .any(|haystack| haystack.to_lowercase().contains(&needle))
+        });
+
+        tag_ok
+            && text_ok
+            && self.since.is_none_or(|since| checkpoint.timestamp >= since)
+            && self.until.is_none_or(|until| checkpoint.timestamp <= until)
+    }
+}
+
+impl CheckpointStorage {
+    /// Search the checkpoints of every session in a project, newest first
+    ///
+    /// Sessions whose timeline can't be read are skipped with a warning.
+    pub fn search_checkpoints(
+        &self,
+        project_id: &str,
+        query: &CheckpointQuery,
+    ) -> Result<Vec<CheckpointSearchHit>> {
+        let timelines_dir = self
+            .claude_dir
+            .join("projects")
+            .join(project_id)
+            .join(".timelines");
+        if !timelines_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let path_filter = match query.path.as_deref() {
+            Some(path) => clean_relative_path(path)?,
+            None => None,
+        };
+
+        let mut hits = Vec::new();
+        for entry in fs::read_dir(&timelines_dir)? {
+            let Some(session_id) = entry?.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let paths = CheckpointPaths::new(&self.claude_dir, project_id, &session_id);
+            if !paths.timeline_file.is_file() {
+                continue;
+            }
+            let timeline = match self.load_timeline(&paths.timeline_file) {
+                Ok(timeline) => timeline,
+                Err(e) => {
+                    log::warn!("Skipping session {} in search: {}", session_id, e);
+                    continue;
+                }
+            };
+
+            let mut checkpoints = Vec::new();
+            if let Some(root) = &timeline.root_node {
+                Self::collect_checkpoints(root, &mut checkpoints);
+            }
+
+            for checkpoint in checkpoints {
+                if !query.matches_labels(&checkpoint) {
+                    continue;
+                }
+
+                let matched_files = match &path_filter {
+                    Some(filter) => {
+                        let touched = self
+                            .files_touched(&paths, &timeline, &checkpoint)
+                            .unwrap_or_else(|e| {
+                                log::warn!(
+                                    "Failed to list files of checkpoint {}: {}",
+                                    checkpoint.id,
+                                    e
+                                );
+                                Vec::new()
+                            });
+                        let matched: Vec<PathBuf> = touched
+                            .into_iter()
+                            .filter(|file| file.starts_with(filter))
+                            .collect();
+                        if matched.is_empty() {
+                            continue;
+                        }
+                        matched
+                    }
+                    None => Vec::new(),
+                };
+
+                hits.push(CheckpointSearchHit {
+                    checkpoint,
+                    matched_files,
+                });
+            }
+        }
+
+        hits.sort_by_key(|hit| std::cmp::Reverse(hit.checkpoint.timestamp));
+        Ok(hits)
+    }
+
+    /// Files a checkpoint recorded a change to, sorted
+    fn files_touched(
+        &self,
+        paths: &CheckpointPaths,
+        timeline: &SessionTimeline,
+        checkpoint: &Checkpoint,
+    ) -> Result<Vec<PathBuf>> {
+        let mut files = match &checkpoint.git_commit {
+            Some(commit) => {
+                let repo = self.git_repository(paths)?;
+                let parent_commit = checkpoint
+                    .parent_checkpoint_id
+                    .as_deref()
+                    .and_then(|id| timeline.find_checkpoint(id))
+                    .and_then(|node| node.checkpoint.git_commit.clone());
+                match parent_commit {
+                    Some(parent) => repo
+                        .diff_trees(&parent, commit)?
+                        .into_iter()
+                        .map(|change| change.path)
+                        .collect(),
+                    None => repo
+                        .read_tree(commit)?
+                        .into_iter()
+                        .map(|entry| entry.path)
+                        .collect(),
+                }
+            }
+            None => ref_paths(&paths.files_dir.join("refs").join(&checkpoint.id)),
+        };
+
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Paths recorded in one checkpoint's refs, deletions included
+fn ref_paths(checkpoint_refs_dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(checkpoint_refs_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("json"))
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .filter_map(|ref_metadata| ref_metadata["path"].as_str().map(PathBuf::from))
+        .collect()
+}
+
+/// A query path as a clean path relative to the project root
+///
+/// Paths that are absolute or climb out of the project are rejected; a path naming the
+/// project root itself, such as an empty one, filters nothing.
+fn clean_relative_path(path: &Path) -> Result<Option<PathBuf>> {
+    let mut clean = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir => {}
+            _ => anyhow::bail!(
+                "Search path must be relative to the project root: {}",
+                path.display()
+            ),
+        }
+    }
+    Ok((!clean.as_os_str().is_empty()).then_some(clean))
+}
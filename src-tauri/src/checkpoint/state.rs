@@ -75,6 +75,15 @@ impl CheckpointState {
             CheckpointManager::new(project_id, session_id.clone(), project_path, claude_dir)
                 .await?;
 
+        // Record file changes precisely while the session is active
+        if let Err(e) = manager.start_watching().await {
+            log::warn!(
+                "Failed to start file watcher for session {}, falling back to heuristics: {}",
+                session_id,
+                e
+            );
+        }
+
         let manager_arc = Arc::new(manager);
         managers.insert(session_id, Arc::clone(&manager_arc));
 
@@ -95,7 +104,11 @@ impl CheckpointState {
     /// This should be called when a session ends to free resources
     pub async fn remove_manager(&self, session_id: &str) -> Option<Arc<CheckpointManager>> {
         let mut managers = self.managers.write().await;
-        managers.remove(session_id)
+        let manager = managers.remove(session_id);
+        if let Some(manager) = &manager {
+            manager.stop_watching();
+        }
+        manager
     }
 
     /// Clears all managers
@@ -104,6 +117,9 @@ impl CheckpointState {
     #[allow(dead_code)]
     pub async fn clear_all(&self) {
         let mut managers = self.managers.write().await;
+        for manager in managers.values() {
+            manager.stop_watching();
+        }
         managers.clear();
     }
 
@@ -1,18 +1,35 @@
 use anyhow::{Context, Result};
 use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 use zstd::stream::{decode_all, encode_all};
 
 use super::{
+    crypto::{self, KeyCache},
+    git::{self, GitRepository},
     Checkpoint, CheckpointPaths, CheckpointResult, FileSnapshot, SessionTimeline, TimelineNode,
 };
 
+/// Current on-disk format version written for new timelines
+///
+/// Version 1 stored file content as UTF-8 text and recorded non-UTF-8 files as empty.
+/// Version 2 stores raw bytes.
+/// Version 3 keeps blobs in a content pool shared by every session of the project.
+pub const STORAGE_FORMAT_VERSION: u32 = 3;
+
+/// Format version assumed for timelines written before versioning was introduced
+pub fn legacy_format_version() -> u32 {
+    1
+}
+
 /// Manages checkpoint storage operations
 pub struct CheckpointStorage {
     pub claude_dir: PathBuf,
-    compression_level: i32,
+    pub(super) compression_level: i32,
+    /// Checkpoint encryption keys, reloaded when the keyfile changes
+    pub(super) keys: KeyCache,
 }
 
 impl CheckpointStorage {
@@ -21,6 +38,7 @@ impl CheckpointStorage {
         Self {
             claude_dir,
             compression_level: 3, // Default zstd compression level
+            keys: KeyCache::default(),
         }
     }
 
@@ -33,16 +51,82 @@ impl CheckpointStorage {
             .context("Failed to create checkpoints directory")?;
         fs::create_dir_all(&paths.files_dir).context("Failed to create files directory")?;
 
-        // Initialize empty timeline if it doesn't exist
+        // Initialize empty timeline if it doesn't exist, unless another manager beats us to it
+        let lock = self.lock_timeline(&paths.timeline_file)?;
         if !paths.timeline_file.exists() {
             let timeline = SessionTimeline::new(session_id.to_string());
-            self.save_timeline(&paths.timeline_file, &timeline)?;
+            return lock.write(&timeline);
         }
+        drop(lock);
 
-        Ok(())
+        self.migrate_storage(&paths)
+    }
+
+    /// Upgrade an existing timeline to the current storage format
+    pub(super) fn migrate_storage(&self, paths: &CheckpointPaths) -> Result<()> {
+        // v2 -> v3: blobs move from the session's own pool to the project pool. This also
+        // runs for imported bundles, which carry their blobs in a session pool.
+        self.migrate_content_pool(paths)?;
+
+        let lock = self.lock_timeline(&paths.timeline_file)?;
+        let mut timeline = lock.read()?;
+        if timeline.format_version >= STORAGE_FORMAT_VERSION {
+            return Ok(());
+        }
+
+        log::info!(
+            "Migrating checkpoint storage for session {} from format v{} to v{}",
+            timeline.session_id,
+            timeline.format_version,
+            STORAGE_FORMAT_VERSION
+        );
+
+        if timeline.format_version < 2 {
+            // v1 -> v2: text snapshots are byte-identical to their raw form, so the content pool
+            // stays valid. Non-UTF-8 files were recorded as empty content, which shows up as a
+            // non-empty file whose hash is the hash of nothing. Flag those references so restore
+            // leaves the file alone instead of truncating it.
+            let empty_hash = Self::calculate_file_hash(&[]);
+            let refs_dir = paths.files_dir.join("refs");
+            if refs_dir.exists() {
+                for checkpoint_entry in fs::read_dir(&refs_dir)? {
+                    let checkpoint_dir = checkpoint_entry?.path();
+                    if !checkpoint_dir.is_dir() {
+                        continue;
+                    }
+                    for ref_entry in fs::read_dir(&checkpoint_dir)? {
+                        let ref_path = ref_entry?.path();
+                        if ref_path.extension().and_then(|e| e.to_str()) != Some("json") {
+                            continue;
+                        }
+                        let ref_json = fs::read_to_string(&ref_path)
+                            .context("Failed to read file reference")?;
+                        let mut ref_metadata: serde_json::Value =
+                            serde_json::from_str(&ref_json)
+                                .context("Failed to parse file reference")?;
+
+                        let lost_content = ref_metadata["hash"].as_str()
+                            == Some(empty_hash.as_str())
+                            && ref_metadata["size"].as_u64().unwrap_or(0) > 0
+                            && !ref_metadata["is_deleted"].as_bool().unwrap_or(false);
+                        if lost_content {
+                            ref_metadata["is_unrecoverable"] = serde_json::Value::Bool(true);
+                            fs::write(&ref_path, serde_json::to_string_pretty(&ref_metadata)?)
+                                .context("Failed to write file reference")?;
+                        }
+                    }
+                }
+            }
+        }
+
+        timeline.format_version = STORAGE_FORMAT_VERSION;
+        lock.write(&timeline)
     }
 
     /// Save a checkpoint to disk
+    ///
+    /// The saved checkpoint's `snapshot_size` is the number of bytes it added to disk:
+    /// its metadata, messages and refs, plus any blobs it was first to store.
     pub fn save_checkpoint(
         &self,
         project_id: &str,
@@ -57,26 +141,42 @@ impl CheckpointStorage {
         // Create checkpoint directory
         fs::create_dir_all(&checkpoint_dir).context("Failed to create checkpoint directory")?;
 
-        // Save checkpoint metadata
-        let metadata_path = paths.checkpoint_metadata_file(&checkpoint.id);
-        let metadata_json = serde_json::to_string_pretty(checkpoint)
-            .context("Failed to serialize checkpoint metadata")?;
-        fs::write(&metadata_path, metadata_json).context("Failed to write checkpoint metadata")?;
-
         // Save messages (compressed)
         let messages_path = paths.checkpoint_messages_file(&checkpoint.id);
         let compressed_messages = encode_all(messages.as_bytes(), self.compression_level)
             .context("Failed to compress messages")?;
-        fs::write(&messages_path, compressed_messages)
+        let mut bytes_written = self
+            .write_sealed(
+                &messages_path,
+                compressed_messages,
+                &crypto::messages_context(&checkpoint.id),
+            )
             .context("Failed to write compressed messages")?;
 
+        // Earlier checkpoints on this branch, nearest first, for finding delta bases
+        let timeline = self.load_timeline(&paths.timeline_file)?;
+        let mut tree_parents = HashMap::new();
+        if let Some(root) = &timeline.root_node {
+            collect_tree_parents(root, None, &mut tree_parents);
+        }
+        let mut ancestors = Vec::new();
+        let mut next = checkpoint.parent_checkpoint_id.clone();
+        while let Some(id) = next {
+            next = tree_parents.get(&id).cloned().flatten();
+            ancestors.push(id);
+        }
+
         // Save file snapshots
         let mut warnings = Vec::new();
         let mut files_processed = 0;
 
         for snapshot in &file_snapshots {
-            match self.save_file_snapshot(&paths, snapshot) {
-                Ok(_) => files_processed += 1,
+            let base_hash = self.previous_hash(&paths, &ancestors, &snapshot.file_path);
+            match self.save_file_snapshot(&paths, snapshot, base_hash.as_deref()) {
+                Ok(written) => {
+                    files_processed += 1;
+                    bytes_written += written;
+                }
                 Err(e) => warnings.push(format!(
                     "Failed to save {}: {}",
                     snapshot.file_path.display(),
@@ -85,34 +185,71 @@ impl CheckpointStorage {
             }
         }
 
+        // Count the new references into the shared pool
+        let hashes = self.checkpoint_ref_hashes(&paths, &checkpoint.id);
+        if let Err(e) = self.adjust_refcounts(&paths, &hashes, 1) {
+            log::warn!("Failed to update content reference counts: {}", e);
+        }
+
+        // Save checkpoint metadata, now that its on-disk cost is known
+        let mut checkpoint = checkpoint.clone();
+        let metadata_path = paths.checkpoint_metadata_file(&checkpoint.id);
+        checkpoint.metadata.snapshot_size = bytes_written;
+        let metadata_json = serde_json::to_string_pretty(&checkpoint)
+            .context("Failed to serialize checkpoint metadata")?;
+        checkpoint.metadata.snapshot_size += metadata_json.len() as u64;
+        let metadata_json = serde_json::to_string_pretty(&checkpoint)
+            .context("Failed to serialize checkpoint metadata")?;
+        fs::write(&metadata_path, metadata_json).context("Failed to write checkpoint metadata")?;
+
         // Update timeline
-        self.update_timeline_with_checkpoint(&paths.timeline_file, checkpoint, &file_snapshots)?;
+        self.update_timeline_with_checkpoint(&paths.timeline_file, &checkpoint, &file_snapshots)?;
 
         Ok(CheckpointResult {
-            checkpoint: checkpoint.clone(),
+            checkpoint,
             files_processed,
             warnings,
         })
     }
 
-    /// Save a single file snapshot
-    fn save_file_snapshot(&self, paths: &CheckpointPaths, snapshot: &FileSnapshot) -> Result<()> {
+    /// Hash of the nearest earlier version of a file among `ancestors`
+    fn previous_hash(
+        &self,
+        paths: &CheckpointPaths,
+        ancestors: &[String],
+        file_path: &Path,
+    ) -> Option<String> {
+        let ref_name = format!("{}.json", safe_ref_name(file_path));
+        for ancestor in ancestors {
+            let ref_path = paths.files_dir.join("refs").join(ancestor).join(&ref_name);
+            let Ok(ref_json) = fs::read_to_string(&ref_path) else {
+                continue;
+            };
+            let ref_metadata: serde_json::Value = serde_json::from_str(&ref_json).ok()?;
+            if ref_metadata["is_deleted"].as_bool().unwrap_or(false)
+                || ref_metadata["is_unrecoverable"].as_bool().unwrap_or(false)
+            {
+                return None;
+            }
+            return ref_metadata["hash"].as_str().map(str::to_string);
+        }
+        None
+    }
+
+    /// Save a single file snapshot, returning the bytes it added to disk
+    fn save_file_snapshot(
+        &self,
+        paths: &CheckpointPaths,
+        snapshot: &FileSnapshot,
+        base_hash: Option<&str>,
+    ) -> Result<u64> {
         // Use content-addressable storage: store files by their hash
-        // This prevents duplication of identical file content across checkpoints
-        let content_pool_dir = paths.files_dir.join("content_pool");
-        fs::create_dir_all(&content_pool_dir).context("Failed to create content pool directory")?;
-
-        // Store the actual content in the content pool
-        let content_file = content_pool_dir.join(&snapshot.hash);
-
-        // Only write the content if it doesn't already exist
-        if !content_file.exists() {
-            // Compress and save file content
-            let compressed_content =
-                encode_all(snapshot.content.as_bytes(), self.compression_level)
-                    .context("Failed to compress file content")?;
-            fs::write(&content_file, compressed_content)
-                .context("Failed to write file content to pool")?;
+        // This prevents duplication of identical file content across checkpoints and
+        // sessions; a new version of a file is stored as a delta against its predecessor
+        let mut bytes_written = 0;
+        if !snapshot.is_deleted && !snapshot.is_unrecoverable {
+            bytes_written +=
+                self.write_blob(paths, &snapshot.hash, &snapshot.content, base_hash)?;
         }
 
         // Create a reference in the checkpoint-specific directory
@@ -120,27 +257,16 @@ impl CheckpointStorage {
         fs::create_dir_all(&checkpoint_refs_dir)
             .context("Failed to create checkpoint refs directory")?;
 
-        // Save file metadata with reference to content
-        let ref_metadata = serde_json::json!({
-            "path": snapshot.file_path,
-            "hash": snapshot.hash,
-            "is_deleted": snapshot.is_deleted,
-            "permissions": snapshot.permissions,
-            "size": snapshot.size,
-        });
-
         // Use a sanitized filename for the reference
-        let safe_filename = snapshot
-            .file_path
-            .to_string_lossy()
-            .replace('/', "_")
-            .replace('\\', "_");
-        let ref_path = checkpoint_refs_dir.join(format!("{}.json", safe_filename));
+        let ref_path =
+            checkpoint_refs_dir.join(format!("{}.json", safe_ref_name(&snapshot.file_path)));
 
-        fs::write(&ref_path, serde_json::to_string_pretty(&ref_metadata)?)
-            .context("Failed to write file reference")?;
+        // Save file metadata with reference to content
+        let ref_json = serde_json::to_string_pretty(&ref_metadata(snapshot))?;
+        bytes_written += ref_json.len() as u64;
+        fs::write(&ref_path, ref_json).context("Failed to write file reference")?;
 
-        Ok(())
+        Ok(bytes_written)
     }
 
     /// Load a checkpoint from disk
@@ -153,29 +279,79 @@ impl CheckpointStorage {
         let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
 
         // Load checkpoint metadata
-        let metadata_path = paths.checkpoint_metadata_file(checkpoint_id);
-        let metadata_json =
-            fs::read_to_string(&metadata_path).context("Failed to read checkpoint metadata")?;
-        let checkpoint: Checkpoint =
-            serde_json::from_str(&metadata_json).context("Failed to parse checkpoint metadata")?;
+        let checkpoint = self.load_checkpoint_metadata(&paths, checkpoint_id)?;
 
         // Load messages
+        let messages = self.load_messages(&paths, checkpoint_id)?;
+
+        // Load file snapshots, from git for checkpoints made with the git backend
+        let file_snapshots = match &checkpoint.git_commit {
+            Some(commit) => self
+                .git_repository(&paths)?
+                .load_snapshots(commit, checkpoint_id)?,
+            None => self.load_file_snapshots(&paths, checkpoint_id)?,
+        };
+
+        Ok((checkpoint, file_snapshots, messages))
+    }
+
+    /// Load a checkpoint with every file in the project as of it, for restoring
+    pub fn load_checkpoint_tree(
+        &self,
+        project_id: &str,
+        session_id: &str,
+        checkpoint_id: &str,
+    ) -> Result<(Checkpoint, Vec<FileSnapshot>, String)> {
+        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
+        let checkpoint = self.load_checkpoint_metadata(&paths, checkpoint_id)?;
+        let messages = self.load_messages(&paths, checkpoint_id)?;
+        let file_snapshots = self.load_checkpoint_files(project_id, session_id, checkpoint_id)?;
+
+        Ok((checkpoint, file_snapshots, messages))
+    }
+
+    /// Every file in the project as of a checkpoint, not just those it recorded itself
+    pub fn load_checkpoint_files(
+        &self,
+        project_id: &str,
+        session_id: &str,
+        checkpoint_id: &str,
+    ) -> Result<Vec<FileSnapshot>> {
+        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
+        let timeline = self.load_timeline(&paths.timeline_file)?;
+        Ok(self
+            .load_file_state(&paths, &timeline, checkpoint_id)?
+            .into_values()
+            .collect())
+    }
+
+    /// Load the conversation saved with a checkpoint
+    fn load_messages(&self, paths: &CheckpointPaths, checkpoint_id: &str) -> Result<String> {
         let messages_path = paths.checkpoint_messages_file(checkpoint_id);
-        let compressed_messages =
-            fs::read(&messages_path).context("Failed to read compressed messages")?;
-        let messages = String::from_utf8(
+        let compressed_messages = self.unseal(
+            fs::read(&messages_path).context("Failed to read compressed messages")?,
+            &crypto::messages_context(checkpoint_id),
+        )?;
+        String::from_utf8(
             decode_all(&compressed_messages[..]).context("Failed to decompress messages")?,
         )
-        .context("Invalid UTF-8 in messages")?;
-
-        // Load file snapshots
-        let file_snapshots = self.load_file_snapshots(&paths, checkpoint_id)?;
+        .context("Invalid UTF-8 in messages")
+    }
 
-        Ok((checkpoint, file_snapshots, messages))
+    /// Load only a checkpoint's metadata
+    pub(super) fn load_checkpoint_metadata(
+        &self,
+        paths: &CheckpointPaths,
+        checkpoint_id: &str,
+    ) -> Result<Checkpoint> {
+        let metadata_path = paths.checkpoint_metadata_file(checkpoint_id);
+        let metadata_json =
+            fs::read_to_string(&metadata_path).context("Failed to read checkpoint metadata")?;
+        serde_json::from_str(&metadata_json).context("Failed to parse checkpoint metadata")
     }
 
     /// Load all file snapshots for a checkpoint
-    fn load_file_snapshots(
+    pub(super) fn load_file_snapshots(
         &self,
         paths: &CheckpointPaths,
         checkpoint_id: &str,
@@ -185,7 +361,6 @@ impl CheckpointStorage {
             return Ok(Vec::new());
         }
 
-        let content_pool_dir = paths.files_dir.join("content_pool");
         let mut snapshots = Vec::new();
 
         // Read all reference files
@@ -207,20 +382,20 @@ impl CheckpointStorage {
                 .as_str()
                 .ok_or_else(|| anyhow::anyhow!("Missing hash in reference"))?;
 
+            let is_deleted = ref_metadata["is_deleted"].as_bool().unwrap_or(false);
+            let mut is_unrecoverable = ref_metadata["is_unrecoverable"].as_bool().unwrap_or(false);
+
             // Load content from pool
-            let content_file = content_pool_dir.join(hash);
-            let content = if content_file.exists() {
-                let compressed_content =
-                    fs::read(&content_file).context("Failed to read file content from pool")?;
-                String::from_utf8(
-                    decode_all(&compressed_content[..])
-                        .context("Failed to decompress file content")?,
-                )
-                .context("Invalid UTF-8 in file content")?
+            let content_file = self.blob_path(paths, hash);
+            let content = if is_deleted || is_unrecoverable {
+                Vec::new()
+            } else if content_file.exists() {
+                self.read_blob(paths, hash)?
             } else {
                 // Handle missing content gracefully
                 log::warn!("Content file missing for hash: {}", hash);
-                String::new()
+                is_unrecoverable = true;
+                Vec::new()
             };
 
             snapshots.push(FileSnapshot {
@@ -228,9 +403,12 @@ impl CheckpointStorage {
                 file_path: PathBuf::from(ref_metadata["path"].as_str().unwrap_or("")),
                 content,
                 hash: hash.to_string(),
-                is_deleted: ref_metadata["is_deleted"].as_bool().unwrap_or(false),
+                is_deleted,
                 permissions: ref_metadata["permissions"].as_u64().map(|p| p as u32),
                 size: ref_metadata["size"].as_u64().unwrap_or(0),
+                // Refs written before kinds were recorded are all regular files
+                kind: serde_json::from_value(ref_metadata["kind"].clone()).unwrap_or_default(),
+                is_unrecoverable,
             });
         }
 
@@ -239,18 +417,12 @@ impl CheckpointStorage {
 
     /// Save timeline to disk
     pub fn save_timeline(&self, timeline_path: &Path, timeline: &SessionTimeline) -> Result<()> {
-        let timeline_json =
-            serde_json::to_string_pretty(timeline).context("Failed to serialize timeline")?;
-        fs::write(timeline_path, timeline_json).context("Failed to write timeline")?;
-        Ok(())
+        self.lock_timeline(timeline_path)?.write(timeline)
     }
 
     /// Load timeline from disk
     pub fn load_timeline(&self, timeline_path: &Path) -> Result<SessionTimeline> {
-        let timeline_json = fs::read_to_string(timeline_path).context("Failed to read timeline")?;
-        let timeline: SessionTimeline =
-            serde_json::from_str(&timeline_json).context("Failed to parse timeline")?;
-        Ok(timeline)
+        self.lock_timeline(timeline_path)?.read()
     }
 
     /// Update timeline with a new checkpoint
@@ -260,36 +432,34 @@ impl CheckpointStorage {
         checkpoint: &Checkpoint,
         file_snapshots: &[FileSnapshot],
     ) -> Result<()> {
-        let mut timeline = self.load_timeline(timeline_path)?;
-
         let new_node = TimelineNode {
             checkpoint: checkpoint.clone(),
             children: Vec::new(),
             file_snapshot_ids: file_snapshots.iter().map(|s| s.hash.clone()).collect(),
         };
 
-        // If this is the first checkpoint
-        if timeline.root_node.is_none() {
-            timeline.root_node = Some(new_node);
-            timeline.current_checkpoint_id = Some(checkpoint.id.clone());
-        } else if let Some(parent_id) = &checkpoint.parent_checkpoint_id {
-            // Check if parent exists before modifying
-            let parent_exists = timeline.find_checkpoint(parent_id).is_some();
-
-            if parent_exists {
-                if let Some(root) = &mut timeline.root_node {
-                    Self::add_child_to_node(root, parent_id, new_node)?;
-                    timeline.current_checkpoint_id = Some(checkpoint.id.clone());
+        self.update_timeline(timeline_path, |timeline| {
+            // If this is the first checkpoint
+            if timeline.root_node.is_none() {
+                timeline.root_node = Some(new_node);
+                timeline.current_checkpoint_id = Some(checkpoint.id.clone());
+            } else if let Some(parent_id) = &checkpoint.parent_checkpoint_id {
+                // Check if parent exists before modifying
+                let parent_exists = timeline.find_checkpoint(parent_id).is_some();
+
+                if parent_exists {
+                    if let Some(root) = &mut timeline.root_node {
+                        Self::add_child_to_node(root, parent_id, new_node)?;
+                        timeline.current_checkpoint_id = Some(checkpoint.id.clone());
+                    }
+                } else {
+                    anyhow::bail!("Parent checkpoint not found: {}", parent_id);
                 }
-            } else {
-                anyhow::bail!("Parent checkpoint not found: {}", parent_id);
             }
-        }
-
-        timeline.total_checkpoints += 1;
-        self.save_timeline(timeline_path, &timeline)?;
 
-        Ok(())
+            timeline.total_checkpoints += 1;
+            Ok(())
+        })
     }
 
     /// Recursively add a child node to the timeline tree
@@ -313,9 +483,9 @@ impl CheckpointStorage {
     }
 
     /// Calculate hash of file content
-    pub fn calculate_file_hash(content: &str) -> String {
+    pub fn calculate_file_hash(content: &[u8]) -> String {
         let mut hasher = Sha256::new();
-        hasher.update(content.as_bytes());
+        hasher.update(content);
         format!("{:x}", hasher.finalize())
     }
 
@@ -324,16 +494,9 @@ impl CheckpointStorage {
         Uuid::new_v4().to_string()
     }
 
-    /// Estimate storage size for a checkpoint
-    pub fn estimate_checkpoint_size(messages: &str, file_snapshots: &[FileSnapshot]) -> u64 {
-        let messages_size = messages.len() as u64;
-        let files_size: u64 = file_snapshots.iter().map(|s| s.content.len() as u64).sum();
-
-        // Estimate compressed size (typically 20-30% of original for text)
-        (messages_size + files_size) / 4
-    }
-
-    /// Clean up old checkpoints based on retention policy
+    /// Clean up old checkpoints, keeping only the `keep_count` most recent
+    ///
+    /// The current checkpoint is always kept.
     pub fn cleanup_old_checkpoints(
         &self,
         project_id: &str,
@@ -341,26 +504,28 @@ impl CheckpointStorage {
         keep_count: usize,
     ) -> Result<usize> {
         let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
-        let timeline = self.load_timeline(&paths.timeline_file)?;
+        let removed_count = self.update_timeline(&paths.timeline_file, |timeline| {
+            // Collect all checkpoint IDs in chronological order
+            let mut all_checkpoints = Vec::new();
+            if let Some(root) = &timeline.root_node {
+                Self::collect_checkpoints(root, &mut all_checkpoints);
+            }
 
-        // Collect all checkpoint IDs in chronological order
-        let mut all_checkpoints = Vec::new();
-        if let Some(root) = &timeline.root_node {
-            Self::collect_checkpoints(root, &mut all_checkpoints);
-        }
+            // Sort by timestamp (oldest first)
+            all_checkpoints.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
 
-        // Sort by timestamp (oldest first)
-        all_checkpoints.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+            // Keep only the most recent checkpoints
+            let to_remove = all_checkpoints.len().saturating_sub(keep_count);
+            let doomed: HashSet<String> = all_checkpoints
+                .into_iter()
+                .take(to_remove)
+                .map(|checkpoint| checkpoint.id)
+                .filter(|id| timeline.current_checkpoint_id.as_ref() != Some(id))
+                .collect();
 
-        // Keep only the most recent checkpoints
-        let to_remove = all_checkpoints.len().saturating_sub(keep_count);
-        let mut removed_count = 0;
-
-        for checkpoint in all_checkpoints.into_iter().take(to_remove) {
-            if self.remove_checkpoint(&paths, &checkpoint.id).is_ok() {
-                removed_count += 1;
-            }
-        }
+            self.prune_checkpoints(&paths, timeline, &doomed)?;
+            Ok(doomed.len())
+        })?;
 
         // Run garbage collection to clean up orphaned content
         if removed_count > 0 {
@@ -378,7 +543,7 @@ impl CheckpointStorage {
     }
 
     /// Collect all checkpoints from the tree in order
-    fn collect_checkpoints(node: &TimelineNode, checkpoints: &mut Vec<Checkpoint>) {
+    pub(super) fn collect_checkpoints(node: &TimelineNode, checkpoints: &mut Vec<Checkpoint>) {
         checkpoints.push(node.checkpoint.clone());
         for child in &node.children {
             Self::collect_checkpoints(child, checkpoints);
@@ -396,65 +561,366 @@ impl CheckpointStorage {
         // Remove file references for this checkpoint
         let refs_dir = paths.files_dir.join("refs").join(checkpoint_id);
         if refs_dir.exists() {
+            let hashes = self.checkpoint_ref_hashes(paths, checkpoint_id);
+            if let Err(e) = self.adjust_refcounts(paths, &hashes, -1) {
+                log::warn!("Failed to update content reference counts: {}", e);
+            }
             fs::remove_dir_all(&refs_dir).context("Failed to remove file references")?;
         }
 
         // Note: We don't remove content from the pool here as it might be
-        // referenced by other checkpoints. Use garbage_collect_content() for that.
+        // referenced by other checkpoints or sessions. Use garbage_collect_content() for that.
 
         Ok(())
     }
 
-    /// Garbage collect unreferenced content from the content pool
-    pub fn garbage_collect_content(&self, project_id: &str, session_id: &str) -> Result<usize> {
-        let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
-        let content_pool_dir = paths.files_dir.join("content_pool");
-        let refs_dir = paths.files_dir.join("refs");
+    /// Remove checkpoints from a timeline and delete their files
+    ///
+    /// Children of a removed checkpoint move up to its nearest surviving ancestor, and so
+    /// does the current checkpoint. The caller is responsible for saving the timeline.
+    pub(super) fn prune_checkpoints(
+        &self,
+        paths: &CheckpointPaths,
+        timeline: &mut SessionTimeline,
+        doomed: &HashSet<String>,
+    ) -> Result<()> {
+        if doomed.is_empty() {
+            return Ok(());
+        }
 
-        if !content_pool_dir.exists() {
-            return Ok(0);
+        let mut tree_parents = HashMap::new();
+        if let Some(root) = &timeline.root_node {
+            collect_tree_parents(root, None, &mut tree_parents);
         }
 
-        // Collect all referenced hashes
-        let mut referenced_hashes = std::collections::HashSet::new();
+        // Nearest surviving ancestor of each doomed checkpoint
+        let nearest_survivor: HashMap<String, Option<String>> = doomed
+            .iter()
+            .map(|id| {
+                let mut candidate = Some(id.clone());
+                while let Some(id) = candidate.clone().filter(|id| doomed.contains(id)) {
+                    candidate = tree_parents.get(&id).cloned().flatten();
+                }
+                (id.clone(), candidate)
+            })
+            .collect();
 
-        if refs_dir.exists() {
-            for checkpoint_entry in fs::read_dir(&refs_dir)? {
-                let checkpoint_dir = checkpoint_entry?.path();
-                if checkpoint_dir.is_dir() {
-                    for ref_entry in fs::read_dir(&checkpoint_dir)? {
-                        let ref_path = ref_entry?.path();
-                        if ref_path.extension().and_then(|e| e.to_str()) == Some("json") {
-                            if let Ok(ref_json) = fs::read_to_string(&ref_path) {
-                                if let Ok(ref_metadata) =
-                                    serde_json::from_str::<serde_json::Value>(&ref_json)
-                                {
-                                    if let Some(hash) = ref_metadata["hash"].as_str() {
-                                        referenced_hashes.insert(hash.to_string());
-                                    }
-                                }
+        // Native checkpoints only record what changed since their parent, so survivors take
+        // over what removed ancestors recorded before those go away
+        let mut checkpoints = Vec::new();
+        if let Some(root) = &timeline.root_node {
+            Self::collect_checkpoints(root, &mut checkpoints);
+        }
+        let is_git: HashMap<&str, bool> = checkpoints
+            .iter()
+            .map(|c| (c.id.as_str(), c.git_commit.is_some()))
+            .collect();
+        for (id, parent) in &tree_parents {
+            if doomed.contains(id) || is_git.get(id.as_str()) != Some(&false) {
+                continue;
+            }
+            let mut ancestor = parent.clone().filter(|p| doomed.contains(p));
+            while let Some(doomed_id) = ancestor {
+                if is_git.get(doomed_id.as_str()) == Some(&true) {
+                    let base = nearest_survivor.get(&doomed_id).cloned().flatten();
+                    self.inherit_tree(paths, timeline, &doomed_id, base.as_deref(), id)?;
+                    break;
+                }
+                self.inherit_refs(paths, &doomed_id, id)?;
+                ancestor = tree_parents
+                    .get(&doomed_id)
+                    .cloned()
+                    .flatten()
+                    .filter(|p| doomed.contains(p));
+            }
+        }
+
+        // Git-backed checkpoints also give up their hidden ref
+        if let Some(project_path) = &timeline.git_repository {
+            let git_refs: Vec<String> = checkpoints
+                .iter()
+                .filter(|c| doomed.contains(&c.id) && c.git_commit.is_some())
+                .map(|c| git::checkpoint_ref(&timeline.session_id, &c.id))
+                .collect();
+            if !git_refs.is_empty() {
+                match GitRepository::open(project_path) {
+                    Ok(repo) => {
+                        for git_ref in &git_refs {
+                            if let Err(e) = repo.delete_ref(git_ref) {
+                                log::warn!("Failed to delete {}: {}", git_ref, e);
                             }
                         }
                     }
+                    Err(e) => log::warn!("Failed to open checkpoint repository: {}", e),
                 }
             }
         }
 
-        // Remove unreferenced content
-        let mut removed_count = 0;
-        for entry in fs::read_dir(&content_pool_dir)? {
-            let content_file = entry?.path();
-            if content_file.is_file() {
-                if let Some(hash) = content_file.file_name().and_then(|n| n.to_str()) {
-                    if !referenced_hashes.contains(hash) {
-                        if fs::remove_file(&content_file).is_ok() {
-                            removed_count += 1;
-                        }
+        let mut reparented = Vec::new();
+        if let Some(root) = timeline.root_node.take() {
+            let mut survivors = prune_node(root, None, doomed, &nearest_survivor, &mut reparented);
+            survivors.sort_by_key(|s| s.checkpoint.timestamp);
+
+            // If the root went away, the oldest survivor takes its place
+            let mut survivors = survivors.into_iter();
+            if let Some(mut root) = survivors.next() {
+                for mut other in survivors {
+                    if other.checkpoint.git_commit.is_none() {
+                        self.delete_beyond(
+                            paths,
+                            timeline,
+                            &root.checkpoint,
+                            &other.checkpoint.id,
+                        )?;
                     }
+                    other.checkpoint.parent_checkpoint_id = Some(root.checkpoint.id.clone());
+                    reparented.push(other.checkpoint.clone());
+                    root.children.push(other);
                 }
+                timeline.root_node = Some(root);
             }
         }
 
-        Ok(removed_count)
+        for checkpoint in &reparented {
+            let metadata_json = serde_json::to_string_pretty(checkpoint)
+                .context("Failed to serialize checkpoint metadata")?;
+            fs::write(
+                paths.checkpoint_metadata_file(&checkpoint.id),
+                metadata_json,
+            )
+            .context("Failed to write checkpoint metadata")?;
+        }
+
+        if let Some(survivor) = timeline
+            .current_checkpoint_id
+            .as_ref()
+            .and_then(|id| nearest_survivor.get(id))
+        {
+            timeline.current_checkpoint_id = survivor.clone();
+        }
+
+        for id in doomed {
+            if let Err(e) = self.remove_checkpoint(paths, id) {
+                log::warn!("Failed to remove checkpoint {}: {}", id, e);
+            }
+        }
+
+        let mut remaining = Vec::new();
+        if let Some(root) = &timeline.root_node {
+            Self::collect_checkpoints(root, &mut remaining);
+        }
+        timeline.total_checkpoints = remaining.len();
+
+        Ok(())
+    }
+
+    /// Copy the refs of `from_id` that `to_id` doesn't record itself into `to_id`
+    fn inherit_refs(&self, paths: &CheckpointPaths, from_id: &str, to_id: &str) -> Result<()> {
+        let from_dir = paths.files_dir.join("refs").join(from_id);
+        let to_dir = paths.files_dir.join("refs").join(to_id);
+        let Ok(entries) = fs::read_dir(&from_dir) else {
+            return Ok(());
+        };
+
+        let mut hashes = Vec::new();
+        for entry in entries {
+            let ref_path = entry?.path();
+            let Some(name) = ref_path.file_name() else {
+                continue;
+            };
+            let target = to_dir.join(name);
+            if target.exists() || ref_path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            fs::create_dir_all(&to_dir).context("Failed to create checkpoint refs directory")?;
+            let ref_json =
+                fs::read_to_string(&ref_path).context("Failed to read file reference")?;
+            fs::write(&target, &ref_json).context("Failed to write file reference")?;
+
+            let ref_metadata: serde_json::Value = serde_json::from_str(&ref_json)?;
+            if !ref_metadata["is_deleted"].as_bool().unwrap_or(false) {
+                if let Some(hash) = ref_metadata["hash"].as_str().filter(|h| !h.is_empty()) {
+                    hashes.push(hash.to_string());
+                }
+            }
+        }
+
+        self.adjust_refcounts(paths, &hashes, 1)
+    }
+
+    /// Record as deleted in `to_id` every file of the parentless `root` it doesn't record
+    ///
+    /// Used when a former sibling of `root` moves below it; both already record their whole
+    /// tree, so the move must not add files of `root` to it.
+    fn delete_beyond(
+        &self,
+        paths: &CheckpointPaths,
+        timeline: &SessionTimeline,
+        root: &Checkpoint,
+        to_id: &str,
+    ) -> Result<()> {
+        let root_files = match &root.git_commit {
+            Some(commit) => git::timeline_repository(timeline)?.load_snapshots(commit, &root.id)?,
+            None => self.load_file_snapshots(paths, &root.id)?,
+        };
+        let to_dir = paths.files_dir.join("refs").join(to_id);
+
+        for snapshot in root_files.into_iter().filter(|s| !s.is_deleted) {
+            let ref_name = format!("{}.json", safe_ref_name(&snapshot.file_path));
+            if to_dir.join(ref_name).exists() {
+                continue;
+            }
+            let deletion = FileSnapshot {
+                checkpoint_id: to_id.to_string(),
+                content: Vec::new(),
+                hash: String::new(),
+                is_deleted: true,
+                permissions: None,
+                size: 0,
+                is_unrecoverable: false,
+                ..snapshot
+            };
+            self.save_file_snapshot(paths, &deletion, None)?;
+        }
+
+        Ok(())
+    }
+
+    /// Record in `to_id` the full tree of the git-backed `from_id`, relative to `base_id`
+    ///
+    /// Files the tree has are written for paths `to_id` doesn't record; files `base_id` has
+    /// that the tree doesn't are recorded as deleted.
+    fn inherit_tree(
+        &self,
+        paths: &CheckpointPaths,
+        timeline: &SessionTimeline,
+        from_id: &str,
+        base_id: Option<&str>,
+        to_id: &str,
+    ) -> Result<()> {
+        let tree = self.load_file_state(paths, timeline, from_id)?;
+        let base = match base_id {
+            Some(id) => self.load_file_state(paths, timeline, id)?,
+            None => BTreeMap::new(),
+        };
+        let to_dir = paths.files_dir.join("refs").join(to_id);
+        let recorded = |path: &Path| {
+            to_dir
+                .join(format!("{}.json", safe_ref_name(path)))
+                .exists()
+        };
+
+        let deletions: Vec<FileSnapshot> = base
+            .into_values()
+            .filter(|s| !tree.contains_key(&s.file_path))
+            .map(|s| FileSnapshot {
+                content: Vec::new(),
+                hash: String::new(),
+                is_deleted: true,
+                permissions: None,
+                size: 0,
+                is_unrecoverable: false,
+                ..s
+            })
+            .collect();
+        let mut hashes = Vec::new();
+        for mut snapshot in tree.into_values().chain(deletions) {
+            if recorded(&snapshot.file_path) {
+                continue;
+            }
+            snapshot.checkpoint_id = to_id.to_string();
+            self.save_file_snapshot(paths, &snapshot, None)?;
+            if !snapshot.is_deleted && !snapshot.is_unrecoverable {
+                hashes.push(snapshot.hash);
+            }
+        }
+
+        self.adjust_refcounts(paths, &hashes, 1)
+    }
+}
+
+/// File name, without extension, of the ref recording `file_path` in a checkpoint
+pub(super) fn safe_ref_name(file_path: &Path) -> String {
+    file_path
+        .to_string_lossy()
+        .replace('/', "_")
+        .replace('\\', "_")
+}
+
+/// The ref a checkpoint stores for one of its file snapshots, pointing at its content
+pub(super) fn ref_metadata(snapshot: &FileSnapshot) -> serde_json::Value {
+    serde_json::json!({
+        "path": snapshot.file_path,
+        "hash": snapshot.hash,
+        "is_deleted": snapshot.is_deleted,
+        "permissions": snapshot.permissions,
+        "size": snapshot.size,
+        "kind": snapshot.kind,
+        "is_unrecoverable": snapshot.is_unrecoverable,
+    })
+}
+
+/// Record the tree parent of every node below `node`
+pub(super) fn collect_tree_parents(
+    node: &TimelineNode,
+    parent_id: Option<&str>,
+    parents: &mut HashMap<String, Option<String>>,
+) {
+    parents.insert(node.checkpoint.id.clone(), parent_id.map(str::to_string));
+    for child in &node.children {
+        collect_tree_parents(child, Some(&node.checkpoint.id), parents);
+    }
+}
+
+/// Remove doomed nodes from a subtree, returning what takes the subtree's place
+fn prune_node(
+    mut node: TimelineNode,
+    parent_id: Option<&str>,
+    doomed: &HashSet<String>,
+    nearest_survivor: &HashMap<String, Option<String>>,
+    reparented: &mut Vec<Checkpoint>,
+) -> Vec<TimelineNode> {
+    let keep = !doomed.contains(&node.checkpoint.id);
+    let child_parent = if keep {
+        Some(node.checkpoint.id.clone())
+    } else {
+        parent_id.map(str::to_string)
+    };
+
+    let mut children = Vec::new();
+    for child in std::mem::take(&mut node.children) {
+        children.extend(prune_node(
+            child,
+            child_parent.as_deref(),
+            doomed,
+            nearest_survivor,
+            reparented,
+        ));
+    }
+
+    if !keep {
+        return children;
+    }
+
+    let mut changed = false;
+    if node.checkpoint.parent_checkpoint_id.as_deref() != parent_id {
+        node.checkpoint.parent_checkpoint_id = parent_id.map(str::to_string);
+        changed = true;
+    }
+    // A merge that lost its second parent points at that parent's nearest survivor instead
+    let merge_survivor = node
+        .checkpoint
+        .merge_parent_id
+        .as_ref()
+        .and_then(|id| nearest_survivor.get(id));
+    if let Some(survivor) = merge_survivor {
+        node.checkpoint.merge_parent_id = survivor.clone();
+        changed = true;
+    }
+    if changed {
+        reparented.push(node.checkpoint.clone());
     }
+    node.children = children;
+    vec![node]
 }
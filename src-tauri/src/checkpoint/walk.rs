@@ -0,0 +1,331 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::FileKind;
+
+/// Project-level ignore file, using the same syntax as `.gitignore`
+pub const OPCODE_IGNORE_FILE: &str = ".opcodeignore";
+
+/// Ignore files honored in every directory of a project, lowest precedence first
+pub const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", OPCODE_IGNORE_FILE];
+
+/// Files larger than this are excluded from checkpoints unless configured otherwise
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Size cap assumed for timelines written before the cap was configurable
+pub fn default_max_file_size() -> u64 {
+    DEFAULT_MAX_FILE_SIZE
+}
+
+/// Mode bits a checkpoint records and restores: permissions plus setuid, setgid and sticky
+pub const PERMISSION_BITS: u32 = 0o7777;
+
+/// Files discovered in a project directory
+#[derive(Debug, Default)]
+pub struct ProjectFiles {
+    /// Files and symbolic links eligible for checkpointing, relative to the project root
+    pub files: Vec<PathBuf>,
+    /// Directories with nothing in them to checkpoint, relative to the project root
+    pub empty_dirs: Vec<PathBuf>,
+    /// Files skipped for exceeding the size cap, with their size in bytes
+    pub oversized: Vec<(PathBuf, u64)>,
+    /// The ignore rules the walk applied
+    pub ignore: IgnoreMatcher,
+}
+
+/// Recursively collect files in the project, relative to the project root
+///
+/// Hidden directories such as `.git` are skipped, and `.gitignore`, `.ignore` and
+/// `.opcodeignore` rules are honored whether or not the project is a git repository.
+/// Symbolic links are collected as links and never followed.
+pub fn collect_project_files(base: &Path, max_file_size: u64) -> ProjectFiles {
+    let mut project_files = ProjectFiles {
+        ignore: IgnoreMatcher::new(base),
+        ..ProjectFiles::default()
+    };
+    let mut dirs = Vec::new();
+    let mut non_empty_dirs = HashSet::new();
+    for result in project_walker(base) {
+        let entry = match result {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Failed to walk project directory: {}", e);
+                continue;
+            }
+        };
+        if entry.file_type().is_some_and(|t| t.is_dir()) {
+            project_files
+                .ignore
+                .add_dir(entry.path(), entry.depth() == 0);
+        }
+        if entry.depth() == 0 {
+            continue;
+        }
+
+        // Compute relative path from project root
+        let rel = match entry.path().strip_prefix(base) {
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) => continue,
+        };
+        if let Some(parent) = rel.parent() {
+            non_empty_dirs.insert(parent.to_path_buf());
+        }
+
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            dirs.push(rel);
+        } else if file_type.is_symlink() {
+            project_files.files.push(rel);
+        } else if file_type.is_file() {
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            if size > max_file_size {
+                project_files.oversized.push((rel, size));
+            } else {
+                project_files.files.push(rel);
+            }
+        }
+    }
+
+    project_files.empty_dirs = dirs
+        .into_iter()
+        .filter(|dir| !non_empty_dirs.contains(dir))
+        .collect();
+
+    project_files
+}
+
+/// Walk a project the way checkpoints see it
+fn project_walker(base: &Path) -> ignore::Walk {
+    WalkBuilder::new(base)
+        .hidden(false)
+        .git_ignore(true)
+        .git_exclude(true)
+        .ignore(true)
+        .require_git(false)
+        .add_custom_ignore_filename(OPCODE_IGNORE_FILE)
+        .filter_entry(|entry| {
+            // Skip hidden directories like .git
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            let is_hidden = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with('.'));
+            entry.depth() == 0 || !(is_dir && is_hidden)
+        })
+        .build()
+}
+
+/// A project entry as a checkpoint records it
+#[derive(Debug)]
+pub struct EntryContent {
+    /// Kind of entry found on disk
+    pub kind: FileKind,
+    /// File bytes, the link target for a symbolic link, or nothing for a directory
+    pub content: Vec<u8>,
+    /// Unix permission bits; symbolic links have none of their own
+    pub permissions: Option<u32>,
+}
+
+/// Read a project entry without following symbolic links
+pub fn read_entry(path: &Path) -> io::Result<EntryContent> {
+    let metadata = fs::symlink_metadata(path)?;
+    let kind = kind_of(&metadata.file_type());
+    let content = match kind {
+        FileKind::File => fs::read(path)?,
+        FileKind::Symlink => link_target_bytes(&fs::read_link(path)?),
+        FileKind::Directory => Vec::new(),
+    };
+    let permissions = match kind {
+        FileKind::Symlink => None,
+        _ => permission_bits(&metadata),
+    };
+
+    Ok(EntryContent {
+        kind,
+        content,
+        permissions,
+    })
+}
+
+/// Kind of entry a file type describes
+pub fn kind_of(file_type: &fs::FileType) -> FileKind {
+    if file_type.is_symlink() {
+        FileKind::Symlink
+    } else if file_type.is_dir() {
+        FileKind::Directory
+    } else {
+        FileKind::File
+    }
+}
+
+/// Unix permission bits of an entry, on platforms that have them
+pub fn permission_bits(metadata: &fs::Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode() & PERMISSION_BITS)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+/// Remove a file, symbolic link or empty directory without following links
+pub fn remove_entry(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Create a symbolic link at `path` pointing at a target recorded by `read_entry`
+pub fn create_symlink(target: &[u8], path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(target), path)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (target, path);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "symbolic links can't be restored on this platform",
+        ))
+    }
+}
+
+fn link_target_bytes(target: &Path) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        target.as_os_str().as_bytes().to_vec()
+    }
+    #[cfg(not(unix))]
+    {
+        target.to_string_lossy().into_owned().into_bytes()
+    }
+}
+
+/// Warning reported when a file is left out of a checkpoint for its size
+pub fn oversized_warning(path: &Path, size: u64, max_file_size: u64) -> String {
+    format!(
+        "Skipped {} ({} bytes): exceeds checkpoint file size limit of {} bytes",
+        path.display(),
+        size,
+        max_file_size
+    )
+}
+
+/// Ignore rules from every ignore file in a project, applied like the project walk applies them
+///
+/// Each directory's rules cover what lies below it and take precedence over its parents',
+/// and nothing below an ignored directory can be brought back.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreMatcher {
+    base: PathBuf,
+    /// One matcher per directory with ignore files, rooted there; parents before children
+    matchers: Vec<Gitignore>,
+}
+
+impl IgnoreMatcher {
+    fn new(base: &Path) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            matchers: Vec::new(),
+        }
+    }
+
+    /// Add the rules of the ignore files in `dir`
+    fn add_dir(&mut self, dir: &Path, is_root: bool) {
+        let mut ignore_files: Vec<PathBuf> = Vec::new();
+        if is_root {
+            ignore_files.push(dir.join(".git").join("info").join("exclude"));
+        }
+        ignore_files.extend(IGNORE_FILES.iter().map(|name| dir.join(name)));
+        ignore_files.retain(|file| file.is_file());
+        if ignore_files.is_empty() {
+            return;
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+        for ignore_file in &ignore_files {
+            if let Some(e) = builder.add(ignore_file) {
+                log::warn!("Failed to parse {}: {}", ignore_file.display(), e);
+            }
+        }
+        match builder.build() {
+            Ok(matcher) => self.matchers.push(matcher),
+            Err(e) => log::warn!("Failed to build ignore rules for {}: {}", dir.display(), e),
+        }
+    }
+
+    /// Whether the rules ignore a path relative to the project root, or any of its parents
+    pub fn is_ignored(&self, rel_path: &Path, is_dir: bool) -> bool {
+        let path = self.base.join(rel_path);
+        path.ancestors()
+            .take_while(|candidate| {
+                candidate.starts_with(&self.base) && *candidate != self.base.as_path()
+            })
+            .any(|candidate| self.ignores(candidate, is_dir || candidate != path.as_path()))
+    }
+
+    /// The verdict of the deepest rules that have one on a single path
+    fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        self.matchers
+            .iter()
+            .rev()
+            .filter(|matcher| path.starts_with(matcher.path()) && path != matcher.path())
+            .map(|matcher| matcher.matched(path, is_dir))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore())
+    }
+}
+
+/// Build a matcher for the ignore files throughout the project
+///
+/// Used where a full walk isn't practical, such as filtering file watcher events.
+pub fn build_ignore_matcher(base: &Path) -> IgnoreMatcher {
+    let mut matcher = IgnoreMatcher::new(base);
+    for entry in project_walker(base).flatten() {
+        if entry.file_type().is_some_and(|t| t.is_dir()) {
+            matcher.add_dir(entry.path(), entry.depth() == 0);
+        }
+    }
+    matcher
+}
+
+/// Whether a path's file name marks it as an ignore file
+pub fn is_ignore_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| IGNORE_FILES.contains(&name))
+}
+
+/// Whether a path relative to the project root is excluded from checkpoints
+///
+/// Paths inside hidden directories and paths matched by `matcher` are excluded.
+pub fn is_excluded(rel_path: &Path, matcher: &IgnoreMatcher, is_dir: bool) -> bool {
+    let mut components = rel_path.components().peekable();
+    while let Some(component) = components.next() {
+        let is_last = components.peek().is_none();
+        let hidden = component
+            .as_os_str()
+            .to_str()
+            .is_some_and(|name| name.starts_with('.'));
+        if hidden && (!is_last || is_dir) {
+            return true;
+        }
+    }
+
+    matcher.is_ignored(rel_path, is_dir)
+}
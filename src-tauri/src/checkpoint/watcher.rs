@@ -0,0 +1,148 @@
+use anyhow::Result;
+use chrono::Utc;
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use super::{walk, FileKind, FileState, FileTracker};
+
+/// How long the watcher waits for a burst of events on a path to settle
+pub const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches a project directory and marks the exact files that change in the file tracker
+///
+/// Tracked files that change are flagged as modified, and marked as gone once removed; files
+/// that appear are added as existing entries of their real kind, with no hash, so they are
+/// snapshotted at the next checkpoint. Hashes are left alone so external edits can still be
+/// detected before a restore. Watching stops when the value is dropped.
+pub struct ProjectWatcher {
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+impl ProjectWatcher {
+    /// Start watching `project_path` recursively
+    pub fn start(
+        project_path: PathBuf,
+        file_tracker: Arc<RwLock<FileTracker>>,
+        max_file_size: u64,
+    ) -> Result<Self> {
+        let mut matcher = walk::build_ignore_matcher(&project_path);
+        let base = project_path.clone();
+
+        let mut debouncer =
+            new_debouncer(DEBOUNCE_INTERVAL, move |result: DebounceEventResult| {
+                let events = match result {
+                    Ok(events) => events,
+                    Err(e) => {
+                        log::warn!("File watcher error: {}", e);
+                        return;
+                    }
+                };
+
+                // Pick up edited ignore rules before judging the paths that changed with them
+                if events.iter().any(|event| walk::is_ignore_file(&event.path)) {
+                    matcher = walk::build_ignore_matcher(&base);
+                }
+
+                // Runs on the debouncer's own thread, outside the async runtime
+                let mut tracker = file_tracker.blocking_write();
+                for event in events {
+                    let rel_path = match event.path.strip_prefix(&base) {
+                        Ok(rel) if !rel.as_os_str().is_empty() => rel.to_path_buf(),
+                        _ => continue,
+                    };
+                    // Links to directories are recorded like files, so don't follow them
+                    let is_dir = std::fs::symlink_metadata(&event.path).is_ok_and(|m| m.is_dir());
+                    if is_dir {
+                        continue;
+                    }
+                    if walk::is_excluded(&rel_path, &matcher, false) {
+                        // Ignored files are no longer part of checkpoints
+                        tracker.tracked_files.remove(&rel_path);
+                        continue;
+                    }
+                    record_change(&mut tracker, &event.path, rel_path, max_file_size);
+                }
+            })?;
+
+        debouncer
+            .watcher()
+            .watch(&project_path, RecursiveMode::Recursive)?;
+
+        log::info!("Watching {} for file changes", project_path.display());
+
+        Ok(Self {
+            _debouncer: debouncer,
+        })
+    }
+}
+
+/// Mark a changed path in the tracker
+fn record_change(
+    tracker: &mut FileTracker,
+    full_path: &Path,
+    rel_path: PathBuf,
+    max_file_size: u64,
+) {
+    let now = Utc::now();
+
+    if let Some(state) = tracker.tracked_files.get_mut(&rel_path) {
+        state.is_modified = true;
+        state.last_modified = now;
+        state.exists = std::fs::symlink_metadata(full_path).is_ok();
+        return;
+    }
+
+    // Untracked paths only matter if they still exist and are small enough to snapshot
+    let Ok(metadata) = std::fs::symlink_metadata(full_path) else {
+        return;
+    };
+    if !(metadata.is_symlink() || (metadata.is_file() && metadata.len() <= max_file_size)) {
+        return;
+    }
+    let kind = walk::kind_of(&metadata.file_type());
+    tracker.tracked_files.insert(
+        rel_path,
+        FileState {
+            last_hash: String::new(),
+            is_modified: true,
+            last_modified: now,
+            exists: true,
+            kind,
+            permissions: match kind {
+                FileKind::Symlink => None,
+                _ => walk::permission_bits(&metadata),
+            },
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_record_change_marks_removed_files_as_gone() {
+        let temp_dir = TempDir::new().unwrap();
+        let full_path = temp_dir.path().join("a.txt");
+        let mut tracker = FileTracker {
+            tracked_files: HashMap::new(),
+            has_baseline: true,
+        };
+
+        std::fs::write(&full_path, "a").unwrap();
+        record_change(&mut tracker, &full_path, PathBuf::from("a.txt"), u64::MAX);
+        assert!(tracker.tracked_files[Path::new("a.txt")].exists);
+
+        std::fs::remove_file(&full_path).unwrap();
+        record_change(&mut tracker, &full_path, PathBuf::from("a.txt"), u64::MAX);
+        let state = &tracker.tracked_files[Path::new("a.txt")];
+        assert!(!state.exists);
+        assert!(state.is_modified);
+    }
+}
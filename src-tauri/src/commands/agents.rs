@@ -35,6 +35,18 @@ pub struct Agent {
     pub hooks: Option<String>, // JSON string of hooks configuration
     pub created_at: String,
     pub updated_at: String,
+    #[serde(flatten)]
+    pub limits: AgentLimits,
+}
+
+/// Resource limits for an agent's runs; `None` leaves a resource unlimited
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AgentLimits {
+    pub timeout_secs: Option<i64>,      // Wall-clock time before the run is stopped
+    pub max_output_tokens: Option<i64>, // Tokens the model may generate over the run
+    pub max_memory_mb: Option<i64>,     // Heap and other private data of the process (RLIMIT_DATA)
+    pub max_cpu_secs: Option<i64>,      // CPU time of the process (RLIMIT_CPU)
+    pub max_open_files: Option<i64>,    // Open file descriptors (RLIMIT_NOFILE)
 }
 
 /// Represents an agent execution run
@@ -48,11 +60,24 @@ pub struct AgentRun {
     pub model: String,
     pub project_path: String,
     pub session_id: String, // UUID session ID from Claude Code
-    pub status: String,     // 'pending', 'running', 'completed', 'failed', 'cancelled'
+    pub status: String,     // 'pending', 'running', 'completed', 'failed', 'cancelled', 'timed_out', 'limit_exceeded'
     pub pid: Option<u32>,
     pub process_started_at: Option<String>,
     pub created_at: String,
     pub completed_at: Option<String>,
+    pub failure_reason: Option<String>, // Why a run failed or was stopped, if known
+}
+
+/// A checkpoint taken automatically during an agent run
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AgentRunCheckpoint {
+    pub id: i64,
+    pub run_id: i64,
+    pub checkpoint_id: String,
+    pub session_id: String,
+    pub project_id: String,
+    pub trigger: String, // 'run_start' or 'tool_use'
+    pub created_at: String,
 }
 
 /// Represents runtime metrics calculated from JSONL
@@ -166,6 +191,174 @@ impl AgentRunMetrics {
     }
 }
 
+/// How much CPU time past its soft limit a process gets to handle SIGXCPU before SIGKILL
+#[cfg(unix)]
+const CPU_LIMIT_KILL_AFTER_SECS: u64 = 5;
+
+impl AgentLimits {
+    /// Read the limit columns, which follow the agent's other columns from `first` on
+    fn from_row(row: &rusqlite::Row, first: usize) -> SqliteResult<Self> {
+        Ok(Self {
+            timeout_secs: row.get(first)?,
+            max_output_tokens: row.get(first + 1)?,
+            max_memory_mb: row.get(first + 2)?,
+            max_cpu_secs: row.get(first + 3)?,
+            max_open_files: row.get(first + 4)?,
+        })
+    }
+
+    /// The limits paired with their column names
+    fn columns(&self) -> [(&'static str, Option<i64>); 5] {
+        [
+            ("timeout_secs", self.timeout_secs),
+            ("max_output_tokens", self.max_output_tokens),
+            ("max_memory_mb", self.max_memory_mb),
+            ("max_cpu_secs", self.max_cpu_secs),
+            ("max_open_files", self.max_open_files),
+        ]
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (name, value) in self.columns() {
+            if matches!(value, Some(v) if v <= 0) {
+                return Err(format!("{} must be a positive number", name));
+            }
+        }
+        Ok(())
+    }
+
+    fn timeout(&self) -> Option<std::time::Duration> {
+        self.timeout_secs
+            .map(|secs| std::time::Duration::from_secs(secs as u64))
+    }
+
+    fn has_rlimits(&self) -> bool {
+        self.max_memory_mb.is_some() || self.max_cpu_secs.is_some() || self.max_open_files.is_some()
+    }
+
+    /// Set the rlimits of the process `cmd` spawns; anything it starts inherits them
+    #[cfg(unix)]
+    fn apply_rlimits(&self, cmd: &mut Command) {
+        let mut rlimits = Vec::new();
+        if let Some(mb) = self.max_memory_mb {
+            // Data rather than address space, which runtimes like V8 reserve far beyond
+            // what they use
+            let bytes = (mb as libc::rlim_t).saturating_mul(1024 * 1024);
+            rlimits.push((libc::RLIMIT_DATA, bytes, bytes));
+        }
+        if let Some(secs) = self.max_cpu_secs {
+            // SIGXCPU at the soft limit, SIGKILL if the process carries on regardless
+            let secs = secs as libc::rlim_t;
+            rlimits.push((
+                libc::RLIMIT_CPU,
+                secs,
+                secs.saturating_add(CPU_LIMIT_KILL_AFTER_SECS as libc::rlim_t),
+            ));
+        }
+        if let Some(files) = self.max_open_files {
+            let files = files as libc::rlim_t;
+            rlimits.push((libc::RLIMIT_NOFILE, files, files));
+        }
+        if rlimits.is_empty() {
+            return;
+        }
+
+        // Safety: the hook runs between fork and exec and only makes async-signal-safe
+        // system calls on memory allocated before the fork
+        unsafe {
+            cmd.pre_exec(move || {
+                for &(resource, soft, hard) in &rlimits {
+                    let mut limit = libc::rlimit {
+                        rlim_cur: 0,
+                        rlim_max: hard,
+                    };
+                    // Without privileges the hard limit can only be lowered
+                    if libc::getrlimit(resource, &mut limit) == 0 {
+                        limit.rlim_max = limit.rlim_max.min(hard);
+                    }
+                    limit.rlim_cur = soft.min(limit.rlim_max);
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Why a process that exited with `status` was stopped by its CPU limit, if it was
+    ///
+    /// SIGKILL also comes from our own kill escalation and the OOM killer, so it only counts
+    /// when the process had used its CPU time; where that can't be read, only SIGXCPU does.
+    #[cfg(unix)]
+    fn cpu_limit_exit(
+        &self,
+        status: &std::process::ExitStatus,
+        cpu_time: Option<std::time::Duration>,
+    ) -> Option<String> {
+        use std::os::unix::process::ExitStatusExt;
+        let secs = self.max_cpu_secs?;
+        let used_up = cpu_time.is_some_and(|cpu| cpu.as_secs() >= secs as u64);
+        match status.signal() {
+            Some(libc::SIGXCPU) => {}
+            Some(libc::SIGKILL) if used_up => {}
+            _ => return None,
+        }
+        Some(format!("Process used up its {}s of CPU time", secs))
+    }
+
+    #[cfg(not(unix))]
+    fn cpu_limit_exit(
+        &self,
+        _status: &std::process::ExitStatus,
+        _cpu_time: Option<std::time::Duration>,
+    ) -> Option<String> {
+        None
+    }
+}
+
+/// A limit that stopped an agent run
+#[derive(Debug, Clone)]
+struct LimitTrip {
+    status: &'static str, // 'timed_out' or 'limit_exceeded'
+    reason: String,
+}
+
+/// Record why a run is being stopped; returns false if another limit already stopped it
+fn record_limit_trip(
+    tripped: &Mutex<Option<LimitTrip>>,
+    status: &'static str,
+    reason: String,
+) -> bool {
+    match tripped.lock() {
+        Ok(mut tripped) if tripped.is_none() => {
+            warn!("Stopping agent run: {}", reason);
+            *tripped = Some(LimitTrip { status, reason });
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The message ID and output tokens reported by an assistant stream-json message
+///
+/// A message streamed in several parts repeats its usage on each, so counts are keyed by ID.
+fn assistant_output_tokens(json: &JsonValue) -> Option<(String, i64)> {
+    if json.get("type").and_then(|t| t.as_str()) != Some("assistant") {
+        return None;
+    }
+    let message = json.get("message")?;
+    let tokens = message
+        .get("usage")
+        .and_then(|u| u.get("output_tokens"))
+        .and_then(|t| t.as_i64())?;
+    let id = message
+        .get("id")
+        .and_then(|id| id.as_str())
+        .unwrap_or_default();
+    Some((id.to_string(), tokens))
+}
+
 /// Read JSONL content from a session file
 pub async fn read_session_jsonl(session_id: &str, project_path: &str) -> Result<String, String> {
     let claude_dir = dirs::home_dir()
@@ -235,7 +428,12 @@ pub fn init_database(app: &AppHandle) -> SqliteResult<Connection> {
             enable_network BOOLEAN NOT NULL DEFAULT 0,
             hooks TEXT,
             created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
-            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            timeout_secs INTEGER,
+            max_output_tokens INTEGER,
+            max_memory_mb INTEGER,
+            max_cpu_secs INTEGER,
+            max_open_files INTEGER
         )",
         [],
     )?;
@@ -259,6 +457,11 @@ pub fn init_database(app: &AppHandle) -> SqliteResult<Connection> {
         "ALTER TABLE agents ADD COLUMN enable_network BOOLEAN DEFAULT 0",
         [],
     );
+    let _ = conn.execute("ALTER TABLE agents ADD COLUMN timeout_secs INTEGER", []);
+    let _ = conn.execute("ALTER TABLE agents ADD COLUMN max_output_tokens INTEGER", []);
+    let _ = conn.execute("ALTER TABLE agents ADD COLUMN max_memory_mb INTEGER", []);
+    let _ = conn.execute("ALTER TABLE agents ADD COLUMN max_cpu_secs INTEGER", []);
+    let _ = conn.execute("ALTER TABLE agents ADD COLUMN max_open_files INTEGER", []);
 
     // Create agent_runs table
     conn.execute(
@@ -276,6 +479,7 @@ pub fn init_database(app: &AppHandle) -> SqliteResult<Connection> {
             process_started_at TEXT,
             created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
             completed_at TEXT,
+            failure_reason TEXT,
             FOREIGN KEY (agent_id) REFERENCES agents(id) ON DELETE CASCADE
         )",
         [],
@@ -292,6 +496,7 @@ pub fn init_database(app: &AppHandle) -> SqliteResult<Connection> {
         "ALTER TABLE agent_runs ADD COLUMN process_started_at TEXT",
         [],
     );
+    let _ = conn.execute("ALTER TABLE agent_runs ADD COLUMN failure_reason TEXT", []);
 
     // Drop old columns that are no longer needed (data is now read from JSONL files)
     // Note: SQLite doesn't support DROP COLUMN, so we'll ignore errors for existing columns
@@ -306,6 +511,42 @@ pub fn init_database(app: &AppHandle) -> SqliteResult<Connection> {
         [],
     );
 
+    // Checkpoints taken automatically while an agent runs
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS agent_run_checkpoints (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            run_id INTEGER NOT NULL,
+            checkpoint_id TEXT NOT NULL,
+            session_id TEXT NOT NULL,
+            project_id TEXT NOT NULL,
+            trigger TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (run_id) REFERENCES agent_runs(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Processes the registry tracks, so they can be reattached after a restart
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS process_registry (
+            run_id INTEGER PRIMARY KEY,
+            process_type TEXT NOT NULL,
+            pid INTEGER NOT NULL,
+            started_at TEXT NOT NULL,
+            project_path TEXT NOT NULL,
+            task TEXT NOT NULL,
+            model TEXT NOT NULL,
+            session_id TEXT,
+            output_file TEXT,
+            output_offset INTEGER
+        )",
+        [],
+    )?;
+    let _ = conn.execute(
+        "ALTER TABLE process_registry ADD COLUMN output_offset INTEGER",
+        [],
+    );
+
     // Create trigger to update the updated_at timestamp
     conn.execute(
         "CREATE TRIGGER IF NOT EXISTS update_agent_timestamp 
@@ -349,7 +590,7 @@ pub async fn list_agents(db: State<'_, AgentDb>) -> Result<Vec<Agent>, String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
 
     let mut stmt = conn
-        .prepare("SELECT id, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, created_at, updated_at FROM agents ORDER BY created_at DESC")
+        .prepare("SELECT id, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, created_at, updated_at, timeout_secs, max_output_tokens, max_memory_mb, max_cpu_secs, max_open_files FROM agents ORDER BY created_at DESC")
         .map_err(|e| e.to_string())?;
 
     let agents = stmt
@@ -369,6 +610,7 @@ pub async fn list_agents(db: State<'_, AgentDb>) -> Result<Vec<Agent>, String> {
                 hooks: row.get(9)?,
                 created_at: row.get(10)?,
                 updated_at: row.get(11)?,
+                limits: AgentLimits::from_row(row, 12)?,
             })
         })
         .map_err(|e| e.to_string())?
@@ -391,7 +633,10 @@ pub async fn create_agent(
     enable_file_write: Option<bool>,
     enable_network: Option<bool>,
     hooks: Option<String>,
+    limits: Option<AgentLimits>,
 ) -> Result<Agent, String> {
+    let limits = limits.unwrap_or_default();
+    limits.validate()?;
     let conn = db.0.lock().map_err(|e| e.to_string())?;
     let model = model.unwrap_or_else(|| "sonnet".to_string());
     let enable_file_read = enable_file_read.unwrap_or(true);
@@ -399,8 +644,23 @@ pub async fn create_agent(
     let enable_network = enable_network.unwrap_or(false);
 
     conn.execute(
-        "INSERT INTO agents (name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-        params![name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks],
+        "INSERT INTO agents (name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, timeout_secs, max_output_tokens, max_memory_mb, max_cpu_secs, max_open_files) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![
+            name,
+            icon,
+            system_prompt,
+            default_task,
+            model,
+            enable_file_read,
+            enable_file_write,
+            enable_network,
+            hooks,
+            limits.timeout_secs,
+            limits.max_output_tokens,
+            limits.max_memory_mb,
+            limits.max_cpu_secs,
+            limits.max_open_files
+        ],
     )
     .map_err(|e| e.to_string())?;
 
@@ -409,7 +669,7 @@ pub async fn create_agent(
     // Fetch the created agent
     let agent = conn
         .query_row(
-            "SELECT id, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, created_at, updated_at FROM agents WHERE id = ?1",
+            "SELECT id, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, created_at, updated_at, timeout_secs, max_output_tokens, max_memory_mb, max_cpu_secs, max_open_files FROM agents WHERE id = ?1",
             params![id],
             |row| {
                 Ok(Agent {
@@ -425,6 +685,7 @@ pub async fn create_agent(
                     hooks: row.get(9)?,
                     created_at: row.get(10)?,
                     updated_at: row.get(11)?,
+                    limits: AgentLimits::from_row(row, 12)?,
                 })
             },
         )
@@ -447,7 +708,11 @@ pub async fn update_agent(
     enable_file_write: Option<bool>,
     enable_network: Option<bool>,
     hooks: Option<String>,
+    limits: Option<AgentLimits>, // Replaces all limits when given
 ) -> Result<Agent, String> {
+    if let Some(limits) = &limits {
+        limits.validate()?;
+    }
     let conn = db.0.lock().map_err(|e| e.to_string())?;
     let model = model.unwrap_or_else(|| "sonnet".to_string());
 
@@ -480,6 +745,13 @@ pub async fn update_agent(
         query.push_str(&format!(", enable_network = ?{}", param_count));
         params_vec.push(Box::new(en));
     }
+    if let Some(limits) = limits {
+        for (column, value) in limits.columns() {
+            param_count += 1;
+            query.push_str(&format!(", {} = ?{}", column, param_count));
+            params_vec.push(Box::new(value));
+        }
+    }
 
     param_count += 1;
     query.push_str(&format!(" WHERE id = ?{}", param_count));
@@ -494,7 +766,7 @@ pub async fn update_agent(
     // Fetch the updated agent
     let agent = conn
         .query_row(
-            "SELECT id, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, created_at, updated_at FROM agents WHERE id = ?1",
+            "SELECT id, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, created_at, updated_at, timeout_secs, max_output_tokens, max_memory_mb, max_cpu_secs, max_open_files FROM agents WHERE id = ?1",
             params![id],
             |row| {
                 Ok(Agent {
@@ -510,6 +782,7 @@ pub async fn update_agent(
                     hooks: row.get(9)?,
                     created_at: row.get(10)?,
                     updated_at: row.get(11)?,
+                    limits: AgentLimits::from_row(row, 12)?,
                 })
             },
         )
@@ -536,7 +809,7 @@ pub async fn get_agent(db: State<'_, AgentDb>, id: i64) -> Result<Agent, String>
 
     let agent = conn
         .query_row(
-            "SELECT id, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, created_at, updated_at FROM agents WHERE id = ?1",
+            "SELECT id, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, created_at, updated_at, timeout_secs, max_output_tokens, max_memory_mb, max_cpu_secs, max_open_files FROM agents WHERE id = ?1",
             params![id],
             |row| {
                 Ok(Agent {
@@ -552,6 +825,7 @@ pub async fn get_agent(db: State<'_, AgentDb>, id: i64) -> Result<Agent, String>
                     hooks: row.get(9)?,
                     created_at: row.get(10)?,
                     updated_at: row.get(11)?,
+                    limits: AgentLimits::from_row(row, 12)?,
                 })
             },
         )
@@ -569,10 +843,10 @@ pub async fn list_agent_runs(
     let conn = db.0.lock().map_err(|e| e.to_string())?;
 
     let query = if agent_id.is_some() {
-        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at 
+        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at, failure_reason 
          FROM agent_runs WHERE agent_id = ?1 ORDER BY created_at DESC"
     } else {
-        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at 
+        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at, failure_reason 
          FROM agent_runs ORDER BY created_at DESC"
     };
 
@@ -599,6 +873,7 @@ pub async fn list_agent_runs(
             process_started_at: row.get(10)?,
             created_at: row.get(11)?,
             completed_at: row.get(12)?,
+            failure_reason: row.get(13)?,
         })
     };
 
@@ -621,7 +896,7 @@ pub async fn get_agent_run(db: State<'_, AgentDb>, id: i64) -> Result<AgentRun,
 
     let run = conn
         .query_row(
-            "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at 
+            "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at, failure_reason 
              FROM agent_runs WHERE id = ?1",
             params![id],
             |row| {
@@ -639,6 +914,7 @@ pub async fn get_agent_run(db: State<'_, AgentDb>, id: i64) -> Result<AgentRun,
                     process_started_at: row.get(10)?,
                     created_at: row.get(11)?,
                     completed_at: row.get(12)?,
+                    failure_reason: row.get(13)?,
                 })
             },
         )
@@ -727,12 +1003,15 @@ pub async fn execute_agent(
         }
     }
 
+    // Choose the session ID up front so the run can be checkpointed before it starts
+    let session_id = uuid::Uuid::new_v4().to_string();
+
     // Create a new run record
     let run_id = {
         let conn = db.0.lock().map_err(|e| e.to_string())?;
         conn.execute(
             "INSERT INTO agent_runs (agent_id, agent_name, agent_icon, task, model, project_path, session_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![agent_id, agent.name, agent.icon, task, execution_model, project_path, ""],
+            params![agent_id, agent.name, agent.icon, task, execution_model, project_path, session_id],
         )
         .map_err(|e| e.to_string())?;
         conn.last_insert_rowid()
@@ -756,6 +1035,8 @@ pub async fn execute_agent(
         agent.system_prompt.clone(),
         "--model".to_string(),
         execution_model.clone(),
+        "--session-id".to_string(),
+        session_id.clone(),
         "--output-format".to_string(),
         "stream-json".to_string(),
         "--verbose".to_string(),
@@ -763,19 +1044,34 @@ pub async fn execute_agent(
     ];
 
     // Always use system binary execution (sidecar removed)
-    spawn_agent_system(
-        app,
+    let run = AgentRunSpec {
         run_id,
         agent_id,
-        agent.name.clone(),
+        agent_name: agent.name.clone(),
         claude_path,
         args,
         project_path,
         task,
         execution_model,
-        db,
-        registry,
-    ).await
+        checkpoint_session_id: session_id,
+        limits: agent.limits,
+    };
+    spawn_agent_system(app, run, db, registry).await
+}
+
+/// What `spawn_agent_system` needs to start a run whose record already exists
+struct AgentRunSpec {
+    run_id: i64,
+    agent_id: i64,
+    agent_name: String,
+    claude_path: String,
+    args: Vec<String>,
+    project_path: String,
+    task: String,
+    execution_model: String,
+    /// Session the run's checkpoints are stored under
+    checkpoint_session_id: String,
+    limits: AgentLimits,
 }
 
 /// Creates a system binary command for agent execution
@@ -783,6 +1079,7 @@ fn create_agent_system_command(
     claude_path: &str,
     args: Vec<String>,
     project_path: &str,
+    limits: &AgentLimits,
 ) -> Command {
     let mut cmd = create_command_with_env(claude_path);
     
@@ -795,26 +1092,185 @@ fn create_agent_system_command(
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
+
+    // Lead a process group of its own, so killing the run also stops what its tools started
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    #[cfg(unix)]
+    limits.apply_rlimits(&mut cmd);
+    #[cfg(not(unix))]
+    if limits.has_rlimits() {
+        warn!("Memory, CPU and open file limits are only enforced on Unix");
+    }
     
     cmd
 }
 
+/// Create the checkpoint manager for an agent run and take its starting checkpoint
+///
+/// Agents run with permissions skipped, so the checkpoints are the only way to undo
+/// their changes. Checkpointing problems are logged rather than stopping the run.
+async fn start_agent_checkpoints(
+    checkpoints: &crate::checkpoint::state::CheckpointState,
+    db_path: &std::path::Path,
+    run_id: i64,
+    agent_name: &str,
+    session_id: &str,
+    project_path: &str,
+) -> Option<std::sync::Arc<crate::checkpoint::manager::CheckpointManager>> {
+    // Encode project path to match Claude Code's directory naming
+    let project_id = project_path.replace('/', "-");
+    let manager = match checkpoints
+        .get_or_create_manager(
+            session_id.to_string(),
+            project_id,
+            std::path::PathBuf::from(project_path),
+        )
+        .await
+    {
+        Ok(manager) => manager,
+        Err(e) => {
+            warn!("Agent run {} will not be checkpointed: {}", run_id, e);
+            return None;
+        }
+    };
+
+    if let Err(e) = manager
+        .update_settings(true, crate::checkpoint::CheckpointStrategy::Smart, None)
+        .await
+    {
+        warn!("Failed to configure checkpoints for agent run {}: {}", run_id, e);
+    }
+
+    take_agent_checkpoint(
+        &manager,
+        db_path,
+        run_id,
+        "run_start",
+        format!("{}: before run", agent_name),
+    )
+    .await;
+
+    Some(manager)
+}
+
+/// Take a checkpoint of an agent run and link it to the run's record
+async fn take_agent_checkpoint(
+    manager: &crate::checkpoint::manager::CheckpointManager,
+    db_path: &std::path::Path,
+    run_id: i64,
+    trigger: &str,
+    description: String,
+) {
+    let result = match manager.create_auto_checkpoint(description, None).await {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Failed to checkpoint agent run {}: {}", run_id, e);
+            return;
+        }
+    };
+
+    let checkpoint = &result.checkpoint;
+    match Connection::open(db_path) {
+        Ok(conn) => {
+            if let Err(e) = conn.execute(
+                "INSERT INTO agent_run_checkpoints (run_id, checkpoint_id, session_id, project_id, trigger) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![run_id, checkpoint.id, checkpoint.session_id, checkpoint.project_id, trigger],
+            ) {
+                error!("Failed to link checkpoint {} to agent run {}: {}", checkpoint.id, run_id, e);
+            }
+        }
+        Err(e) => {
+            error!("Failed to open database to link checkpoint for run {}: {}", run_id, e);
+        }
+    }
+}
+
+/// Whether any of the tool uses calls for a checkpoint under the session's strategy
+async fn any_auto_checkpoint(
+    manager: &crate::checkpoint::manager::CheckpointManager,
+    tool_uses: &[String],
+) -> bool {
+    for tool_use in tool_uses {
+        if manager.should_auto_checkpoint(tool_use).await {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether a stream-json line carries tool results back to the model
+fn is_tool_result(line: &str) -> bool {
+    has_content_of_type(line, "tool_result")
+}
+
+/// Whether a stream-json line asks for tools to be run
+fn is_tool_use(line: &str) -> bool {
+    has_content_of_type(line, "tool_use")
+}
+
+fn has_content_of_type(line: &str, content_type: &str) -> bool {
+    serde_json::from_str::<JsonValue>(line)
+        .ok()
+        .and_then(|json| {
+            json.get("message")
+                .and_then(|m| m.get("content"))
+                .and_then(|c| c.as_array())
+                .map(|content| {
+                    content.iter().any(|item| {
+                        item.get("type").and_then(|t| t.as_str()) == Some(content_type)
+                    })
+                })
+        })
+        .unwrap_or(false)
+}
+
 /// Spawn agent using system binary command
 async fn spawn_agent_system(
     app: AppHandle,
-    run_id: i64,
-    agent_id: i64,
-    agent_name: String,
-    claude_path: String,
-    args: Vec<String>,
-    project_path: String,
-    task: String,
-    execution_model: String,
+    run: AgentRunSpec,
     db: State<'_, AgentDb>,
     registry: State<'_, crate::process::ProcessRegistryState>,
 ) -> Result<i64, String> {
+    let AgentRunSpec {
+        run_id,
+        agent_id,
+        agent_name,
+        claude_path,
+        args,
+        project_path,
+        task,
+        execution_model,
+        checkpoint_session_id,
+        limits,
+    } = run;
+    let app_dir = app
+        .path()
+        .app_data_dir()
+        .expect("Failed to get app data dir");
+    let db_path = app_dir.join("agents.db");
+
+    // Snapshot the project before the agent can touch it
+    let checkpoints = app
+        .state::<crate::checkpoint::state::CheckpointState>()
+        .inner()
+        .clone();
+    let checkpoint_manager = start_agent_checkpoints(
+        &checkpoints,
+        &db_path,
+        run_id,
+        &agent_name,
+        &checkpoint_session_id,
+        &project_path,
+    )
+    .await;
+
     // Build the command
-    let mut cmd = create_agent_system_command(&claude_path, args, &project_path);
+    let mut cmd = create_agent_system_command(&claude_path, args, &project_path, &limits);
+    if limits.has_rlimits() || limits.timeout_secs.is_some() || limits.max_output_tokens.is_some() {
+        info!("Agent run {} limits: {:?}", run_id, limits);
+    }
 
     // Spawn the process
     info!("🚀 Spawning Claude system process...");
@@ -849,12 +1305,21 @@ async fn spawn_agent_system(
     let stdout_reader = TokioBufReader::new(stdout);
     let stderr_reader = TokioBufReader::new(stderr);
 
-    // Create variables we need for the spawned tasks
-    let app_dir = app
-        .path()
-        .app_data_dir()
-        .expect("Failed to get app data dir");
-    let db_path = app_dir.join("agents.db");
+    // Register the process before reading output, so the session ID has an entry to land on
+    registry
+        .0
+        .register_process(
+            run_id,
+            agent_id,
+            agent_name.clone(),
+            pid,
+            project_path.clone(),
+            task.clone(),
+            execution_model.clone(),
+            child,
+        )
+        .map_err(|e| format!("Failed to register process: {}", e))?;
+    info!("📋 Registered process in registry");
 
     // Shared state for collecting session ID and live output
     let session_id = std::sync::Arc::new(Mutex::new(String::new()));
@@ -869,11 +1334,46 @@ async fn spawn_agent_system(
     let first_output = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
     let first_output_clone = first_output.clone();
     let db_path_for_stdout = db_path.clone(); // Clone the db_path for the stdout task
+    let checkpoint_manager_clone = checkpoint_manager.clone();
+    let limit_trip: std::sync::Arc<Mutex<Option<LimitTrip>>> = Default::default();
+    let limit_trip_clone = limit_trip.clone();
+    let max_output_tokens = limits.max_output_tokens;
+
+    // Tool uses are judged and checkpointed on a task of their own, so reading output
+    // never waits on a snapshot of the project
+    let (checkpoint_tx, checkpoint_task) = match checkpoint_manager.clone() {
+        Some(manager) => {
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Vec<String>>();
+            let db_path = db_path.clone();
+            let agent_name = agent_name.clone();
+            let task = tokio::spawn(async move {
+                while let Some(tool_uses) = rx.recv().await {
+                    if any_auto_checkpoint(&manager, &tool_uses).await {
+                        take_agent_checkpoint(
+                            &manager,
+                            &db_path,
+                            run_id,
+                            "tool_use",
+                            format!("{}: after destructive tool use", agent_name),
+                        )
+                        .await;
+                    }
+                }
+            });
+            (Some(tx), Some(task))
+        }
+        None => (None, None),
+    };
 
     let stdout_task = tokio::spawn(async move {
         info!("📖 Starting to read Claude stdout...");
         let mut lines = stdout_reader.lines();
         let mut line_count = 0;
+        // Tool uses whose results haven't arrived yet
+        let mut pending_tool_uses: Vec<String> = Vec::new();
+        // Output tokens of each assistant message so far
+        let mut output_tokens: std::collections::HashMap<String, i64> =
+            std::collections::HashMap::new();
 
         while let Ok(Some(line)) = lines.next_line().await {
             line_count += 1;
@@ -902,6 +1402,21 @@ async fn spawn_agent_system(
             // Also store in process registry for cross-session access
             let _ = registry_clone.append_live_output(run_id, &line);
 
+            // Judge a tool use once its result comes back, so the Smart rules see the
+            // files it changed and the checkpoint holds the project as the tool left it
+            if let Some(manager) = &checkpoint_manager_clone {
+                if let Err(e) = manager.track_message(line.clone()).await {
+                    warn!("Failed to track agent message for checkpoints: {}", e);
+                }
+                if is_tool_result(&line) && !pending_tool_uses.is_empty() {
+                    if let Some(tx) = &checkpoint_tx {
+                        let _ = tx.send(std::mem::take(&mut pending_tool_uses));
+                    }
+                } else if is_tool_use(&line) {
+                    pending_tool_uses.push(line.clone());
+                }
+            }
+
             // Extract session ID from JSONL output
             if let Ok(json) = serde_json::from_str::<JsonValue>(&line) {
                 // Claude Code uses "session_id" (underscore), not "sessionId"
@@ -912,6 +1427,7 @@ async fn spawn_agent_system(
                             if current_session_id.is_empty() {
                                 *current_session_id = sid.to_string();
                                 info!("🔑 Extracted session ID: {}", sid);
+                                let _ = registry_clone.set_session_id(run_id, sid.to_string());
                                 
                                 // Update database immediately with session ID
                                 if let Ok(conn) = Connection::open(&db_path_for_stdout) {
@@ -933,6 +1449,31 @@ async fn spawn_agent_system(
                         }
                     }
                 }
+
+                // Stop the run once the model has written more than the agent allows
+                if let Some(max_tokens) = max_output_tokens {
+                    if let Some((message_id, tokens)) = assistant_output_tokens(&json) {
+                        output_tokens.insert(message_id, tokens);
+                        let total: i64 = output_tokens.values().sum();
+                        if total > max_tokens
+                            && record_limit_trip(
+                                &limit_trip_clone,
+                                "limit_exceeded",
+                                format!(
+                                    "Run produced {} output tokens, over its limit of {}",
+                                    total, max_tokens
+                                ),
+                            )
+                        {
+                            let registry = registry_clone.clone();
+                            tokio::spawn(async move {
+                                if let Err(e) = registry.kill_process(run_id).await {
+                                    error!("Failed to stop agent run {}: {}", run_id, e);
+                                }
+                            });
+                        }
+                    }
+                }
             }
 
             // Emit the line to the frontend with run_id for isolation
@@ -941,6 +1482,13 @@ async fn spawn_agent_system(
             let _ = app_handle.emit("agent-output", &line);
         }
 
+        // The run ended before the last tool reported back
+        if let Some(tx) = checkpoint_tx {
+            if !pending_tool_uses.is_empty() {
+                let _ = tx.send(pending_tool_uses);
+            }
+        }
+
         info!(
             "📖 Finished reading Claude stdout. Total lines: {}",
             line_count
@@ -982,23 +1530,9 @@ async fn spawn_agent_system(
         }
     });
 
-    // Register the process in the registry for live output tracking (after stdout/stderr setup)
-    registry
-        .0
-        .register_process(
-            run_id,
-            agent_id,
-            agent_name,
-            pid,
-            project_path.clone(),
-            task.clone(),
-            execution_model.clone(),
-            child,
-        )
-        .map_err(|e| format!("Failed to register process: {}", e))?;
-    info!("📋 Registered process in registry");
-
     let db_path_for_monitor = db_path.clone(); // Clone for the monitor task
+    let registry_for_monitor = registry.0.clone();
+    let timeout = limits.timeout();
 
     // Monitor process status and wait for completion
     tokio::spawn(async move {
@@ -1015,6 +1549,11 @@ async fn spawn_agent_system(
                 break;
             }
 
+            // The agent's own timeout is up first; it's enforced below
+            if timeout.is_some_and(|timeout| start_time.elapsed() >= timeout) {
+                break;
+            }
+
             if i == 299 {
                 warn!("⏰ TIMEOUT: No output from Claude process after 30 seconds");
                 warn!("💡 This usually means:");
@@ -1023,26 +1562,17 @@ async fn spawn_agent_system(
                 warn!("   4. Network connectivity issues");
                 warn!("   5. Authentication issues (API key not found/invalid)");
 
-                // Process timed out - kill it via PID
+                // Process timed out - kill it along with anything it started
                 warn!(
                     "🔍 Process likely stuck waiting for input, attempting to kill PID: {}",
                     pid
                 );
-                let kill_result = std::process::Command::new("kill")
-                    .arg("-TERM")
-                    .arg(pid.to_string())
-                    .output();
-
-                match kill_result {
-                    Ok(output) if output.status.success() => {
-                        warn!("🔍 Successfully sent TERM signal to process");
+                match registry_for_monitor.kill_process(run_id).await {
+                    Ok(Some(report)) if report.exited => {
+                        warn!("🔍 Successfully killed process group");
                     }
                     Ok(_) => {
-                        warn!("🔍 Failed to kill process with TERM, trying KILL");
-                        let _ = std::process::Command::new("kill")
-                            .arg("-KILL")
-                            .arg(pid.to_string())
-                            .output();
+                        warn!("🔍 Process {} may still be running", pid);
                     }
                     Err(e) => {
                         warn!("🔍 Error killing process: {}", e);
@@ -1057,6 +1587,10 @@ async fn spawn_agent_system(
                     );
                 }
 
+                if checkpoint_manager.is_some() {
+                    checkpoints.remove_manager(&checkpoint_session_id).await;
+                }
+
                 let _ = app.emit("agent-complete", false);
                 let _ = app.emit(&format!("agent-complete:{}", run_id), false);
                 return;
@@ -1067,12 +1601,64 @@ async fn spawn_agent_system(
 
         // Wait for reading tasks to complete
         info!("⏳ Waiting for stdout/stderr reading to complete...");
-        let _ = stdout_task.await;
-        let _ = stderr_task.await;
+        let mut output_done = tokio::spawn(async move {
+            let _ = stdout_task.await;
+            let _ = stderr_task.await;
+        });
+        if let Some(timeout) = timeout {
+            let remaining = timeout.saturating_sub(start_time.elapsed());
+            if tokio::time::timeout(remaining, &mut output_done).await.is_err()
+                && record_limit_trip(
+                    &limit_trip,
+                    "timed_out",
+                    format!("Run exceeded its {}s timeout", timeout.as_secs()),
+                )
+            {
+                if let Err(e) = registry_for_monitor.kill_process(run_id).await {
+                    error!("Failed to stop agent run {}: {}", run_id, e);
+                }
+            }
+        }
+        let _ = output_done.await;
+
+        // Let the last checkpoints land before the manager goes away
+        if let Some(checkpoint_task) = checkpoint_task {
+            let _ = checkpoint_task.await;
+        }
+
+        // Reap the process; if its CPU limit killed it rather than us, say so. Memory and file
+        // limits only make calls fail inside the process, which can't be told apart from other
+        // failures, so those runs fail without being attributed to a limit. `exit` is `None`
+        // when the run was stopped through the registry, by a cancel or a limit.
+        let exit: Option<Result<(), String>> =
+            match registry_for_monitor.wait_for_exit_with_cpu_time(run_id).await {
+                Ok(Some((status, cpu_time))) => {
+                    info!("Agent run {} exited with {}", run_id, status);
+                    if let Some(reason) = limits.cpu_limit_exit(&status, cpu_time) {
+                        record_limit_trip(&limit_trip, "limit_exceeded", reason);
+                    }
+                    Some(if status.success() {
+                        Ok(())
+                    } else {
+                        Err(format!("Process exited with {}", status))
+                    })
+                }
+                Ok(None) => None,
+                Err(e) => {
+                    warn!("Failed to reap agent run {}: {}", run_id, e);
+                    Some(Err(format!("Failed to reap process: {}", e)))
+                }
+            };
+        let limit_trip = limit_trip.lock().ok().and_then(|trip| trip.clone());
 
         let duration_ms = start_time.elapsed().as_millis() as i64;
         info!("⏱️ Process execution took {} ms", duration_ms);
 
+        // The run is over; stop watching its files
+        if checkpoint_manager.is_some() {
+            checkpoints.remove_manager(&checkpoint_session_id).await;
+        }
+
         // Get the session ID that was extracted
         let extracted_session_id = if let Ok(sid) = session_id.lock() {
             sid.clone()
@@ -1083,11 +1669,28 @@ async fn spawn_agent_system(
         // Wait for process completion and update status
         info!("✅ Claude process execution monitoring complete");
 
-        // Update the run record with session ID and mark as completed - open a new connection
+        // Update the run record with session ID and mark it completed or failed by its exit, or
+        // stopped by whichever limit tripped. A run stopped some other way, such as a cancel,
+        // keeps the status it was given - open a new connection
+        let (status, failure_reason) = match (&limit_trip, exit) {
+            (Some(trip), _) => (Some(trip.status), Some(trip.reason.clone())),
+            (None, Some(Ok(()))) => (Some("completed"), None),
+            (None, Some(Err(reason))) => (Some("failed"), Some(reason)),
+            (None, None) => (None, None),
+        };
         if let Ok(conn) = Connection::open(&db_path_for_monitor) {
+            if let Some(status) = status {
+                if let Err(e) = conn.execute(
+                    "UPDATE agent_runs SET status = ?1, failure_reason = ?2, completed_at = CURRENT_TIMESTAMP WHERE id = ?3 AND status = 'running'",
+                    params![status, failure_reason, run_id],
+                ) {
+                    error!("❌ Failed to record the outcome of agent run {}: {}", run_id, e);
+                }
+            }
+
             info!("🔄 Updating database with extracted session ID: {}", extracted_session_id);
             match conn.execute(
-                "UPDATE agent_runs SET session_id = ?1, status = 'completed', completed_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                "UPDATE agent_runs SET session_id = ?1 WHERE id = ?2",
                 params![extracted_session_id, run_id],
             ) {
                 Ok(rows_affected) => {
@@ -1107,13 +1710,48 @@ async fn spawn_agent_system(
 
         // Cleanup will be handled by the cleanup_finished_processes function
 
-        let _ = app.emit("agent-complete", true);
-        let _ = app.emit(&format!("agent-complete:{}", run_id), true);
+        let success = status == Some("completed");
+        let _ = app.emit("agent-complete", success);
+        let _ = app.emit(&format!("agent-complete:{}", run_id), success);
     });
 
     Ok(run_id)
 }
 
+/// List the checkpoints taken automatically during an agent run, oldest first
+#[tauri::command]
+pub async fn list_agent_run_checkpoints(
+    db: State<'_, AgentDb>,
+    run_id: i64,
+) -> Result<Vec<AgentRunCheckpoint>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, run_id, checkpoint_id, session_id, project_id, trigger, created_at
+             FROM agent_run_checkpoints WHERE run_id = ?1 ORDER BY id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let checkpoints = stmt
+        .query_map(params![run_id], |row| {
+            Ok(AgentRunCheckpoint {
+                id: row.get(0)?,
+                run_id: row.get(1)?,
+                checkpoint_id: row.get(2)?,
+                session_id: row.get(3)?,
+                project_id: row.get(4)?,
+                trigger: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(checkpoints)
+}
+
 /// List all currently running agent sessions
 #[tauri::command]
 pub async fn list_running_sessions(
@@ -1124,7 +1762,7 @@ pub async fn list_running_sessions(
 
     // First get all running sessions from the database
     let mut stmt = conn.prepare(
-        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at 
+        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at, failure_reason 
          FROM agent_runs WHERE status = 'running' ORDER BY process_started_at DESC"
     ).map_err(|e| e.to_string())?;
 
@@ -1150,6 +1788,7 @@ pub async fn list_running_sessions(
                 process_started_at: row.get(10)?,
                 created_at: row.get(11)?,
                 completed_at: row.get(12)?,
+                failure_reason: row.get(13)?,
             })
         })
         .map_err(|e| e.to_string())?
@@ -1192,14 +1831,18 @@ pub async fn kill_agent_session(
 
     // First try to kill using the process registry
     let killed_via_registry = match registry.0.kill_process(run_id).await {
-        Ok(success) => {
-            if success {
-                info!("Successfully killed process {} via registry", run_id);
-                true
-            } else {
-                warn!("Process {} not found in registry", run_id);
-                false
-            }
+        Ok(Some(report)) => {
+            info!(
+                "Killed process {} via registry (exited: {}, reaped descendants: {:?})",
+                run_id,
+                report.exited,
+                report.reaped().collect::<Vec<_>>()
+            );
+            report.exited
+        }
+        Ok(None) => {
+            warn!("Process {} not found in registry", run_id);
+            false
         }
         Err(e) => {
             warn!("Failed to kill process {} via registry: {}", run_id, e);
@@ -1221,7 +1864,7 @@ pub async fn kill_agent_session(
 
         if let Some(pid) = pid_result {
             info!("Attempting fallback kill for PID {} from database", pid);
-            let _ = registry.0.kill_process_by_pid(run_id, pid as u32)?;
+            let _ = registry.0.kill_process_by_pid(run_id, pid as u32).await?;
         }
     }
 
@@ -1332,6 +1975,101 @@ pub async fn get_live_session_output(
     registry.0.get_live_output(run_id)
 }
 
+/// Read a running process's live output from a line cursor
+#[tauri::command]
+pub async fn read_live_session_output(
+    registry: State<'_, crate::process::ProcessRegistryState>,
+    run_id: i64,
+    offset: u64,
+    limit: Option<usize>,
+) -> Result<crate::process::OutputChunk, String> {
+    registry.0.read_live_output(
+        run_id,
+        offset,
+        limit.unwrap_or(crate::process::output::DEFAULT_OUTPUT_READ_LIMIT),
+    )
+}
+
+/// Get how many bytes of live output each process keeps in memory
+#[tauri::command]
+pub async fn get_live_output_buffer_limit(db: State<'_, AgentDb>) -> Result<usize, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    Ok(load_live_output_buffer_limit(&conn))
+}
+
+/// Set how many bytes of live output each process keeps in memory before spilling to disk
+#[tauri::command]
+pub async fn set_live_output_buffer_limit(
+    db: State<'_, AgentDb>,
+    registry: State<'_, crate::process::ProcessRegistryState>,
+    max_bytes: usize,
+) -> Result<(), String> {
+    if max_bytes == 0 {
+        return Err("Live output buffer limit must be greater than zero".to_string());
+    }
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES ('live_output_buffer_bytes', ?1)",
+        params![max_bytes.to_string()],
+    )
+    .map_err(|e| format!("Failed to save live output buffer limit: {}", e))?;
+
+    registry.0.set_output_buffer_limit(max_bytes)
+}
+
+/// The saved live output buffer limit, or the default
+pub fn load_live_output_buffer_limit(conn: &Connection) -> usize {
+    conn.query_row(
+        "SELECT value FROM app_settings WHERE key = 'live_output_buffer_bytes'",
+        [],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|value| value.parse().ok())
+    .filter(|max_bytes| *max_bytes > 0)
+    .unwrap_or(crate::process::output::DEFAULT_OUTPUT_BUFFER_BYTES)
+}
+
+/// Get how long, in milliseconds, a killed process group gets to exit before SIGKILL
+#[tauri::command]
+pub async fn get_process_kill_grace_ms(db: State<'_, AgentDb>) -> Result<u64, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    Ok(load_process_kill_grace_ms(&conn))
+}
+
+/// Set how long, in milliseconds, a killed process group gets to exit before SIGKILL
+#[tauri::command]
+pub async fn set_process_kill_grace_ms(
+    db: State<'_, AgentDb>,
+    registry: State<'_, crate::process::ProcessRegistryState>,
+    grace_ms: u64,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES ('process_kill_grace_ms', ?1)",
+        params![grace_ms.to_string()],
+    )
+    .map_err(|e| format!("Failed to save kill grace period: {}", e))?;
+
+    registry
+        .0
+        .set_kill_grace_period(std::time::Duration::from_millis(grace_ms));
+    Ok(())
+}
+
+/// The saved kill grace period in milliseconds, or the default
+pub fn load_process_kill_grace_ms(conn: &Connection) -> u64 {
+    conn.query_row(
+        "SELECT value FROM app_settings WHERE key = 'process_kill_grace_ms'",
+        [],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|value| value.parse().ok())
+    .unwrap_or(crate::process::kill::DEFAULT_KILL_GRACE_MS)
+}
+
 /// Get real-time output for a running session by reading its JSONL file with live output fallback
 #[tauri::command]
 pub async fn get_session_output(
@@ -1728,7 +2466,7 @@ pub async fn import_agent(db: State<'_, AgentDb>, json_data: String) -> Result<A
     // Fetch the created agent
     let agent = conn
         .query_row(
-            "SELECT id, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, created_at, updated_at FROM agents WHERE id = ?1",
+            "SELECT id, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, created_at, updated_at, timeout_secs, max_output_tokens, max_memory_mb, max_cpu_secs, max_open_files FROM agents WHERE id = ?1",
             params![id],
             |row| {
                 Ok(Agent {
@@ -1744,6 +2482,7 @@ pub async fn import_agent(db: State<'_, AgentDb>, json_data: String) -> Result<A
                     hooks: row.get(9)?,
                     created_at: row.get(10)?,
                     updated_at: row.get(11)?,
+                    limits: AgentLimits::from_row(row, 12)?,
                 })
             },
         )
@@ -7,23 +7,9 @@ use std::process::Stdio;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tauri::{AppHandle, Emitter, Manager};
-use tokio::process::{Child, Command};
-use tokio::sync::Mutex;
+use tokio::process::Command;
 
 
-/// Global state to track current Claude process
-pub struct ClaudeProcessState {
-    pub current_process: Arc<Mutex<Option<Child>>>,
-}
-
-impl Default for ClaudeProcessState {
-    fn default() -> Self {
-        Self {
-            current_process: Arc::new(Mutex::new(None)),
-        }
-    }
-}
-
 /// Represents a project in the ~/.claude/projects directory
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Project {
@@ -295,6 +281,10 @@ fn create_system_command(
     cmd.current_dir(project_path)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
+
+    // Lead a process group of its own, so cancelling also stops what its tools started
+    #[cfg(unix)]
+    cmd.process_group(0);
     
     cmd
 }
@@ -916,6 +906,7 @@ pub async fn execute_claude_code(
     project_path: String,
     prompt: String,
     model: String,
+    stream_id: Option<String>,
 ) -> Result<(), String> {
     log::info!(
         "Starting new Claude Code session in: {} with model: {}",
@@ -937,7 +928,7 @@ pub async fn execute_claude_code(
     ];
 
     let cmd = create_system_command(&claude_path, args, &project_path);
-    spawn_claude_process(app, cmd, prompt, model, project_path).await
+    spawn_claude_process(app, cmd, prompt, model, project_path, None, stream_id).await
 }
 
 /// Continue an existing Claude Code conversation with streaming output
@@ -947,6 +938,7 @@ pub async fn continue_claude_code(
     project_path: String,
     prompt: String,
     model: String,
+    stream_id: Option<String>,
 ) -> Result<(), String> {
     log::info!(
         "Continuing Claude Code conversation in: {} with model: {}",
@@ -969,7 +961,7 @@ pub async fn continue_claude_code(
     ];
 
     let cmd = create_system_command(&claude_path, args, &project_path);
-    spawn_claude_process(app, cmd, prompt, model, project_path).await
+    spawn_claude_process(app, cmd, prompt, model, project_path, None, stream_id).await
 }
 
 /// Resume an existing Claude Code session by ID with streaming output
@@ -980,6 +972,7 @@ pub async fn resume_claude_code(
     session_id: String,
     prompt: String,
     model: String,
+    stream_id: Option<String>,
 ) -> Result<(), String> {
     log::info!(
         "Resuming Claude Code session: {} in: {} with model: {}",
@@ -1004,133 +997,123 @@ pub async fn resume_claude_code(
     ];
 
     let cmd = create_system_command(&claude_path, args, &project_path);
-    spawn_claude_process(app, cmd, prompt, model, project_path).await
+    spawn_claude_process(
+        app,
+        cmd,
+        prompt,
+        model,
+        project_path,
+        Some(session_id),
+        stream_id,
+    )
+    .await
 }
 
-/// Cancel the currently running Claude Code execution
+/// Cancel a running Claude Code execution
+///
+/// Only the given session is stopped, found by its session ID or, before Claude has reported
+/// one, by the stream ID its run was started with. Without either, the single running session
+/// is, if there is exactly one. The run itself reports the cancellation on its channels.
 #[tauri::command]
 pub async fn cancel_claude_execution(
     app: AppHandle,
     session_id: Option<String>,
+    stream_id: Option<String>,
 ) -> Result<(), String> {
     log::info!(
-        "Cancelling Claude Code execution for session: {:?}",
-        session_id
+        "Cancelling Claude Code execution for session: {:?}, stream: {:?}",
+        session_id,
+        stream_id
     );
 
-    let mut killed = false;
-    let mut attempted_methods = Vec::new();
-
-    // Method 1: Try to find and kill via ProcessRegistry using session ID
-    if let Some(sid) = &session_id {
-        let registry = app.state::<crate::process::ProcessRegistryState>();
-        match registry.0.get_claude_session_by_id(sid) {
-            Ok(Some(process_info)) => {
-                log::info!("Found process in registry for session {}: run_id={}, PID={}", 
-                    sid, process_info.run_id, process_info.pid);
-                match registry.0.kill_process(process_info.run_id).await {
-                    Ok(success) => {
-                        if success {
-                            log::info!("Successfully killed process via registry");
-                            killed = true;
-                        } else {
-                            log::warn!("Registry kill returned false");
-                        }
-                    }
-                    Err(e) => {
-                        log::warn!("Failed to kill via registry: {}", e);
-                    }
-                }
-                attempted_methods.push("registry");
-            }
-            Ok(None) => {
-                log::warn!("Session {} not found in ProcessRegistry", sid);
-            }
-            Err(e) => {
-                log::error!("Error querying ProcessRegistry: {}", e);
-            }
+    let registry = app.state::<crate::process::ProcessRegistryState>();
+    let mut process_info = match &session_id {
+        Some(sid) => registry.0.get_claude_session_by_id(sid)?,
+        None => None,
+    };
+    if process_info.is_none() {
+        if let Some(stream_id) = &stream_id {
+            process_info = registry.0.get_claude_session_by_stream_id(stream_id)?;
+        }
+    }
+    if session_id.is_none() && stream_id.is_none() {
+        let mut sessions = registry.0.get_running_claude_sessions()?;
+        if sessions.len() > 1 {
+            return Err(
+                "Several Claude sessions are running; specify which one to cancel".to_string(),
+            );
         }
+        process_info = sessions.pop();
     }
 
-    // Method 2: Try the legacy approach via ClaudeProcessState
-    if !killed {
-        let claude_state = app.state::<ClaudeProcessState>();
-        let mut current_process = claude_state.current_process.lock().await;
-
-        if let Some(mut child) = current_process.take() {
-            // Try to get the PID before killing
-            let pid = child.id();
-            log::info!("Attempting to kill Claude process via ClaudeProcessState with PID: {:?}", pid);
-
-            // Kill the process
-            match child.kill().await {
-                Ok(_) => {
-                    log::info!("Successfully killed Claude process via ClaudeProcessState");
-                    killed = true;
+    let mut killed = false;
+    let mut reported = false;
+    match &process_info {
+        Some(process_info) => {
+            log::info!("Found process in registry for session {:?}: run_id={}, PID={}",
+                session_id, process_info.run_id, process_info.pid);
+            match registry.0.kill_process(process_info.run_id).await {
+                Ok(Some(report)) => {
+                    log::info!(
+                        "Killed process via registry (exited: {}, reaped descendants: {:?})",
+                        report.exited,
+                        report.reaped().collect::<Vec<_>>()
+                    );
+                    killed = report.exited;
+                    // Unregistered now, so the run's monitor emits the cancellation
+                    reported = true;
+                }
+                Ok(None) => {
+                    log::warn!("Process was no longer in the registry");
                 }
                 Err(e) => {
-                    log::error!("Failed to kill Claude process via ClaudeProcessState: {}", e);
-                    
-                    // Method 3: If we have a PID, try system kill as last resort
-                    if let Some(pid) = pid {
-                        log::info!("Attempting system kill as last resort for PID: {}", pid);
-                        let kill_result = if cfg!(target_os = "windows") {
-                            std::process::Command::new("taskkill")
-                                .args(["/F", "/PID", &pid.to_string()])
-                                .output()
-                        } else {
-                            std::process::Command::new("kill")
-                                .args(["-KILL", &pid.to_string()])
-                                .output()
-                        };
-                        
-                        match kill_result {
-                            Ok(output) if output.status.success() => {
-                                log::info!("Successfully killed process via system command");
-                                killed = true;
-                            }
-                            Ok(output) => {
-                                let stderr = String::from_utf8_lossy(&output.stderr);
-                                log::error!("System kill failed: {}", stderr);
-                            }
-                            Err(e) => {
-                                log::error!("Failed to execute system kill command: {}", e);
-                            }
-                        }
-                    }
+                    log::warn!("Failed to kill via registry: {}", e);
                 }
             }
-            attempted_methods.push("claude_state");
-        } else {
-            log::warn!("No active Claude process in ClaudeProcessState");
+        }
+        None => {
+            log::warn!("No active Claude process found to cancel");
         }
     }
 
-    if !killed && attempted_methods.is_empty() {
-        log::warn!("No active Claude process found to cancel");
-    }
-
-    // Always emit cancellation events for UI consistency
-    if let Some(sid) = session_id {
-        let _ = app.emit(&format!("claude-cancelled:{}", sid), true);
+    // Nothing is left to report for the run, so emit the events here for UI consistency
+    if !reported {
+        let session_channel =
+            session_id.or_else(|| process_info.as_ref().and_then(claude_session_id));
+        let channels: Vec<String> = session_channel.into_iter().chain(stream_id).collect();
+        let emit = |event: &str, payload: bool| {
+            if channels.is_empty() {
+                // Run not identified; fall back to the un-scoped events
+                let _ = app.emit(event, payload);
+            }
+            for channel in &channels {
+                let _ = app.emit(&format!("{}:{}", event, channel), payload);
+            }
+        };
+        emit("claude-cancelled", true);
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        let _ = app.emit(&format!("claude-complete:{}", sid), false);
+        emit("claude-complete", false);
     }
-    
-    // Also emit generic events for backward compatibility
-    let _ = app.emit("claude-cancelled", true);
-    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-    let _ = app.emit("claude-complete", false);
-    
+
     if killed {
         log::info!("Claude process cancellation completed successfully");
-    } else if !attempted_methods.is_empty() {
-        log::warn!("Claude process cancellation attempted but process may have already exited. Attempted methods: {:?}", attempted_methods);
+    } else if process_info.is_some() {
+        log::warn!("Claude process cancellation attempted but process may have already exited");
     }
-    
+
     Ok(())
 }
 
+/// The session ID of a registered Claude session, once Claude has reported it
+fn claude_session_id(process_info: &crate::process::ProcessInfo) -> Option<String> {
+    match &process_info.process_type {
+        crate::process::ProcessType::ClaudeSession { session_id, .. } if !session_id.is_empty() => {
+            Some(session_id.clone())
+        }
+        _ => None,
+    }
+}
+
 /// Get all running Claude sessions
 #[tauri::command]
 pub async fn list_running_claude_sessions(
@@ -1153,10 +1136,65 @@ pub async fn get_claude_session_output(
     }
 }
 
+/// Read a Claude session's live output from a line cursor
+#[tauri::command]
+pub async fn read_claude_session_output(
+    registry: tauri::State<'_, crate::process::ProcessRegistryState>,
+    session_id: String,
+    offset: u64,
+    limit: Option<usize>,
+) -> Result<crate::process::OutputChunk, String> {
+    let limit = limit.unwrap_or(crate::process::output::DEFAULT_OUTPUT_READ_LIMIT);
+    match registry.0.get_claude_session_by_id(&session_id)? {
+        Some(process_info) => registry.0.read_live_output(process_info.run_id, offset, limit),
+        None => Ok(crate::process::OutputChunk::empty(offset)),
+    }
+}
+
+/// Where a Claude run's output, errors and completion are emitted
+///
+/// Every event goes to the session-scoped channel once Claude has reported its session ID,
+/// and to the caller's stream channel from the first line, so concurrent runs never see
+/// each other's events. Callers that pass no stream ID get the un-scoped channels instead.
+#[derive(Clone)]
+struct ClaudeRunEvents {
+    app: AppHandle,
+    stream_id: Option<String>,
+    session_id: Arc<std::sync::Mutex<Option<String>>>,
+}
+
+impl ClaudeRunEvents {
+    fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        if let Some(ref session_id) = *self.session_id.lock().unwrap() {
+            let _ = self
+                .app
+                .emit(&format!("{}:{}", event, session_id), payload.clone());
+        }
+        match &self.stream_id {
+            Some(stream_id) => {
+                let _ = self.app.emit(&format!("{}:{}", event, stream_id), payload);
+            }
+            None => {
+                let _ = self.app.emit(event, payload);
+            }
+        }
+    }
+}
+
 /// Helper function to spawn Claude process and handle streaming
-async fn spawn_claude_process(app: AppHandle, mut cmd: Command, prompt: String, model: String, project_path: String) -> Result<(), String> {
+///
+/// The child is owned by its ProcessRegistry entry, so any number of sessions can run at once
+/// and each is cancelled on its own.
+async fn spawn_claude_process(
+    app: AppHandle,
+    mut cmd: Command,
+    prompt: String,
+    model: String,
+    project_path: String,
+    session_id: Option<String>,
+    stream_id: Option<String>,
+) -> Result<(), String> {
     use tokio::io::{AsyncBufReadExt, BufReader};
-    use std::sync::Mutex;
 
     // Spawn the process
     let mut child = cmd
@@ -1174,148 +1212,100 @@ async fn spawn_claude_process(app: AppHandle, mut cmd: Command, prompt: String,
         pid
     );
 
-    // Create readers first (before moving child)
     let stdout_reader = BufReader::new(stdout);
     let stderr_reader = BufReader::new(stderr);
 
-    // We'll extract the session ID from Claude's init message
-    let session_id_holder: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
-    let run_id_holder: Arc<Mutex<Option<i64>>> = Arc::new(Mutex::new(None));
-
-    // Store the child process in the global state (for backward compatibility)
-    let claude_state = app.state::<ClaudeProcessState>();
-    {
-        let mut current_process = claude_state.current_process.lock().await;
-        // If there's already a process running, kill it first
-        if let Some(mut existing_child) = current_process.take() {
-            log::warn!("Killing existing Claude process before starting new one");
-            let _ = existing_child.kill().await;
+    // Hand the child to the registry; a resumed session may still get a new ID from Claude
+    let registry = app.state::<crate::process::ProcessRegistryState>().0.clone();
+    let run_id = match registry.register_claude_session(
+        session_id.clone().unwrap_or_default(),
+        stream_id.clone(),
+        project_path,
+        prompt,
+        model,
+        child,
+    ) {
+        Ok(run_id) => run_id,
+        Err(e) => {
+            log::error!("Failed to register Claude session: {}", e);
+            return Err(e);
         }
-        *current_process = Some(child);
-    }
+    };
+    log::info!("Registered Claude session with run_id: {}", run_id);
+
+    let events = ClaudeRunEvents {
+        app: app.clone(),
+        stream_id,
+        session_id: Arc::new(std::sync::Mutex::new(None)),
+    };
 
     // Spawn tasks to read stdout and stderr
-    let app_handle = app.clone();
-    let session_id_holder_clone = session_id_holder.clone();
-    let run_id_holder_clone = run_id_holder.clone();
-    let registry = app.state::<crate::process::ProcessRegistryState>();
-    let registry_clone = registry.0.clone();
-    let project_path_clone = project_path.clone();
-    let prompt_clone = prompt.clone();
-    let model_clone = model.clone();
+    let stdout_events = events.clone();
+    let stdout_registry = registry.clone();
     let stdout_task = tokio::spawn(async move {
         let mut lines = stdout_reader.lines();
         while let Ok(Some(line)) = lines.next_line().await {
             log::debug!("Claude stdout: {}", line);
-            
-            // Parse the line to check for init message with session ID
+
+            // Pick up the session ID from Claude's init message
             if let Ok(msg) = serde_json::from_str::<serde_json::Value>(&line) {
                 if msg["type"] == "system" && msg["subtype"] == "init" {
                     if let Some(claude_session_id) = msg["session_id"].as_str() {
-                        let mut session_id_guard = session_id_holder_clone.lock().unwrap();
+                        let mut session_id_guard = stdout_events.session_id.lock().unwrap();
                         if session_id_guard.is_none() {
                             *session_id_guard = Some(claude_session_id.to_string());
                             log::info!("Extracted Claude session ID: {}", claude_session_id);
-                            
-                            // Now register with ProcessRegistry using Claude's session ID
-                            match registry_clone.register_claude_session(
-                                claude_session_id.to_string(),
-                                pid,
-                                project_path_clone.clone(),
-                                prompt_clone.clone(),
-                                model_clone.clone(),
-                            ) {
-                                Ok(run_id) => {
-                                    log::info!("Registered Claude session with run_id: {}", run_id);
-                                    let mut run_id_guard = run_id_holder_clone.lock().unwrap();
-                                    *run_id_guard = Some(run_id);
-                                }
-                                Err(e) => {
-                                    log::error!("Failed to register Claude session: {}", e);
-                                }
+                            if let Err(e) = stdout_registry
+                                .set_session_id(run_id, claude_session_id.to_string())
+                            {
+                                log::error!("Failed to record Claude session ID: {}", e);
                             }
                         }
                     }
                 }
             }
-            
-            // Store live output in registry if we have a run_id
-            if let Some(run_id) = *run_id_holder_clone.lock().unwrap() {
-                let _ = registry_clone.append_live_output(run_id, &line);
-            }
-            
-            // Emit the line to the frontend with session isolation if we have session ID
-            if let Some(ref session_id) = *session_id_holder_clone.lock().unwrap() {
-                let _ = app_handle.emit(&format!("claude-output:{}", session_id), &line);
-            }
-            // Also emit to the generic event for backward compatibility
-            let _ = app_handle.emit("claude-output", &line);
+
+            let _ = stdout_registry.append_live_output(run_id, &line);
+            stdout_events.emit("claude-output", &line);
         }
     });
 
-    let app_handle_stderr = app.clone();
-    let session_id_holder_clone2 = session_id_holder.clone();
+    let stderr_events = events.clone();
     let stderr_task = tokio::spawn(async move {
         let mut lines = stderr_reader.lines();
         while let Ok(Some(line)) = lines.next_line().await {
             log::error!("Claude stderr: {}", line);
-            // Emit error lines to the frontend with session isolation if we have session ID
-            if let Some(ref session_id) = *session_id_holder_clone2.lock().unwrap() {
-                let _ = app_handle_stderr.emit(&format!("claude-error:{}", session_id), &line);
-            }
-            // Also emit to the generic event for backward compatibility
-            let _ = app_handle_stderr.emit("claude-error", &line);
+            stderr_events.emit("claude-error", &line);
         }
     });
 
     // Wait for the process to complete
-    let app_handle_wait = app.clone();
-    let claude_state_wait = claude_state.current_process.clone();
-    let session_id_holder_clone3 = session_id_holder.clone();
-    let run_id_holder_clone2 = run_id_holder.clone();
-    let registry_clone2 = registry.0.clone();
     tokio::spawn(async move {
         let _ = stdout_task.await;
         let _ = stderr_task.await;
 
-        // Get the child from the state to wait on it
-        let mut current_process = claude_state_wait.lock().await;
-        if let Some(mut child) = current_process.take() {
-            match child.wait().await {
-                Ok(status) => {
-                    log::info!("Claude process exited with status: {}", status);
-                    // Add a small delay to ensure all messages are processed
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                    if let Some(ref session_id) = *session_id_holder_clone3.lock().unwrap() {
-                        let _ = app_handle_wait.emit(
-                            &format!("claude-complete:{}", session_id),
-                            status.success(),
-                        );
-                    }
-                    // Also emit to the generic event for backward compatibility
-                    let _ = app_handle_wait.emit("claude-complete", status.success());
-                }
-                Err(e) => {
-                    log::error!("Failed to wait for Claude process: {}", e);
-                    // Add a small delay to ensure all messages are processed
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                    if let Some(ref session_id) = *session_id_holder_clone3.lock().unwrap() {
-                        let _ = app_handle_wait
-                            .emit(&format!("claude-complete:{}", session_id), false);
-                    }
-                    // Also emit to the generic event for backward compatibility
-                    let _ = app_handle_wait.emit("claude-complete", false);
-                }
+        match registry.wait_for_exit(run_id).await {
+            Ok(Some(status)) => {
+                log::info!("Claude process {} exited with status: {}", run_id, status);
+                // Add a small delay to ensure all messages are processed
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                events.emit("claude-complete", status.success());
+            }
+            Ok(None) => {
+                // Killed through the registry, usually by cancel_claude_execution
+                log::info!("Claude process {} was stopped before it exited", run_id);
+                events.emit("claude-cancelled", true);
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                events.emit("claude-complete", false);
+            }
+            Err(e) => {
+                log::error!("Failed to wait for Claude process {}: {}", run_id, e);
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                events.emit("claude-complete", false);
             }
         }
 
-        // Unregister from ProcessRegistry if we have a run_id
-        if let Some(run_id) = *run_id_holder_clone2.lock().unwrap() {
-            let _ = registry_clone2.unregister_process(run_id);
-        }
-
-        // Clear the process from state
-        *current_process = None;
+        let _ = registry.unregister_process(run_id);
     });
 
     Ok(())
@@ -1525,6 +1515,7 @@ pub async fn create_checkpoint(
     project_path: String,
     message_index: Option<usize>,
     description: Option<String>,
+    auto_generated: Option<bool>,
 ) -> Result<crate::checkpoint::CheckpointResult, String> {
     log::info!(
         "Creating checkpoint for session: {} in project: {}",
@@ -1570,10 +1561,11 @@ pub async fn create_checkpoint(
         }
     }
 
-    manager
-        .create_checkpoint(description, None)
-        .await
-        .map_err(|e| format!("Failed to create checkpoint: {}", e))
+    let result = match (description, auto_generated.unwrap_or(false)) {
+        (Some(description), true) => manager.create_auto_checkpoint(description, None).await,
+        (description, _) => manager.create_checkpoint(description, None).await,
+    };
+    result.map_err(|e| format!("Failed to create checkpoint: {}", e))
 }
 
 /// Restores a session to a specific checkpoint
@@ -1584,13 +1576,24 @@ pub async fn restore_checkpoint(
     session_id: String,
     project_id: String,
     project_path: String,
+    on_conflict: Option<String>,
 ) -> Result<crate::checkpoint::CheckpointResult, String> {
+    use crate::checkpoint::ConflictResolution;
+
     log::info!(
         "Restoring checkpoint: {} for session: {}",
         checkpoint_id,
         session_id
     );
 
+    let resolution = match on_conflict.as_deref() {
+        None => ConflictResolution::default(),
+        Some("abort") => ConflictResolution::Abort,
+        Some("force") => ConflictResolution::Force,
+        Some("stash") => ConflictResolution::Stash,
+        Some(other) => return Err(format!("Invalid conflict resolution: {}", other)),
+    };
+
     let manager = app
         .get_or_create_manager(
             session_id.clone(),
@@ -1601,7 +1604,7 @@ pub async fn restore_checkpoint(
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
     let result = manager
-        .restore_checkpoint(&checkpoint_id)
+        .restore_checkpoint_with_resolution(&checkpoint_id, resolution)
         .await
         .map_err(|e| format!("Failed to restore checkpoint: {}", e))?;
 
@@ -1625,6 +1628,109 @@ pub async fn restore_checkpoint(
     Ok(result)
 }
 
+/// Lists files modified outside the session that a restore would overwrite
+#[tauri::command]
+pub async fn check_restore_conflicts(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    session_id: String,
+    project_id: String,
+    project_path: String,
+) -> Result<Vec<crate::checkpoint::ExternalChange>, String> {
+    log::info!("Checking for external edits in session: {}", session_id);
+
+    let manager = app
+        .get_or_create_manager(session_id, project_id, PathBuf::from(&project_path))
+        .await
+        .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
+
+    Ok(manager.detect_external_changes().await)
+}
+
+/// Verifies checkpoint storage for a session, optionally repairing it
+#[tauri::command]
+pub async fn verify_checkpoint_storage(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    session_id: String,
+    project_id: String,
+    project_path: String,
+    repair: Option<bool>,
+) -> Result<crate::checkpoint::integrity::IntegrityReport, String> {
+    let repair = repair.unwrap_or(false);
+    log::info!(
+        "Verifying checkpoint storage for session: {} (repair: {})",
+        session_id,
+        repair
+    );
+
+    let manager = app
+        .get_or_create_manager(session_id, project_id, PathBuf::from(&project_path))
+        .await
+        .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
+
+    manager
+        .verify_storage(repair)
+        .await
+        .map_err(|e| format!("Failed to verify checkpoint storage: {}", e))
+}
+
+/// Previews what restoring a checkpoint would change, without touching disk
+#[tauri::command]
+pub async fn preview_restore_checkpoint(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    checkpoint_id: String,
+    session_id: String,
+    project_id: String,
+    project_path: String,
+    paths: Option<Vec<String>>,
+) -> Result<crate::checkpoint::RestorePlan, String> {
+    log::info!(
+        "Previewing restore of checkpoint: {} for session: {}",
+        checkpoint_id,
+        session_id
+    );
+
+    let manager = app
+        .get_or_create_manager(session_id, project_id, PathBuf::from(&project_path))
+        .await
+        .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
+
+    manager
+        .plan_restore(
+            &checkpoint_id,
+            paths.map(|paths| paths.into_iter().map(PathBuf::from).collect()),
+        )
+        .await
+        .map_err(|e| format!("Failed to preview restore: {}", e))
+}
+
+/// Restores selected files or directories from a checkpoint, keeping the conversation
+#[tauri::command]
+pub async fn restore_checkpoint_paths(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    checkpoint_id: String,
+    session_id: String,
+    project_id: String,
+    project_path: String,
+    paths: Vec<String>,
+) -> Result<crate::checkpoint::CheckpointResult, String> {
+    log::info!(
+        "Restoring {} path(s) from checkpoint: {} for session: {}",
+        paths.len(),
+        checkpoint_id,
+        session_id
+    );
+
+    let manager = app
+        .get_or_create_manager(session_id, project_id, PathBuf::from(&project_path))
+        .await
+        .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
+
+    manager
+        .restore_paths(&checkpoint_id, paths.into_iter().map(PathBuf::from).collect())
+        .await
+        .map_err(|e| format!("Failed to restore paths: {}", e))
+}
+
 /// Lists all checkpoints for a session
 #[tauri::command]
 pub async fn list_checkpoints(
@@ -1647,6 +1753,167 @@ pub async fn list_checkpoints(
     Ok(manager.list_checkpoints().await)
 }
 
+/// Merges one checkpoint into another as a new checkpoint with both as parents
+#[tauri::command]
+pub async fn merge_checkpoints(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    session_id: String,
+    project_id: String,
+    project_path: String,
+    ours_checkpoint_id: String,
+    theirs_checkpoint_id: String,
+    description: Option<String>,
+    allow_conflicts: Option<bool>,
+) -> Result<crate::checkpoint::merge::MergeResult, String> {
+    log::info!(
+        "Merging checkpoint {} into {} in session: {}",
+        theirs_checkpoint_id,
+        ours_checkpoint_id,
+        session_id
+    );
+
+    let manager = app
+        .get_or_create_manager(session_id, project_id, PathBuf::from(&project_path))
+        .await
+        .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
+
+    manager
+        .merge_checkpoints(
+            &ours_checkpoint_id,
+            &theirs_checkpoint_id,
+            description,
+            allow_conflicts.unwrap_or(false),
+        )
+        .await
+        .map_err(|e| format!("Failed to merge checkpoints: {}", e))
+}
+
+/// Searches checkpoints across every session of a project
+#[tauri::command]
+pub async fn search_checkpoints(
+    project_id: String,
+    query: crate::checkpoint::search::CheckpointQuery,
+) -> Result<Vec<crate::checkpoint::search::CheckpointSearchHit>, String> {
+    use crate::checkpoint::storage::CheckpointStorage;
+
+    log::info!("Searching checkpoints in project: {}", project_id);
+
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let storage = CheckpointStorage::new(claude_dir);
+
+    storage
+        .search_checkpoints(&project_id, &query)
+        .map_err(|e| format!("Failed to search checkpoints: {}", e))
+}
+
+/// Gets the rules deciding when the Smart strategy checkpoints in a project
+#[tauri::command]
+pub async fn get_smart_checkpoint_rules(
+    project_id: String,
+) -> Result<crate::checkpoint::rules::SmartRules, String> {
+    use crate::checkpoint::storage::CheckpointStorage;
+
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let storage = CheckpointStorage::new(claude_dir);
+
+    storage
+        .load_smart_rules(&project_id)
+        .map_err(|e| format!("Failed to load Smart checkpoint rules: {}", e))
+}
+
+/// Replaces the rules deciding when the Smart strategy checkpoints in a project
+#[tauri::command]
+pub async fn update_smart_checkpoint_rules(
+    project_id: String,
+    rules: crate::checkpoint::rules::SmartRules,
+) -> Result<(), String> {
+    use crate::checkpoint::storage::CheckpointStorage;
+
+    log::info!("Updating Smart checkpoint rules for project: {}", project_id);
+
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let storage = CheckpointStorage::new(claude_dir);
+
+    storage
+        .save_smart_rules(&project_id, &rules)
+        .map_err(|e| format!("Failed to save Smart checkpoint rules: {}", e))
+}
+
+/// Reports whether checkpoint data is encrypted at rest
+#[tauri::command]
+pub async fn get_checkpoint_encryption(
+) -> Result<crate::checkpoint::crypto::EncryptionStatus, String> {
+    use crate::checkpoint::storage::CheckpointStorage;
+
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let storage = CheckpointStorage::new(claude_dir);
+
+    storage
+        .encryption_status()
+        .map_err(|e| format!("Failed to read checkpoint encryption status: {}", e))
+}
+
+/// Turns checkpoint encryption on or off, rewriting existing checkpoint data to match
+#[tauri::command]
+pub async fn set_checkpoint_encryption(
+    enabled: bool,
+) -> Result<crate::checkpoint::crypto::KeyRotationResult, String> {
+    use crate::checkpoint::storage::CheckpointStorage;
+
+    log::info!("Setting checkpoint encryption: {}", enabled);
+
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let storage = CheckpointStorage::new(claude_dir);
+
+    storage
+        .set_encryption(enabled)
+        .map_err(|e| format!("Failed to set checkpoint encryption: {}", e))
+}
+
+/// Re-encrypts all checkpoint data with a new key and retires the old ones
+#[tauri::command]
+pub async fn rotate_checkpoint_encryption_key(
+) -> Result<crate::checkpoint::crypto::KeyRotationResult, String> {
+    use crate::checkpoint::storage::CheckpointStorage;
+
+    log::info!("Rotating checkpoint encryption key");
+
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let storage = CheckpointStorage::new(claude_dir);
+
+    storage
+        .rotate_encryption_key()
+        .map_err(|e| format!("Failed to rotate checkpoint encryption key: {}", e))
+}
+
+/// Renames a checkpoint and/or replaces its tags
+#[tauri::command]
+pub async fn update_checkpoint_labels(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    checkpoint_id: String,
+    session_id: String,
+    project_id: String,
+    project_path: String,
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+) -> Result<crate::checkpoint::Checkpoint, String> {
+    log::info!(
+        "Updating labels of checkpoint: {} in session: {}",
+        checkpoint_id,
+        session_id
+    );
+
+    let manager = app
+        .get_or_create_manager(session_id, project_id, PathBuf::from(&project_path))
+        .await
+        .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
+
+    manager
+        .update_checkpoint_labels(&checkpoint_id, description, tags)
+        .await
+        .map_err(|e| format!("Failed to update checkpoint labels: {}", e))
+}
+
 /// Forks a new timeline branch from a checkpoint
 #[tauri::command]
 pub async fn fork_from_checkpoint(
@@ -1728,6 +1995,7 @@ pub async fn update_checkpoint_settings(
     project_path: String,
     auto_checkpoint_enabled: bool,
     checkpoint_strategy: String,
+    max_file_size: Option<u64>,
 ) -> Result<(), String> {
     use crate::checkpoint::CheckpointStrategy;
 
@@ -1752,7 +2020,7 @@ pub async fn update_checkpoint_settings(
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
     manager
-        .update_settings(auto_checkpoint_enabled, strategy)
+        .update_settings(auto_checkpoint_enabled, strategy, max_file_size)
         .await
         .map_err(|e| format!("Failed to update settings: {}", e))
 }
@@ -1764,7 +2032,9 @@ pub async fn get_checkpoint_diff(
     to_checkpoint_id: String,
     session_id: String,
     project_id: String,
+    context_lines: Option<usize>,
 ) -> Result<crate::checkpoint::CheckpointDiff, String> {
+    use crate::checkpoint::diff;
     use crate::checkpoint::storage::CheckpointStorage;
 
     log::info!(
@@ -1776,59 +2046,16 @@ pub async fn get_checkpoint_diff(
     let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
     let storage = CheckpointStorage::new(claude_dir);
 
-    // Load both checkpoints
-    let (from_checkpoint, from_files, _) = storage
-        .load_checkpoint(&project_id, &session_id, &from_checkpoint_id)
-        .map_err(|e| format!("Failed to load source checkpoint: {}", e))?;
-    let (to_checkpoint, to_files, _) = storage
-        .load_checkpoint(&project_id, &session_id, &to_checkpoint_id)
-        .map_err(|e| format!("Failed to load target checkpoint: {}", e))?;
-
-    // Build file maps
-    let mut from_map: std::collections::HashMap<PathBuf, &crate::checkpoint::FileSnapshot> =
-        std::collections::HashMap::new();
-    for file in &from_files {
-        from_map.insert(file.file_path.clone(), file);
-    }
-
-    let mut to_map: std::collections::HashMap<PathBuf, &crate::checkpoint::FileSnapshot> =
-        std::collections::HashMap::new();
-    for file in &to_files {
-        to_map.insert(file.file_path.clone(), file);
-    }
-
-    // Calculate differences
-    let mut modified_files = Vec::new();
-    let mut added_files = Vec::new();
-    let mut deleted_files = Vec::new();
-
-    // Check for modified and deleted files
-    for (path, from_file) in &from_map {
-        if let Some(to_file) = to_map.get(path) {
-            if from_file.hash != to_file.hash {
-                // File was modified
-                let additions = to_file.content.lines().count();
-                let deletions = from_file.content.lines().count();
-
-                modified_files.push(crate::checkpoint::FileDiff {
-                    path: path.clone(),
-                    additions,
-                    deletions,
-                    diff_content: None, // TODO: Generate actual diff
-                });
-            }
-        } else {
-            // File was deleted
-            deleted_files.push(path.clone());
-        }
-    }
-
-    // Check for added files
-    for (path, _) in &to_map {
-        if !from_map.contains_key(path) {
-            added_files.push(path.clone());
-        }
-    }
+    // Load both checkpoints and calculate line-level differences
+    let (from_checkpoint, to_checkpoint, snapshot_diff) = storage
+        .diff_checkpoints(
+            &project_id,
+            &session_id,
+            &from_checkpoint_id,
+            &to_checkpoint_id,
+            context_lines.unwrap_or(diff::DEFAULT_CONTEXT_LINES),
+        )
+        .map_err(|e| format!("Failed to diff checkpoints: {}", e))?;
 
     // Calculate token delta
     let token_delta = (to_checkpoint.metadata.total_tokens as i64)
@@ -1837,13 +2064,98 @@ pub async fn get_checkpoint_diff(
     Ok(crate::checkpoint::CheckpointDiff {
         from_checkpoint_id,
         to_checkpoint_id,
-        modified_files,
-        added_files,
-        deleted_files,
+        modified_files: snapshot_diff.modified_files,
+        added_files: snapshot_diff.added_files,
+        deleted_files: snapshot_diff.deleted_files,
         token_delta,
     })
 }
 
+/// Gets diff between a checkpoint and the current working tree
+#[tauri::command]
+pub async fn get_working_tree_diff(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    checkpoint_id: String,
+    session_id: String,
+    project_id: String,
+    project_path: String,
+    context_lines: Option<usize>,
+) -> Result<crate::checkpoint::CheckpointDiff, String> {
+    use crate::checkpoint::diff;
+
+    log::info!(
+        "Getting diff between checkpoint {} and working tree",
+        checkpoint_id
+    );
+
+    let manager = app
+        .get_or_create_manager(session_id, project_id, PathBuf::from(&project_path))
+        .await
+        .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
+
+    manager
+        .diff_with_working_tree(
+            &checkpoint_id,
+            context_lines.unwrap_or(diff::DEFAULT_CONTEXT_LINES),
+        )
+        .await
+        .map_err(|e| format!("Failed to diff working tree: {}", e))
+}
+
+/// Exports a checkpoint, or a session's whole timeline, into a portable bundle file
+#[tauri::command]
+pub async fn export_checkpoint_bundle(
+    session_id: String,
+    project_id: String,
+    checkpoint_id: Option<String>,
+    output_path: String,
+) -> Result<crate::checkpoint::bundle::BundleManifest, String> {
+    use crate::checkpoint::storage::CheckpointStorage;
+
+    log::info!(
+        "Exporting checkpoint bundle for session: {} to {}",
+        session_id,
+        output_path
+    );
+
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let storage = CheckpointStorage::new(claude_dir);
+
+    storage
+        .export_bundle(
+            &project_id,
+            &session_id,
+            checkpoint_id.as_deref(),
+            &PathBuf::from(&output_path),
+        )
+        .map_err(|e| format!("Failed to export checkpoint bundle: {}", e))
+}
+
+/// Imports a checkpoint bundle as a new session timeline in a project
+#[tauri::command]
+pub async fn import_checkpoint_bundle(
+    bundle_path: String,
+    project_id: String,
+    session_id: Option<String>,
+) -> Result<crate::checkpoint::bundle::BundleImportResult, String> {
+    use crate::checkpoint::storage::CheckpointStorage;
+
+    let session_id = session_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    log::info!(
+        "Importing checkpoint bundle {} into project: {} as session: {}",
+        bundle_path,
+        project_id,
+        session_id
+    );
+
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let storage = CheckpointStorage::new(claude_dir);
+
+    storage
+        .import_bundle(&PathBuf::from(&bundle_path), &project_id, &session_id)
+        .map_err(|e| format!("Failed to import checkpoint bundle: {}", e))
+}
+
 /// Tracks a message for checkpointing
 #[tauri::command]
 pub async fn track_checkpoint_message(
@@ -1909,10 +2221,94 @@ pub async fn cleanup_old_checkpoints(
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
-    manager
+    let removed = manager
         .storage
         .cleanup_old_checkpoints(&project_id, &session_id, keep_count)
-        .map_err(|e| format!("Failed to cleanup checkpoints: {}", e))
+        .map_err(|e| format!("Failed to cleanup checkpoints: {}", e))?;
+
+    manager
+        .reload_timeline()
+        .await
+        .map_err(|e| format!("Failed to reload timeline: {}", e))?;
+
+    Ok(removed)
+}
+
+/// Sets where new checkpoints for a session store file contents ("native" or "git")
+#[tauri::command]
+pub async fn set_checkpoint_backend(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    session_id: String,
+    project_id: String,
+    project_path: String,
+    backend: String,
+) -> Result<(), String> {
+    use crate::checkpoint::CheckpointBackend;
+
+    log::info!(
+        "Setting checkpoint backend for session {} to {}",
+        session_id,
+        backend
+    );
+
+    let backend = match backend.as_str() {
+        "native" => CheckpointBackend::Native,
+        "git" => CheckpointBackend::Git,
+        _ => return Err(format!("Invalid checkpoint backend: {}", backend)),
+    };
+
+    let manager = app
+        .get_or_create_manager(session_id, project_id, PathBuf::from(project_path))
+        .await
+        .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
+
+    manager
+        .set_backend(backend)
+        .await
+        .map_err(|e| format!("Failed to set checkpoint backend: {}", e))
+}
+
+/// Applies the session's checkpoint retention policy immediately
+#[tauri::command]
+pub async fn apply_checkpoint_retention(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    session_id: String,
+    project_id: String,
+    project_path: String,
+) -> Result<crate::checkpoint::retention::RetentionResult, String> {
+    log::info!("Applying checkpoint retention for session: {}", session_id);
+
+    let manager = app
+        .get_or_create_manager(session_id, project_id, PathBuf::from(project_path))
+        .await
+        .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
+
+    manager
+        .apply_retention()
+        .await
+        .map_err(|e| format!("Failed to apply retention policy: {}", e))
+}
+
+/// Updates the checkpoint retention policy for a session
+#[tauri::command]
+pub async fn update_retention_policy(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    session_id: String,
+    project_id: String,
+    project_path: String,
+    policy: crate::checkpoint::retention::RetentionPolicy,
+) -> Result<(), String> {
+    log::info!("Updating retention policy for session: {}", session_id);
+
+    let manager = app
+        .get_or_create_manager(session_id, project_id, PathBuf::from(project_path))
+        .await
+        .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
+
+    manager
+        .update_retention_policy(policy)
+        .await
+        .map_err(|e| format!("Failed to update retention policy: {}", e))
 }
 
 /// Gets checkpoint settings for a session
@@ -1937,6 +2333,9 @@ pub async fn get_checkpoint_settings(
         "checkpoint_strategy": timeline.checkpoint_strategy,
         "total_checkpoints": timeline.total_checkpoints,
         "current_checkpoint_id": timeline.current_checkpoint_id,
+        "max_file_size": timeline.max_file_size,
+        "retention": timeline.retention,
+        "backend": timeline.backend,
     }))
 }
 
@@ -286,6 +286,7 @@ pub async fn storage_update_row(
 #[allow(non_snake_case)]
 pub async fn storage_delete_row(
     db: State<'_, AgentDb>,
+    registry: State<'_, crate::process::ProcessRegistryState>,
     tableName: String,
     primaryKeyValues: HashMap<String, JsonValue>,
 ) -> Result<(), String> {
@@ -315,9 +316,28 @@ pub async fn storage_delete_row(
         .map(json_to_sql_value)
         .collect::<Result<Vec<_>, _>>()?;
     
+    // Foreign keys aren't enforced, so a run's checkpoint links go with it by hand
+    if tableName == "agent_runs" {
+        if let Some(run_id) = primaryKeyValues.get("id") {
+            let run_id = json_to_sql_value(run_id)?;
+            conn.execute(
+                "DELETE FROM agent_run_checkpoints WHERE run_id = ?1",
+                [run_id.as_ref()],
+            )
+            .map_err(|e| format!("Failed to delete run checkpoints: {}", e))?;
+        }
+    }
+
     // Execute delete
     conn.execute(&query, rusqlite::params_from_iter(params.iter().map(|p| p.as_ref())))
         .map_err(|e| format!("Failed to delete row: {}", e))?;
+
+    // A run's output log goes with its record
+    if tableName == "agent_runs" {
+        if let Some(run_id) = primaryKeyValues.get("id").and_then(JsonValue::as_i64) {
+            registry.0.remove_output_log(run_id);
+        }
+    }
     
     Ok(())
 }
@@ -443,6 +463,8 @@ pub async fn storage_reset_database(app: AppHandle) -> Result<(), String> {
             .map_err(|e| format!("Failed to disable foreign keys: {}", e))?;
         
         // Drop tables - order doesn't matter with foreign keys disabled
+        conn.execute("DROP TABLE IF EXISTS agent_run_checkpoints", [])
+            .map_err(|e| format!("Failed to drop agent_run_checkpoints table: {}", e))?;
         conn.execute("DROP TABLE IF EXISTS agent_runs", [])
             .map_err(|e| format!("Failed to drop agent_runs table: {}", e))?;
         conn.execute("DROP TABLE IF EXISTS agents", [])
@@ -476,6 +498,11 @@ pub async fn storage_reset_database(app: AppHandle) -> Result<(), String> {
         conn.execute("VACUUM", [])
             .map_err(|e| e.to_string())?;
     }
+
+    // The run records are gone, so their output logs go too
+    app.state::<crate::process::ProcessRegistryState>()
+        .0
+        .remove_stale_output_logs();
     
     Ok(())
 }
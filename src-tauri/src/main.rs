@@ -11,24 +11,36 @@ use commands::agents::{
     cleanup_finished_processes, create_agent, delete_agent, execute_agent, export_agent,
     export_agent_to_file, fetch_github_agent_content, fetch_github_agents, get_agent,
     get_agent_run, get_agent_run_with_real_time_metrics, get_claude_binary_path,
-    get_live_session_output, get_session_output, get_session_status, import_agent,
+    get_live_output_buffer_limit, get_live_session_output, get_session_output,
+    get_session_status, import_agent, load_live_output_buffer_limit, read_live_session_output,
+    set_live_output_buffer_limit, get_process_kill_grace_ms, load_process_kill_grace_ms,
+    set_process_kill_grace_ms,
     import_agent_from_file, import_agent_from_github, init_database, kill_agent_session,
-    list_agent_runs, list_agent_runs_with_metrics, list_agents, list_claude_installations,
+    list_agent_run_checkpoints, list_agent_runs, list_agent_runs_with_metrics, list_agents,
+    list_claude_installations,
     list_running_sessions, load_agent_session_history, set_claude_binary_path, stream_session_output, update_agent, AgentDb,
 };
 use commands::lm_studio::{fetch_lm_studio_models, test_lm_studio_connection};
 use commands::claude::{
-    cancel_claude_execution, check_auto_checkpoint, check_claude_version, cleanup_old_checkpoints,
-    clear_checkpoint_manager, continue_claude_code, create_checkpoint, create_project, execute_claude_code,
+    apply_checkpoint_retention, cancel_claude_execution, check_auto_checkpoint, check_claude_version,
+    check_restore_conflicts,
+    cleanup_old_checkpoints, clear_checkpoint_manager, continue_claude_code, create_checkpoint,
+    create_project, execute_claude_code, export_checkpoint_bundle, import_checkpoint_bundle,
     find_claude_md_files, fork_from_checkpoint, get_checkpoint_diff, get_checkpoint_settings,
     get_checkpoint_state_stats, get_claude_session_output, get_claude_settings, get_home_directory, get_project_sessions,
-    get_recently_modified_files, get_session_timeline, get_system_prompt, list_checkpoints,
-    list_directory_contents, list_projects, list_running_claude_sessions, load_session_history,
-    open_new_session, read_claude_md_file, restore_checkpoint, resume_claude_code,
-    save_claude_md_file, save_claude_settings, save_system_prompt, search_files,
-    track_checkpoint_message, track_session_messages, update_checkpoint_settings,
+    get_recently_modified_files, get_session_timeline, get_system_prompt, get_working_tree_diff,
+    list_checkpoints, list_directory_contents, list_projects, list_running_claude_sessions,
+    load_session_history, merge_checkpoints, open_new_session, preview_restore_checkpoint,
+    read_claude_md_file,
+    restore_checkpoint, restore_checkpoint_paths, resume_claude_code, set_checkpoint_backend,
+    save_claude_md_file, save_claude_settings, save_system_prompt, search_checkpoints,
+    search_files, get_smart_checkpoint_rules, update_smart_checkpoint_rules,
+    get_checkpoint_encryption, set_checkpoint_encryption, rotate_checkpoint_encryption_key,
+    read_claude_session_output,
+    track_checkpoint_message, track_session_messages, update_checkpoint_labels,
+    update_checkpoint_settings, update_retention_policy,
+    verify_checkpoint_storage,
     get_hooks_config, update_hooks_config, validate_hook_command,
-    ClaudeProcessState,
 };
 use commands::mcp::{
     mcp_add, mcp_add_from_claude_desktop, mcp_add_json, mcp_get, mcp_get_server_status, mcp_list,
@@ -112,6 +124,8 @@ fn main() {
             
             // Re-open the connection for the app to manage
             let conn = init_database(&app.handle()).expect("Failed to initialize agents database");
+            let live_output_limit = load_live_output_buffer_limit(&conn);
+            let kill_grace_ms = load_process_kill_grace_ms(&conn);
             app.manage(AgentDb(Mutex::new(conn)));
 
             // Initialize checkpoint state
@@ -135,11 +149,21 @@ fn main() {
 
             app.manage(checkpoint_state);
 
-            // Initialize process registry
-            app.manage(ProcessRegistryState::default());
-
-            // Initialize Claude process state
-            app.manage(ClaudeProcessState::default());
+            // Initialize process registry, reattaching processes that outlived the last run
+            let registry_db = app
+                .path()
+                .app_data_dir()
+                .expect("Failed to get app data dir")
+                .join("agents.db");
+            let registry_state = ProcessRegistryState::with_database(registry_db);
+            let registry = registry_state.0.clone();
+            let _ = registry.set_output_buffer_limit(live_output_limit);
+            registry.set_kill_grace_period(std::time::Duration::from_millis(kill_grace_ms));
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                registry.reattach_processes(app_handle).await;
+            });
+            app.manage(registry_state);
 
             // Apply window vibrancy with rounded corners on macOS
             #[cfg(target_os = "macos")]
@@ -194,6 +218,7 @@ fn main() {
             cancel_claude_execution,
             list_running_claude_sessions,
             get_claude_session_output,
+            read_claude_session_output,
             list_directory_contents,
             search_files,
             get_recently_modified_files,
@@ -204,15 +229,33 @@ fn main() {
             // Checkpoint Management
             create_checkpoint,
             restore_checkpoint,
+            preview_restore_checkpoint,
+            check_restore_conflicts,
+            restore_checkpoint_paths,
             list_checkpoints,
+            search_checkpoints,
+            get_smart_checkpoint_rules,
+            update_smart_checkpoint_rules,
+            get_checkpoint_encryption,
+            set_checkpoint_encryption,
+            rotate_checkpoint_encryption_key,
+            update_checkpoint_labels,
             fork_from_checkpoint,
+            merge_checkpoints,
             get_session_timeline,
             update_checkpoint_settings,
             get_checkpoint_diff,
+            get_working_tree_diff,
+            export_checkpoint_bundle,
+            import_checkpoint_bundle,
+            verify_checkpoint_storage,
             track_checkpoint_message,
             track_session_messages,
             check_auto_checkpoint,
             cleanup_old_checkpoints,
+            apply_checkpoint_retention,
+            update_retention_policy,
+            set_checkpoint_backend,
             get_checkpoint_settings,
             clear_checkpoint_manager,
             get_checkpoint_state_stats,
@@ -228,12 +271,18 @@ fn main() {
             get_agent_run,
             list_agent_runs_with_metrics,
             get_agent_run_with_real_time_metrics,
+            list_agent_run_checkpoints,
             list_running_sessions,
             kill_agent_session,
             get_session_status,
             cleanup_finished_processes,
             get_session_output,
             get_live_session_output,
+            read_live_session_output,
+            get_live_output_buffer_limit,
+            set_live_output_buffer_limit,
+            get_process_kill_grace_ms,
+            set_process_kill_grace_ms,
             stream_session_output,
             load_agent_session_history,
             get_claude_binary_path,
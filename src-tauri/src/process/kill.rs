@@ -0,0 +1,246 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How long a process group gets to exit after SIGTERM unless configured otherwise
+pub const DEFAULT_KILL_GRACE_MS: u64 = 5000;
+
+/// How long to wait for processes to disappear after SIGKILL
+const KILL_WAIT: Duration = Duration::from_secs(1);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Outcome of terminating a process together with everything it started
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KillReport {
+    pub pid: u32,
+    /// Whether the process itself is gone
+    pub exited: bool,
+    /// Whether anything outlived the grace period and had to be sent SIGKILL
+    pub escalated: bool,
+    /// Descendants that exited after SIGTERM
+    pub terminated: Vec<u32>,
+    /// Descendants that exited only after SIGKILL
+    pub killed: Vec<u32>,
+    /// Descendants still running after SIGKILL
+    pub survivors: Vec<u32>,
+}
+
+impl KillReport {
+    /// Descendants that are gone
+    pub fn reaped(&self) -> impl Iterator<Item = &u32> {
+        self.terminated.iter().chain(self.killed.iter())
+    }
+}
+
+/// Terminate a process and its descendants, escalating to SIGKILL after `grace`
+///
+/// Sessions are spawned as process group leaders, so the whole group is signalled;
+/// descendants that left the group are found through their parent links. Blocks until
+/// everything is gone or the SIGKILL wait runs out.
+#[cfg(unix)]
+pub fn terminate_process_tree(pid: u32, grace: Duration) -> KillReport {
+    let mut report = KillReport {
+        pid,
+        ..KillReport::default()
+    };
+    if pid == 0 {
+        return report;
+    }
+
+    let mut tracked = descendants(pid, &snapshot());
+    info!(
+        "Sending SIGTERM to process group {} ({} descendants)",
+        pid,
+        tracked.len()
+    );
+    signal_tree(pid, &tracked, libc::SIGTERM);
+
+    // Wait out the grace period, picking up anything spawned meanwhile
+    let deadline = Instant::now() + grace;
+    let mut alive = loop {
+        let processes = snapshot();
+        for descendant in descendants(pid, &processes) {
+            if !tracked.contains(&descendant) {
+                tracked.push(descendant);
+            }
+        }
+        let alive = still_alive(&tracked, &processes);
+        if (alive.is_empty() && !is_alive(pid, &processes)) || Instant::now() >= deadline {
+            break alive;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    report.terminated = tracked
+        .iter()
+        .copied()
+        .filter(|p| !alive.contains(p))
+        .collect();
+
+    if !alive.is_empty() || is_alive(pid, &snapshot()) {
+        warn!(
+            "Process group {} still running after {:?}, sending SIGKILL",
+            pid, grace
+        );
+        report.escalated = true;
+        signal_tree(pid, &alive, libc::SIGKILL);
+
+        let deadline = Instant::now() + KILL_WAIT;
+        let remaining = loop {
+            let processes = snapshot();
+            let remaining = still_alive(&alive, &processes);
+            if (remaining.is_empty() && !is_alive(pid, &processes)) || Instant::now() >= deadline {
+                break remaining;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+        report.killed = alive
+            .iter()
+            .copied()
+            .filter(|p| !remaining.contains(p))
+            .collect();
+        alive = remaining;
+    }
+
+    report.survivors = alive;
+    report.exited = !is_alive(pid, &snapshot());
+    info!(
+        "Terminated process {}: exited={}, reaped descendants {:?}, survivors {:?}",
+        pid,
+        report.exited,
+        report.reaped().collect::<Vec<_>>(),
+        report.survivors
+    );
+    report
+}
+
+/// Terminate a process tree, escalating to a forced kill after `grace`
+#[cfg(windows)]
+pub fn terminate_process_tree(pid: u32, grace: Duration) -> KillReport {
+    let mut report = KillReport {
+        pid,
+        ..KillReport::default()
+    };
+
+    let _ = std::process::Command::new("taskkill")
+        .args(["/T", "/PID", &pid.to_string()])
+        .output();
+    let deadline = Instant::now() + grace;
+    while is_running_windows(pid) && Instant::now() < deadline {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    if is_running_windows(pid) {
+        warn!(
+            "Process tree {} still running after {:?}, forcing it",
+            pid, grace
+        );
+        report.escalated = true;
+        let _ = std::process::Command::new("taskkill")
+            .args(["/F", "/T", "/PID", &pid.to_string()])
+            .output();
+    }
+    report.exited = !is_running_windows(pid);
+    report
+}
+
+#[cfg(windows)]
+fn is_running_windows(pid: u32) -> bool {
+    match std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid)])
+        .args(["/FO", "CSV"])
+        .output()
+    {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).lines().count() > 1,
+        Err(_) => false,
+    }
+}
+
+/// One row of the process table
+#[cfg(unix)]
+struct ProcessEntry {
+    pid: u32,
+    ppid: u32,
+    pgid: u32,
+    zombie: bool,
+}
+
+/// The current process table, or nothing if ps can't be run
+#[cfg(unix)]
+fn snapshot() -> Vec<ProcessEntry> {
+    let output = match std::process::Command::new("ps")
+        .args(["-A", "-o", "pid=,ppid=,pgid=,stat="])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            warn!("Failed to list processes: {}", e);
+            return Vec::new();
+        }
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(ProcessEntry {
+                pid: fields.next()?.parse().ok()?,
+                ppid: fields.next()?.parse().ok()?,
+                pgid: fields.next()?.parse().ok()?,
+                zombie: fields.next().unwrap_or("").starts_with('Z'),
+            })
+        })
+        .collect()
+}
+
+/// Live processes in `pid`'s group or below it in the process tree, excluding `pid` itself
+#[cfg(unix)]
+fn descendants(pid: u32, processes: &[ProcessEntry]) -> Vec<u32> {
+    let mut found: Vec<u32> = processes
+        .iter()
+        .filter(|p| p.pgid == pid && p.pid != pid && !p.zombie)
+        .map(|p| p.pid)
+        .collect();
+
+    let mut frontier = vec![pid];
+    frontier.extend(found.iter().copied());
+    while let Some(parent) = frontier.pop() {
+        for child in processes.iter().filter(|p| p.ppid == parent && !p.zombie) {
+            if child.pid != pid && !found.contains(&child.pid) {
+                found.push(child.pid);
+                frontier.push(child.pid);
+            }
+        }
+    }
+    found
+}
+
+#[cfg(unix)]
+fn still_alive(pids: &[u32], processes: &[ProcessEntry]) -> Vec<u32> {
+    pids.iter()
+        .copied()
+        .filter(|pid| is_alive(*pid, processes))
+        .collect()
+}
+
+/// Whether a process is running; zombies only wait to be reaped and don't count
+#[cfg(unix)]
+fn is_alive(pid: u32, processes: &[ProcessEntry]) -> bool {
+    if processes.is_empty() {
+        // No process table; fall back to probing the PID
+        return unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    }
+    processes.iter().any(|p| p.pid == pid && !p.zombie)
+}
+
+/// Signal the process group led by `pid`, `pid` itself and descendants outside the group
+#[cfg(unix)]
+fn signal_tree(pid: u32, descendants: &[u32], signal: libc::c_int) {
+    unsafe {
+        libc::killpg(pid as libc::pid_t, signal);
+        libc::kill(pid as libc::pid_t, signal);
+        for descendant in descendants {
+            libc::kill(*descendant as libc::pid_t, signal);
+        }
+    }
+}
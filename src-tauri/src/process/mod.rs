@@ -1,3 +1,7 @@
+pub mod kill;
+pub mod output;
+pub mod persistence;
 pub mod registry;
 
+pub use output::OutputChunk;
 pub use registry::*;
@@ -0,0 +1,346 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Bytes of live output kept in memory per process unless configured otherwise
+pub const DEFAULT_OUTPUT_BUFFER_BYTES: usize = 1024 * 1024;
+
+/// Lines returned by a cursor read when the caller sets no limit
+pub const DEFAULT_OUTPUT_READ_LIMIT: usize = 1000;
+
+/// A slice of a process's output, read from a line cursor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputChunk {
+    /// Line number of the first returned line; above the requested offset if lines were lost
+    pub offset: u64,
+    /// Cursor to pass to the next read
+    pub next_offset: u64,
+    /// Lines produced by the process so far
+    pub total_lines: u64,
+    pub lines: Vec<String>,
+}
+
+impl OutputChunk {
+    /// Nothing read, e.g. because the process isn't registered
+    pub fn empty(offset: u64) -> Self {
+        Self {
+            offset,
+            next_offset: offset,
+            total_lines: 0,
+            lines: Vec::new(),
+        }
+    }
+}
+
+/// Lines between entries of a log's line index
+const INDEX_STRIDE: u64 = 256;
+
+/// Live output of one process: the most recent lines in memory, every line on disk
+///
+/// Lines evicted from memory once `max_bytes` is exceeded are served from the log file,
+/// which outlives the buffer so a finished run's output can still be read.
+pub struct LiveOutput {
+    lines: VecDeque<String>,
+    /// Line number of the oldest line still in memory
+    first_line: u64,
+    total_lines: u64,
+    bytes: usize,
+    max_bytes: usize,
+    log_file: Option<PathBuf>,
+    log: Option<BufWriter<File>>,
+    /// Log offset of every `INDEX_STRIDE`th line, so reads seek near the lines they want
+    index: Vec<u64>,
+    /// Bytes written to the log so far
+    log_bytes: u64,
+}
+
+impl LiveOutput {
+    /// Buffer output in memory, spilling to `log_file` if one is given
+    pub fn new(max_bytes: usize, log_file: Option<PathBuf>) -> Self {
+        Self::open(max_bytes, log_file, false)
+    }
+
+    /// Continue the output in an existing log file, e.g. after the app restarted
+    ///
+    /// Lines already in the log count as output, and the newest of them are held in memory
+    /// again. A missing log is started afresh.
+    pub fn reopen(max_bytes: usize, log_file: PathBuf) -> Self {
+        let mut output = Self::open(max_bytes, Some(log_file), true);
+        let Some(file) = output
+            .log_file
+            .as_ref()
+            .and_then(|path| File::open(path).ok())
+        else {
+            return output;
+        };
+
+        let mut reader = BufReader::new(file);
+        let mut line = Vec::new();
+        let mut unterminated = false;
+        while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+            output.index_line(line.len() as u64);
+            unterminated = line.last() != Some(&b'\n');
+            let text = String::from_utf8_lossy(&line);
+            output.buffer(text.trim_end_matches('\n').to_string());
+            line.clear();
+        }
+
+        // A line cut short by a crash ends where the next one starts
+        if unterminated {
+            if let Some(log) = output.log.as_mut() {
+                if writeln!(log).is_ok() {
+                    output.log_bytes += 1;
+                }
+            }
+        }
+        output
+    }
+
+    fn open(max_bytes: usize, log_file: Option<PathBuf>, append: bool) -> Self {
+        let log = log_file.as_ref().and_then(|path| {
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            let opened = if append {
+                File::options().create(true).append(true).open(path)
+            } else {
+                File::create(path)
+            };
+            match opened {
+                Ok(file) => Some(BufWriter::new(file)),
+                Err(e) => {
+                    log::warn!("Failed to open output log {}: {}", path.display(), e);
+                    None
+                }
+            }
+        });
+        Self {
+            lines: VecDeque::new(),
+            first_line: 0,
+            total_lines: 0,
+            bytes: 0,
+            max_bytes,
+            log_file: log.as_ref().and(log_file),
+            log,
+            index: Vec::new(),
+            log_bytes: 0,
+        }
+    }
+
+    pub fn push(&mut self, line: &str) {
+        if let Some(log) = self.log.as_mut() {
+            match writeln!(log, "{}", line) {
+                Ok(()) => self.index_line(line.len() as u64 + 1),
+                Err(e) => {
+                    log::warn!(
+                        "Failed to write output log, keeping output in memory only: {}",
+                        e
+                    );
+                    self.log = None;
+                }
+            }
+        }
+        self.buffer(line.to_string());
+    }
+
+    /// Account for a line of `len` bytes written to the log, before it is buffered
+    fn index_line(&mut self, len: u64) {
+        if self.total_lines % INDEX_STRIDE == 0 {
+            self.index.push(self.log_bytes);
+        }
+        self.log_bytes += len;
+    }
+
+    /// Hold a line in memory, without logging it
+    fn buffer(&mut self, line: String) {
+        self.bytes += line.len() + 1;
+        self.lines.push_back(line);
+        self.total_lines += 1;
+        self.trim();
+    }
+
+    /// Change the memory cap, evicting old lines if the buffer is now over it
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        // Always keep the latest line, however long
+        while self.bytes > self.max_bytes && self.lines.len() > 1 {
+            if let Some(evicted) = self.lines.pop_front() {
+                self.bytes -= evicted.len() + 1;
+                self.first_line += 1;
+            }
+        }
+    }
+
+    /// Up to `limit` lines starting at line `offset`
+    pub fn read(&mut self, offset: u64, limit: usize) -> OutputChunk {
+        let start = offset.min(self.total_lines);
+        let mut first = start;
+        let mut lines = Vec::new();
+
+        if start < self.first_line {
+            let wanted = limit.min((self.first_line - start) as usize);
+            match self.read_spilled(start, wanted) {
+                Some(spilled) => lines = spilled,
+                // Evicted lines are gone; resume at the oldest line still held
+                None => first = self.first_line,
+            }
+        }
+
+        let next = first + lines.len() as u64;
+        if next >= self.first_line {
+            let skip = (next - self.first_line) as usize;
+            let remaining = limit.saturating_sub(lines.len());
+            lines.extend(self.lines.iter().skip(skip).take(remaining).cloned());
+        }
+
+        OutputChunk {
+            offset: first,
+            next_offset: first + lines.len() as u64,
+            total_lines: self.total_lines,
+            lines,
+        }
+    }
+
+    /// The output still held in memory as one string, newline-terminated
+    ///
+    /// Lines already evicted to the log are left out, so this never touches the disk; use
+    /// `read` for the complete output.
+    pub fn contents(&self) -> String {
+        let mut contents = String::with_capacity(self.bytes);
+        for line in &self.lines {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        contents
+    }
+
+    /// Exactly `count` lines from the log file, or `None` if it can't supply them
+    fn read_spilled(&mut self, offset: u64, count: usize) -> Option<Vec<String>> {
+        let log = self.log.as_mut()?;
+        log.flush().ok()?;
+        let block = (offset / INDEX_STRIDE) as usize;
+        let mut file = File::open(self.log_file.as_ref()?).ok()?;
+        file.seek(SeekFrom::Start(*self.index.get(block)?)).ok()?;
+
+        let lines = BufReader::new(file)
+            .lines()
+            .skip((offset % INDEX_STRIDE) as usize)
+            .take(count)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        (lines.len() == count).then_some(lines)
+    }
+}
+
+/// Up to `limit` lines from line `offset` of a finished process's log
+///
+/// Nothing indexes a log once its process is gone, so the lines before `offset` are
+/// scanned. Returns `None` if the log can't be read.
+pub fn read_log(log_file: &Path, offset: u64, limit: usize) -> Option<OutputChunk> {
+    let file = File::open(log_file).ok()?;
+    let mut total_lines = 0u64;
+    let mut lines = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.ok()?;
+        if total_lines >= offset && lines.len() < limit {
+            lines.push(line);
+        }
+        total_lines += 1;
+    }
+
+    let first = offset.min(total_lines);
+    Some(OutputChunk {
+        offset: first,
+        next_offset: first + lines.len() as u64,
+        total_lines,
+        lines,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn numbered(count: u64) -> Vec<String> {
+        (0..count).map(|n| format!("line {}", n)).collect()
+    }
+
+    #[test]
+    fn test_eviction_keeps_newest_lines_in_memory() {
+        let mut output = LiveOutput::new(20, None);
+        for line in numbered(5) {
+            output.push(&line);
+        }
+
+        // Each line takes 7 bytes with its newline, so only two fit
+        assert_eq!(output.contents(), "line 3\nline 4\n");
+
+        // Without a log the evicted lines are gone, and reads resume at the oldest one left
+        let chunk = output.read(0, 10);
+        assert_eq!(chunk.offset, 3);
+        assert_eq!(chunk.next_offset, 5);
+        assert_eq!(chunk.total_lines, 5);
+        assert_eq!(chunk.lines, numbered(5)[3..]);
+
+        // The latest line is kept however long it is
+        output.set_max_bytes(1);
+        assert_eq!(output.contents(), "line 4\n");
+    }
+
+    #[test]
+    fn test_cursor_reads_span_log_and_memory() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut output = LiveOutput::new(64, Some(temp_dir.path().join("1.log")));
+        let all = numbered(1000);
+        for line in &all {
+            output.push(line);
+        }
+
+        // Starts inside the log, past its first index entry, and runs into memory
+        let chunk = output.read(300, 1000);
+        assert_eq!(chunk.offset, 300);
+        assert_eq!(chunk.next_offset, 1000);
+        assert_eq!(chunk.lines, all[300..]);
+
+        let chunk = output.read(513, 4);
+        assert_eq!(chunk.lines, all[513..517]);
+        assert_eq!(chunk.next_offset, 517);
+
+        // Reading at the end returns nothing new
+        let chunk = output.read(1000, 10);
+        assert!(chunk.lines.is_empty());
+        assert_eq!(chunk.next_offset, 1000);
+    }
+
+    #[test]
+    fn test_log_outlives_buffer_and_reopens() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_file = temp_dir.path().join("1.log");
+        let all = numbered(600);
+
+        let mut output = LiveOutput::new(64, Some(log_file.clone()));
+        for line in &all[..400] {
+            output.push(line);
+        }
+        drop(output);
+
+        let chunk = read_log(&log_file, 390, 5).unwrap();
+        assert_eq!(chunk.total_lines, 400);
+        assert_eq!(chunk.lines, all[390..395]);
+
+        let mut output = LiveOutput::reopen(64, log_file.clone());
+        for line in &all[400..] {
+            output.push(line);
+        }
+        let chunk = output.read(250, 400);
+        assert_eq!(chunk.total_lines, 600);
+        assert_eq!(chunk.lines, all[250..]);
+    }
+}
@@ -0,0 +1,625 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+
+use super::registry::{
+    ProcessHandle, ProcessInfo, ProcessRegistry, ProcessType, FIRST_GENERATED_ID,
+};
+
+/// How far a live process's start time may be from the recorded one for it to still be ours
+const START_TIME_TOLERANCE_SECS: i64 = 10;
+
+/// A registry entry read back from the database
+struct PersistedProcess {
+    info: ProcessInfo,
+    session_id: Option<String>,
+    /// Bytes of the session file already copied into the process's output log
+    output_offset: Option<u64>,
+    /// Whether the entry was rebuilt from `agent_runs` rather than read from the registry table
+    untracked: bool,
+}
+
+/// What became of a recorded process while the app was not running
+enum ProcessState {
+    Running,
+    Exited,
+    /// The PID is alive but was reused by a process started at another time
+    Replaced,
+    /// The PID is alive but no start time was recorded to tell whether it is still ours
+    Unverifiable,
+}
+
+impl ProcessRegistry {
+    /// Start generated IDs past every persisted one, so none is reused before reattaching
+    pub(super) fn seed_next_id(&self) {
+        let Some(conn) = self.open_database() else {
+            return;
+        };
+        // The table is missing until the agents database is first set up
+        let highest = conn
+            .query_row(
+                "SELECT MAX(run_id) FROM process_registry WHERE run_id >= ?1",
+                params![FIRST_GENERATED_ID],
+                |row| row.get::<_, Option<i64>>(0),
+            )
+            .ok()
+            .flatten();
+        if let (Some(highest), Ok(mut next_id)) = (highest, self.next_id.lock()) {
+            *next_id = (*next_id).max(highest + 1);
+        }
+    }
+
+    /// Write a registry entry to the database
+    pub(super) fn persist_process(&self, info: &ProcessInfo) {
+        let Some(conn) = self.open_database() else {
+            return;
+        };
+        let session_id = match &info.process_type {
+            ProcessType::ClaudeSession { session_id, .. } if !session_id.is_empty() => {
+                Some(session_id.clone())
+            }
+            _ => None,
+        };
+        let output_file = session_id
+            .as_deref()
+            .and_then(|sid| session_output_file(&info.project_path, sid))
+            .map(|path| path.to_string_lossy().to_string());
+        let process_type = match serde_json::to_string(&info.process_type) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!(
+                    "Failed to serialize process type for {}: {}",
+                    info.run_id, e
+                );
+                return;
+            }
+        };
+
+        if let Err(e) = conn.execute(
+            "INSERT OR REPLACE INTO process_registry
+             (run_id, process_type, pid, started_at, project_path, task, model, session_id, output_file)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                info.run_id,
+                process_type,
+                info.pid as i64,
+                info.started_at.to_rfc3339(),
+                info.project_path,
+                info.task,
+                info.model,
+                session_id,
+                output_file,
+            ],
+        ) {
+            warn!("Failed to persist process {}: {}", info.run_id, e);
+        }
+    }
+
+    /// Record a persisted entry's session ID and the session file its output goes to
+    pub(super) fn persist_session_id(&self, run_id: i64, project_path: &str, session_id: &str) {
+        let Some(conn) = self.open_database() else {
+            return;
+        };
+        let output_file = session_output_file(project_path, session_id)
+            .map(|path| path.to_string_lossy().to_string());
+        if let Err(e) = conn.execute(
+            "UPDATE process_registry SET session_id = ?1, output_file = ?2 WHERE run_id = ?3",
+            params![session_id, output_file, run_id],
+        ) {
+            warn!("Failed to persist session ID for process {}: {}", run_id, e);
+        }
+    }
+
+    /// Record how far a reattached process's session file has been followed
+    fn persist_output_offset(&self, run_id: i64, offset: u64) {
+        let Some(conn) = self.open_database() else {
+            return;
+        };
+        if let Err(e) = conn.execute(
+            "UPDATE process_registry SET output_offset = ?1 WHERE run_id = ?2",
+            params![offset as i64, run_id],
+        ) {
+            warn!(
+                "Failed to persist output offset for process {}: {}",
+                run_id, e
+            );
+        }
+    }
+
+    /// Remove a registry entry from the database
+    pub(super) fn forget_process(&self, run_id: i64) {
+        let Some(conn) = self.open_database() else {
+            return;
+        };
+        if let Err(e) = conn.execute(
+            "DELETE FROM process_registry WHERE run_id = ?1",
+            params![run_id],
+        ) {
+            warn!("Failed to remove persisted process {}: {}", run_id, e);
+        }
+    }
+
+    fn open_database(&self) -> Option<Connection> {
+        let db_path = self.db_path.as_ref()?;
+        match Connection::open(db_path) {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                warn!("Failed to open process registry database: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Reconcile the entries persisted before the app last stopped
+    ///
+    /// Processes that are still alive and started when recorded are registered again and
+    /// their output is tailed from the session JSONL. Agent runs whose process has gone, or
+    /// whose PID now belongs to another process, are marked failed with the reason. Output
+    /// logs of processes that weren't reattached are removed, unless they belong to an agent
+    /// run that is still on record.
+    pub async fn reattach_processes(self: Arc<Self>, app: AppHandle) {
+        let Some(conn) = self.open_database() else {
+            return;
+        };
+        let entries = match load_persisted(&conn) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to load persisted processes: {}", e);
+                return;
+            }
+        };
+
+        for entry in entries {
+            let run_id = entry.info.run_id;
+            let pid = entry.info.pid;
+
+            if let ProcessType::AgentRun { .. } = entry.info.process_type {
+                let status = conn
+                    .query_row(
+                        "SELECT status FROM agent_runs WHERE id = ?1",
+                        params![run_id],
+                        |row| row.get::<_, String>(0),
+                    )
+                    .unwrap_or_default();
+                if status != "running" {
+                    // The run finished before the app stopped
+                    self.forget_process(run_id);
+                    continue;
+                }
+            }
+
+            let reason = match process_state(pid, entry.info.started_at) {
+                ProcessState::Running => {
+                    self.reattach(entry, app.clone());
+                    continue;
+                }
+                ProcessState::Exited => {
+                    format!("Process {} exited while the app was not running", pid)
+                }
+                ProcessState::Replaced => {
+                    format!("Process {} was replaced by an unrelated process", pid)
+                }
+                ProcessState::Unverifiable => format!(
+                    "Process {} has no recorded start time, so it may be an unrelated process \
+                     that reused the PID",
+                    pid
+                ),
+            };
+
+            warn!("Dropping process {}: {}", run_id, reason);
+            if let ProcessType::AgentRun { .. } = entry.info.process_type {
+                if let Err(e) = conn.execute(
+                    "UPDATE agent_runs SET status = 'failed', completed_at = CURRENT_TIMESTAMP, failure_reason = ?1
+                     WHERE id = ?2 AND status = 'running'",
+                    params![reason, run_id],
+                ) {
+                    warn!("Failed to mark agent run {} as failed: {}", run_id, e);
+                }
+            }
+            self.forget_process(run_id);
+        }
+
+        self.remove_stale_output_logs();
+    }
+
+    /// Remove output logs of processes that are neither registered nor recorded agent runs
+    pub fn remove_stale_output_logs(&self) {
+        let Some(output_dir) = &self.output_dir else {
+            return;
+        };
+        let Ok(entries) = std::fs::read_dir(output_dir) else {
+            return;
+        };
+        let Some(conn) = self.open_database() else {
+            return;
+        };
+        let mut keep: HashSet<i64> = match self.processes.lock() {
+            Ok(processes) => processes.keys().copied().collect(),
+            Err(_) => return,
+        };
+        // Agent runs keep their logs for as long as their records exist
+        match agent_run_ids(&conn) {
+            Ok(run_ids) => keep.extend(run_ids),
+            Err(e) => {
+                warn!("Not removing output logs, agent runs unreadable: {}", e);
+                return;
+            }
+        }
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let run_id = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<i64>().ok());
+            if run_id.is_some_and(|run_id| !keep.contains(&run_id)) {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+    }
+
+    /// Register a surviving process again and follow its output
+    fn reattach(self: &Arc<Self>, entry: PersistedProcess, app: AppHandle) {
+        let run_id = entry.info.run_id;
+        info!("Reattaching process {} (PID: {})", run_id, entry.info.pid);
+
+        if entry.untracked {
+            self.persist_process(&entry.info);
+        }
+        if let Some(session_id) = &entry.session_id {
+            self.persist_session_id(run_id, &entry.info.project_path, session_id);
+        }
+
+        // Keep generated IDs clear of the reattached ones
+        if let Ok(mut next_id) = self.next_id.lock() {
+            if *next_id <= run_id && run_id >= FIRST_GENERATED_ID {
+                *next_id = run_id + 1;
+            }
+        }
+
+        if let Ok(mut processes) = self.processes.lock() {
+            processes.insert(
+                run_id,
+                ProcessHandle {
+                    info: entry.info.clone(),
+                    child: Arc::new(Mutex::new(None)), // Not our child any more; killed by PID
+                    live_output: self.reopen_live_output(run_id),
+                },
+            );
+        }
+
+        let output_file = entry
+            .session_id
+            .as_deref()
+            .and_then(|sid| session_output_file(&entry.info.project_path, sid));
+        let registry = self.clone();
+        tokio::spawn(async move {
+            registry
+                .follow_output(app, entry.info, output_file, entry.output_offset)
+                .await;
+        });
+    }
+
+    /// Tail a reattached process's session file until the process exits
+    ///
+    /// Following starts where the last follow of the file stopped, or at the end of the file
+    /// the first time, since earlier messages are already in the session's history.
+    async fn follow_output(
+        self: Arc<Self>,
+        app: AppHandle,
+        info: ProcessInfo,
+        output_file: Option<PathBuf>,
+        output_offset: Option<u64>,
+    ) {
+        let run_id = info.run_id;
+        let output_event = match &info.process_type {
+            ProcessType::AgentRun { .. } => format!("agent-output:{}", run_id),
+            ProcessType::ClaudeSession { session_id, .. } => {
+                format!("claude-output:{}", session_id)
+            }
+        };
+
+        let file_len = output_file
+            .as_ref()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map_or(0, |metadata| metadata.len());
+        let mut offset = output_offset
+            .filter(|offset| *offset <= file_len)
+            .unwrap_or(file_len);
+        let mut partial = String::new();
+        loop {
+            let alive = pid_is_alive(info.pid);
+            if let Some(path) = &output_file {
+                let lines = read_new_lines(path, &mut offset, &mut partial);
+                for line in &lines {
+                    let _ = self.append_live_output(run_id, line);
+                    let _ = app.emit(&output_event, line);
+                }
+                if !lines.is_empty() {
+                    // Only complete lines count as followed
+                    self.persist_output_offset(run_id, offset.saturating_sub(partial.len() as u64));
+                }
+            }
+            if !alive {
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        }
+
+        // A kill has already reported the outcome
+        let still_registered = self
+            .processes
+            .lock()
+            .map(|processes| processes.contains_key(&run_id))
+            .unwrap_or(false);
+        if !still_registered {
+            return;
+        }
+
+        info!("Reattached process {} (PID: {}) exited", run_id, info.pid);
+        match &info.process_type {
+            ProcessType::AgentRun { .. } => {
+                if let Some(conn) = self.open_database() {
+                    let _ = conn.execute(
+                        "UPDATE agent_runs SET status = 'completed', completed_at = CURRENT_TIMESTAMP WHERE id = ?1 AND status = 'running'",
+                        params![run_id],
+                    );
+                }
+                let _ = app.emit(&format!("agent-complete:{}", run_id), true);
+            }
+            ProcessType::ClaudeSession { session_id, .. } => {
+                let _ = app.emit(&format!("claude-complete:{}", session_id), true);
+            }
+        }
+        let _ = self.unregister_process(run_id);
+    }
+}
+
+/// Persisted registry entries, plus running agent runs the registry has no record of
+fn load_persisted(conn: &Connection) -> Result<Vec<PersistedProcess>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT run_id, process_type, pid, started_at, project_path, task, model, session_id,
+                    output_offset
+             FROM process_registry",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, Option<String>>(7)?,
+                row.get::<_, Option<i64>>(8)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut entries = Vec::new();
+    for (run_id, process_type, pid, started_at, project_path, task, model, session_id, offset) in
+        rows
+    {
+        let Ok(process_type) = serde_json::from_str::<ProcessType>(&process_type) else {
+            warn!("Skipping persisted process {} with unknown type", run_id);
+            continue;
+        };
+        let session_id = match (&process_type, session_id) {
+            (ProcessType::ClaudeSession { session_id, .. }, None) if !session_id.is_empty() => {
+                Some(session_id.clone())
+            }
+            (ProcessType::AgentRun { .. }, None) => agent_run_session_id(conn, run_id),
+            (_, session_id) => session_id,
+        };
+        entries.push(PersistedProcess {
+            info: ProcessInfo {
+                run_id,
+                process_type,
+                pid: pid as u32,
+                started_at: parse_started_at(&started_at),
+                project_path,
+                task,
+                model,
+            },
+            session_id,
+            output_offset: offset.map(|offset| offset as u64),
+            untracked: false,
+        });
+    }
+
+    // Runs started before their processes were persisted
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, agent_id, agent_name, pid, process_started_at, project_path, task, model, session_id
+             FROM agent_runs
+             WHERE status = 'running' AND pid IS NOT NULL
+               AND id NOT IN (SELECT run_id FROM process_registry)",
+        )
+        .map_err(|e| e.to_string())?;
+    let untracked = stmt
+        .query_map([], |row| {
+            let session_id: String = row.get(8)?;
+            Ok(PersistedProcess {
+                info: ProcessInfo {
+                    run_id: row.get(0)?,
+                    process_type: ProcessType::AgentRun {
+                        agent_id: row.get(1)?,
+                        agent_name: row.get(2)?,
+                    },
+                    pid: row.get::<_, i64>(3)? as u32,
+                    started_at: parse_started_at(
+                        &row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    ),
+                    project_path: row.get(5)?,
+                    task: row.get(6)?,
+                    model: row.get(7)?,
+                },
+                session_id: Some(session_id).filter(|sid| !sid.is_empty()),
+                output_offset: None,
+                untracked: true,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    entries.extend(untracked);
+
+    Ok(entries)
+}
+
+fn agent_run_ids(conn: &Connection) -> rusqlite::Result<Vec<i64>> {
+    let mut stmt = conn.prepare("SELECT id FROM agent_runs")?;
+    let run_ids = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<i64>, _>>()?;
+    Ok(run_ids)
+}
+
+fn agent_run_session_id(conn: &Connection, run_id: i64) -> Option<String> {
+    conn.query_row(
+        "SELECT session_id FROM agent_runs WHERE id = ?1",
+        params![run_id],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .filter(|sid| !sid.is_empty())
+}
+
+/// Missing or unreadable start times fall back to the epoch, so the process is never trusted
+fn parse_started_at(started_at: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(started_at)
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_default()
+}
+
+/// The JSONL file Claude writes a session's messages to
+fn session_output_file(project_path: &str, session_id: &str) -> Option<PathBuf> {
+    let encoded_project = project_path.replace('/', "-");
+    Some(
+        dirs::home_dir()?
+            .join(".claude")
+            .join("projects")
+            .join(encoded_project)
+            .join(format!("{}.jsonl", session_id)),
+    )
+}
+
+/// Complete lines appended to a file since `offset`, advancing it past them
+fn read_new_lines(path: &Path, offset: &mut u64, partial: &mut String) -> Vec<String> {
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return Vec::new();
+    };
+    let mut appended = Vec::new();
+    if file.seek(SeekFrom::Start(*offset)).is_err() || file.read_to_end(&mut appended).is_err() {
+        return Vec::new();
+    }
+    *offset += appended.len() as u64;
+    partial.push_str(&String::from_utf8_lossy(&appended));
+
+    let Some(last_newline) = partial.rfind('\n') else {
+        return Vec::new();
+    };
+    let rest = partial.split_off(last_newline + 1);
+    let lines = partial
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect();
+    *partial = rest;
+    lines
+}
+
+fn process_state(pid: u32, started_at: DateTime<Utc>) -> ProcessState {
+    if !pid_is_alive(pid) {
+        return ProcessState::Exited;
+    }
+    if started_at.timestamp() == 0 {
+        return ProcessState::Unverifiable;
+    }
+    match process_start_time(pid) {
+        Some(actual) if (actual - started_at).num_seconds().abs() > START_TIME_TOLERANCE_SECS => {
+            ProcessState::Replaced
+        }
+        _ => ProcessState::Running,
+    }
+}
+
+#[cfg(unix)]
+fn pid_is_alive(pid: u32) -> bool {
+    if pid == 0 {
+        return false;
+    }
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    // EPERM means the process exists but belongs to someone else
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+fn pid_is_alive(pid: u32) -> bool {
+    match std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid)])
+        .args(["/FO", "CSV"])
+        .output()
+    {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).lines().count() > 1,
+        Err(_) => false,
+    }
+}
+
+/// When a process started, from the clock ticks since boot in /proc
+#[cfg(target_os = "linux")]
+fn process_start_time(pid: u32) -> Option<DateTime<Utc>> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces, so count fields after its closing parenthesis
+    let after_comm = &stat[stat.rfind(')')? + 1..];
+    let start_ticks: i64 = after_comm.split_whitespace().nth(19)?.parse().ok()?;
+    let boot_time: i64 = std::fs::read_to_string("/proc/stat")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()?;
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as i64;
+    if ticks_per_sec <= 0 {
+        return None;
+    }
+    DateTime::from_timestamp_millis(boot_time * 1000 + start_ticks * 1000 / ticks_per_sec)
+}
+
+/// When a process started, as reported by ps
+#[cfg(all(unix, not(target_os = "linux")))]
+fn process_start_time(pid: u32) -> Option<DateTime<Utc>> {
+    use chrono::{Local, NaiveDateTime, TimeZone};
+
+    let output = std::process::Command::new("ps")
+        .args(["-o", "lstart=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let lstart = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let started = NaiveDateTime::parse_from_str(&lstart, "%a %b %d %H:%M:%S %Y").ok()?;
+    Local
+        .from_local_datetime(&started)
+        .single()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Windows offers no cheap start time lookup; liveness alone decides
+#[cfg(windows)]
+fn process_start_time(_pid: u32) -> Option<DateTime<Utc>> {
+    None
+}
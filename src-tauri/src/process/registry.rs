@@ -1,9 +1,18 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::process::Child;
 
+use super::kill::{terminate_process_tree, KillReport, DEFAULT_KILL_GRACE_MS};
+use super::output::{self, LiveOutput, OutputChunk, DEFAULT_OUTPUT_BUFFER_BYTES};
+
+/// First ID handed to processes that aren't agent runs, well clear of agent run IDs
+pub(super) const FIRST_GENERATED_ID: i64 = 1000000;
+
 /// Type of process being tracked
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ProcessType {
@@ -13,6 +22,9 @@ pub enum ProcessType {
     },
     ClaudeSession {
         session_id: String,
+        /// Channel suffix the frontend listens on for this run's events
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stream_id: Option<String>,
     },
 }
 
@@ -33,21 +45,80 @@ pub struct ProcessInfo {
 pub struct ProcessHandle {
     pub info: ProcessInfo,
     pub child: Arc<Mutex<Option<Child>>>,
-    pub live_output: Arc<Mutex<String>>,
+    pub live_output: Arc<Mutex<LiveOutput>>,
 }
 
 /// Registry for tracking active agent processes
 pub struct ProcessRegistry {
-    processes: Arc<Mutex<HashMap<i64, ProcessHandle>>>, // run_id -> ProcessHandle
-    next_id: Arc<Mutex<i64>>, // Auto-incrementing ID for non-agent processes
+    pub(super) processes: Arc<Mutex<HashMap<i64, ProcessHandle>>>, // run_id -> ProcessHandle
+    pub(super) next_id: Arc<Mutex<i64>>, // Auto-incrementing ID for non-agent processes
+    pub(super) db_path: Option<PathBuf>, // Database the entries are persisted to, if any
+    pub(super) output_dir: Option<PathBuf>, // Where live output beyond the memory buffer spills to
+    output_limit: AtomicUsize, // Bytes of live output kept in memory per process
+    kill_grace_ms: AtomicU64, // Time a process group gets between SIGTERM and SIGKILL
 }
 
 impl ProcessRegistry {
     pub fn new() -> Self {
         Self {
             processes: Arc::new(Mutex::new(HashMap::new())),
-            next_id: Arc::new(Mutex::new(1000000)), // Start at high number to avoid conflicts
+            next_id: Arc::new(Mutex::new(FIRST_GENERATED_ID)),
+            db_path: None,
+            output_dir: None,
+            output_limit: AtomicUsize::new(DEFAULT_OUTPUT_BUFFER_BYTES),
+            kill_grace_ms: AtomicU64::new(DEFAULT_KILL_GRACE_MS),
+        }
+    }
+
+    /// Create a registry that persists its entries, so they can be reattached after a restart
+    ///
+    /// Live output logs are kept next to the database, so reattached processes carry on
+    /// with theirs; `reattach_processes` removes the logs of processes that didn't survive.
+    pub fn with_database(db_path: PathBuf) -> Self {
+        let output_dir = db_path.with_file_name("process-output");
+        let registry = Self {
+            db_path: Some(db_path),
+            output_dir: Some(output_dir),
+            ..Self::new()
+        };
+        registry.seed_next_id();
+        registry
+    }
+
+    /// Where a process's live output spills to, if anywhere
+    fn output_log(&self, run_id: i64) -> Option<PathBuf> {
+        self.output_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.log", run_id)))
+    }
+
+    /// Empty live output for a new entry
+    pub(super) fn new_live_output(&self, run_id: i64) -> Arc<Mutex<LiveOutput>> {
+        Arc::new(Mutex::new(LiveOutput::new(
+            self.output_limit.load(Ordering::Relaxed),
+            self.output_log(run_id),
+        )))
+    }
+
+    /// Live output for a reattached process, continuing the log it had before a restart
+    pub(super) fn reopen_live_output(&self, run_id: i64) -> Arc<Mutex<LiveOutput>> {
+        let max_bytes = self.output_limit.load(Ordering::Relaxed);
+        let live_output = match self.output_log(run_id) {
+            Some(log_file) => LiveOutput::reopen(max_bytes, log_file),
+            None => LiveOutput::new(max_bytes, None),
+        };
+        Arc::new(Mutex::new(live_output))
+    }
+
+    /// Cap the live output each process keeps in memory
+    pub fn set_output_buffer_limit(&self, max_bytes: usize) -> Result<(), String> {
+        self.output_limit.store(max_bytes, Ordering::Relaxed);
+        let processes = self.processes.lock().map_err(|e| e.to_string())?;
+        for handle in processes.values() {
+            let mut live_output = handle.live_output.lock().map_err(|e| e.to_string())?;
+            live_output.set_max_bytes(max_bytes);
         }
+        Ok(())
     }
 
     /// Generate a unique ID for non-agent processes
@@ -105,50 +176,69 @@ impl ProcessRegistry {
         };
 
         // For sidecar processes, we register without the child handle since it's managed differently
+        self.persist_process(&process_info);
         let mut processes = self.processes.lock().map_err(|e| e.to_string())?;
         
         let process_handle = ProcessHandle {
             info: process_info,
             child: Arc::new(Mutex::new(None)), // No tokio::process::Child handle for sidecar
-            live_output: Arc::new(Mutex::new(String::new())),
+            live_output: self.new_live_output(run_id),
         };
 
         processes.insert(run_id, process_handle);
         Ok(())
     }
 
-    /// Register a new Claude session (without child process - handled separately)
+    /// Register a new interactive Claude session, which owns its child process
+    ///
+    /// The session ID may be empty until Claude reports it in its init message; fill it in
+    /// with `set_session_id`.
     pub fn register_claude_session(
         &self,
         session_id: String,
-        pid: u32,
+        stream_id: Option<String>,
         project_path: String,
         task: String,
         model: String,
+        child: Child,
     ) -> Result<i64, String> {
         let run_id = self.generate_id()?;
-        
+
         let process_info = ProcessInfo {
             run_id,
-            process_type: ProcessType::ClaudeSession { session_id },
-            pid,
+            process_type: ProcessType::ClaudeSession {
+                session_id,
+                stream_id,
+            },
+            pid: child.id().unwrap_or(0),
             started_at: Utc::now(),
             project_path,
             task,
             model,
         };
 
-        // Register without child - Claude sessions use ClaudeProcessState for process management
-        let mut processes = self.processes.lock().map_err(|e| e.to_string())?;
-        
-        let process_handle = ProcessHandle {
-            info: process_info,
-            child: Arc::new(Mutex::new(None)), // No child handle for Claude sessions
-            live_output: Arc::new(Mutex::new(String::new())),
+        self.register_process_internal(run_id, process_info, child)?;
+        Ok(run_id)
+    }
+
+    /// Record the session ID Claude reported for a running process
+    pub fn set_session_id(&self, run_id: i64, session_id: String) -> Result<(), String> {
+        let project_path = {
+            let mut processes = self.processes.lock().map_err(|e| e.to_string())?;
+            let Some(handle) = processes.get_mut(&run_id) else {
+                return Ok(());
+            };
+            if let ProcessType::ClaudeSession {
+                session_id: sid, ..
+            } = &mut handle.info.process_type
+            {
+                *sid = session_id.clone();
+            }
+            handle.info.project_path.clone()
         };
 
-        processes.insert(run_id, process_handle);
-        Ok(run_id)
+        self.persist_session_id(run_id, &project_path, &session_id);
+        Ok(())
     }
 
     /// Internal method to register any process
@@ -158,12 +248,13 @@ impl ProcessRegistry {
         process_info: ProcessInfo,
         child: Child,
     ) -> Result<(), String> {
+        self.persist_process(&process_info);
         let mut processes = self.processes.lock().map_err(|e| e.to_string())?;
 
         let process_handle = ProcessHandle {
             info: process_info,
             child: Arc::new(Mutex::new(Some(child))),
-            live_output: Arc::new(Mutex::new(String::new())),
+            live_output: self.new_live_output(run_id),
         };
 
         processes.insert(run_id, process_handle);
@@ -191,21 +282,60 @@ impl ProcessRegistry {
             .values()
             .find(|handle| {
                 match &handle.info.process_type {
-                    ProcessType::ClaudeSession { session_id: sid } => sid == session_id,
+                    ProcessType::ClaudeSession { session_id: sid, .. } => sid == session_id,
                     _ => false,
                 }
             })
             .map(|handle| handle.info.clone()))
     }
 
+    /// Get a specific Claude session by the stream ID its run was started with
+    pub fn get_claude_session_by_stream_id(
+        &self,
+        stream_id: &str,
+    ) -> Result<Option<ProcessInfo>, String> {
+        let processes = self.processes.lock().map_err(|e| e.to_string())?;
+        Ok(processes
+            .values()
+            .find(|handle| match &handle.info.process_type {
+                ProcessType::ClaudeSession {
+                    stream_id: Some(sid),
+                    ..
+                } => sid == stream_id,
+                _ => false,
+            })
+            .map(|handle| handle.info.clone()))
+    }
+
     /// Unregister a process (called when it completes)
+    ///
+    /// An agent run's output log is kept, so its output can still be read, until the run
+    /// record is deleted; a Claude session's log goes with its registry entry.
     #[allow(dead_code)]
     pub fn unregister_process(&self, run_id: i64) -> Result<(), String> {
         let mut processes = self.processes.lock().map_err(|e| e.to_string())?;
-        processes.remove(&run_id);
+        let removed = processes.remove(&run_id);
+        drop(processes);
+        if let Some(ProcessType::ClaudeSession { .. }) =
+            removed.map(|handle| handle.info.process_type)
+        {
+            self.remove_output_log(run_id);
+        }
+        self.forget_process(run_id);
         Ok(())
     }
 
+    /// Delete a process's output log, e.g. along with its run record
+    pub fn remove_output_log(&self, run_id: i64) {
+        if let Some(log_file) = self.output_log(run_id) {
+            if let Err(e) = std::fs::remove_file(&log_file) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to remove output log {}: {}", log_file.display(), e);
+                }
+            }
+        }
+    }
+
     /// Get all running processes
     #[allow(dead_code)]
     pub fn get_running_processes(&self) -> Result<Vec<ProcessInfo>, String> {
@@ -237,193 +367,57 @@ impl ProcessRegistry {
         Ok(processes.get(&run_id).map(|handle| handle.info.clone()))
     }
 
-    /// Kill a running process with proper cleanup
-    pub async fn kill_process(&self, run_id: i64) -> Result<bool, String> {
-        use log::{error, info, warn};
-
-        // First check if the process exists and get its PID
+    /// Terminate a process and everything it started, then unregister it
+    ///
+    /// The whole process group gets SIGTERM, then SIGKILL for whatever outlives the grace
+    /// period. Returns `None` if the process isn't registered.
+    pub async fn kill_process(&self, run_id: i64) -> Result<Option<KillReport>, String> {
         let (pid, child_arc) = {
             let processes = self.processes.lock().map_err(|e| e.to_string())?;
-            if let Some(handle) = processes.get(&run_id) {
-                (handle.info.pid, handle.child.clone())
-            } else {
-                warn!("Process {} not found in registry", run_id);
-                return Ok(false); // Process not found
-            }
-        };
-
-        info!(
-            "Attempting graceful shutdown of process {} (PID: {})",
-            run_id, pid
-        );
-
-        // Send kill signal to the process
-        let kill_sent = {
-            let mut child_guard = child_arc.lock().map_err(|e| e.to_string())?;
-            if let Some(child) = child_guard.as_mut() {
-                match child.start_kill() {
-                    Ok(_) => {
-                        info!("Successfully sent kill signal to process {}", run_id);
-                        true
-                    }
-                    Err(e) => {
-                        error!("Failed to send kill signal to process {}: {}", run_id, e);
-                        // Don't return error here, try fallback method
-                        false
-                    }
+            match processes.get(&run_id) {
+                Some(handle) => (handle.info.pid, handle.child.clone()),
+                None => {
+                    log::warn!("Process {} not found in registry", run_id);
+                    return Ok(None);
                 }
-            } else {
-                warn!("No child handle available for process {} (PID: {}), attempting system kill", run_id, pid);
-                false // Process handle not available, try fallback
             }
         };
 
-        // If direct kill didn't work, try system command as fallback
-        if !kill_sent {
-            info!("Attempting fallback kill for process {} (PID: {})", run_id, pid);
-            match self.kill_process_by_pid(run_id, pid) {
-                Ok(true) => return Ok(true),
-                Ok(false) => warn!("Fallback kill also failed for process {} (PID: {})", run_id, pid),
-                Err(e) => error!("Error during fallback kill: {}", e),
-            }
-            // Continue with the rest of the cleanup even if fallback failed
-        }
-
-        // Wait for the process to exit (with timeout)
-        let wait_result = tokio::time::timeout(tokio::time::Duration::from_secs(5), async {
-            loop {
-                // Check if process has exited
-                let status = {
-                    let mut child_guard = child_arc.lock().map_err(|e| e.to_string())?;
-                    if let Some(child) = child_guard.as_mut() {
-                        match child.try_wait() {
-                            Ok(Some(status)) => {
-                                info!("Process {} exited with status: {:?}", run_id, status);
-                                *child_guard = None; // Clear the child handle
-                                Some(Ok::<(), String>(()))
-                            }
-                            Ok(None) => {
-                                // Still running
-                                None
-                            }
-                            Err(e) => {
-                                error!("Error checking process status: {}", e);
-                                Some(Err(e.to_string()))
-                            }
-                        }
-                    } else {
-                        // Process already gone
-                        Some(Ok(()))
-                    }
-                };
+        let report = self.terminate_pid(pid).await?;
 
-                match status {
-                    Some(result) => return result,
-                    None => {
-                        // Still running, wait a bit
-                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                    }
-                }
-            }
-        })
-        .await;
-
-        match wait_result {
-            Ok(Ok(_)) => {
-                info!("Process {} exited gracefully", run_id);
-            }
-            Ok(Err(e)) => {
-                error!("Error waiting for process {}: {}", run_id, e);
-            }
-            Err(_) => {
-                warn!("Process {} didn't exit within 5 seconds after kill", run_id);
-                // Force clear the handle
-                if let Ok(mut child_guard) = child_arc.lock() {
+        // Reap our own child so it doesn't linger as a zombie
+        if let Ok(mut child_guard) = child_arc.lock() {
+            if let Some(child) = child_guard.as_mut() {
+                if let Ok(Some(status)) = child.try_wait() {
+                    log::info!("Process {} exited with status: {:?}", run_id, status);
                     *child_guard = None;
                 }
-                // One more attempt with system kill
-                let _ = self.kill_process_by_pid(run_id, pid);
             }
         }
 
-        // Remove from registry after killing
         self.unregister_process(run_id)?;
-
-        Ok(true)
+        Ok(Some(report))
     }
 
-    /// Kill a process by PID using system commands (fallback method)
-    pub fn kill_process_by_pid(&self, run_id: i64, pid: u32) -> Result<bool, String> {
-        use log::{error, info, warn};
-
-        info!("Attempting to kill process {} by PID {}", run_id, pid);
+    /// Terminate a process tree by PID, for processes the registry holds no child for
+    pub async fn kill_process_by_pid(&self, run_id: i64, pid: u32) -> Result<KillReport, String> {
+        let report = self.terminate_pid(pid).await?;
+        if report.exited {
+            self.unregister_process(run_id)?;
+        }
+        Ok(report)
+    }
 
-        let kill_result = if cfg!(target_os = "windows") {
-            std::process::Command::new("taskkill")
-                .args(["/F", "/PID", &pid.to_string()])
-                .output()
-        } else {
-            // First try SIGTERM
-            let term_result = std::process::Command::new("kill")
-                .args(["-TERM", &pid.to_string()])
-                .output();
-
-            match &term_result {
-                Ok(output) if output.status.success() => {
-                    info!("Sent SIGTERM to PID {}", pid);
-                    // Give it 2 seconds to exit gracefully
-                    std::thread::sleep(std::time::Duration::from_secs(2));
-
-                    // Check if still running
-                    let check_result = std::process::Command::new("kill")
-                        .args(["-0", &pid.to_string()])
-                        .output();
-
-                    if let Ok(output) = check_result {
-                        if output.status.success() {
-                            // Still running, send SIGKILL
-                            warn!(
-                                "Process {} still running after SIGTERM, sending SIGKILL",
-                                pid
-                            );
-                            std::process::Command::new("kill")
-                                .args(["-KILL", &pid.to_string()])
-                                .output()
-                        } else {
-                            term_result
-                        }
-                    } else {
-                        term_result
-                    }
-                }
-                _ => {
-                    // SIGTERM failed, try SIGKILL directly
-                    warn!("SIGTERM failed for PID {}, trying SIGKILL", pid);
-                    std::process::Command::new("kill")
-                        .args(["-KILL", &pid.to_string()])
-                        .output()
-                }
-            }
-        };
+    async fn terminate_pid(&self, pid: u32) -> Result<KillReport, String> {
+        let grace = Duration::from_millis(self.kill_grace_ms.load(Ordering::Relaxed));
+        tokio::task::spawn_blocking(move || terminate_process_tree(pid, grace))
+            .await
+            .map_err(|e| format!("Failed to terminate process {}: {}", pid, e))
+    }
 
-        match kill_result {
-            Ok(output) => {
-                if output.status.success() {
-                    info!("Successfully killed process with PID {}", pid);
-                    // Remove from registry
-                    self.unregister_process(run_id)?;
-                    Ok(true)
-                } else {
-                    let error_msg = String::from_utf8_lossy(&output.stderr);
-                    warn!("Failed to kill PID {}: {}", pid, error_msg);
-                    Ok(false)
-                }
-            }
-            Err(e) => {
-                error!("Failed to execute kill command for PID {}: {}", pid, e);
-                Err(format!("Failed to execute kill command: {}", e))
-            }
-        }
+    /// Set how long a process group gets to exit after SIGTERM before SIGKILL
+    pub fn set_kill_grace_period(&self, grace: Duration) {
+        self.kill_grace_ms.store(grace.as_millis() as u64, Ordering::Relaxed);
     }
 
     /// Check if a process is still running by trying to get its status
@@ -461,28 +455,99 @@ impl ProcessRegistry {
         }
     }
 
+    /// Wait for a process to exit on its own and reap it
+    ///
+    /// Returns `None` if the process was killed or unregistered in the meantime, in which
+    /// case whoever did that is responsible for reporting it.
+    pub async fn wait_for_exit(
+        &self,
+        run_id: i64,
+    ) -> Result<Option<std::process::ExitStatus>, String> {
+        Ok(self
+            .wait_for_exit_with_cpu_time(run_id)
+            .await?
+            .map(|(status, _)| status))
+    }
+
+    /// Like `wait_for_exit`, also returning the CPU time the process itself used
+    ///
+    /// The CPU time is read just before the process is reaped, and is `None` where the
+    /// platform can't report it for an exited process.
+    pub async fn wait_for_exit_with_cpu_time(
+        &self,
+        run_id: i64,
+    ) -> Result<Option<(std::process::ExitStatus, Option<Duration>)>, String> {
+        loop {
+            let child_arc = {
+                let processes = self.processes.lock().map_err(|e| e.to_string())?;
+                match processes.get(&run_id) {
+                    Some(handle) => handle.child.clone(),
+                    None => return Ok(None),
+                }
+            };
+
+            {
+                let mut child_guard = child_arc.lock().map_err(|e| e.to_string())?;
+                let Some(child) = child_guard.as_mut() else {
+                    return Ok(None);
+                };
+                let cpu_time = child.id().and_then(exited_cpu_time);
+                if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+                    *child_guard = None;
+                    return Ok(Some((status, cpu_time)));
+                }
+            }
+
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+    }
+
     /// Append to live output for a process
     pub fn append_live_output(&self, run_id: i64, output: &str) -> Result<(), String> {
         let processes = self.processes.lock().map_err(|e| e.to_string())?;
         if let Some(handle) = processes.get(&run_id) {
             let mut live_output = handle.live_output.lock().map_err(|e| e.to_string())?;
-            live_output.push_str(output);
-            live_output.push('\n');
+            live_output.push(output);
         }
         Ok(())
     }
 
-    /// Get live output for a process
+    /// The recent live output a process holds in memory; read older lines by cursor
     pub fn get_live_output(&self, run_id: i64) -> Result<String, String> {
         let processes = self.processes.lock().map_err(|e| e.to_string())?;
         if let Some(handle) = processes.get(&run_id) {
             let live_output = handle.live_output.lock().map_err(|e| e.to_string())?;
-            Ok(live_output.clone())
+            Ok(live_output.contents())
         } else {
             Ok(String::new())
         }
     }
 
+    /// Read live output from a line cursor, so pollers fetch only what is new
+    ///
+    /// Once the process has finished, its output is read back from the log it left.
+    pub fn read_live_output(
+        &self,
+        run_id: i64,
+        offset: u64,
+        limit: usize,
+    ) -> Result<OutputChunk, String> {
+        let live_output = {
+            let processes = self.processes.lock().map_err(|e| e.to_string())?;
+            match processes.get(&run_id) {
+                Some(handle) => handle.live_output.clone(),
+                None => {
+                    return Ok(self
+                        .output_log(run_id)
+                        .and_then(|log_file| output::read_log(&log_file, offset, limit))
+                        .unwrap_or_else(|| OutputChunk::empty(offset)))
+                }
+            }
+        };
+        let mut live_output = live_output.lock().map_err(|e| e.to_string())?;
+        Ok(live_output.read(offset, limit))
+    }
+
     /// Cleanup finished processes
     #[allow(dead_code)]
     pub async fn cleanup_finished_processes(&self) -> Result<Vec<i64>, String> {
@@ -507,6 +572,7 @@ impl ProcessRegistry {
             let mut processes = processes_lock.lock().map_err(|e| e.to_string())?;
             for run_id in &finished_runs {
                 processes.remove(run_id);
+                self.forget_process(*run_id);
             }
         }
 
@@ -514,6 +580,30 @@ impl ProcessRegistry {
     }
 }
 
+/// User and system CPU time of a process that has exited but not been reaped yet
+#[cfg(target_os = "linux")]
+fn exited_cpu_time(pid: u32) -> Option<Duration> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces, so count fields after its closing parenthesis
+    let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace();
+    if fields.next()? != "Z" {
+        return None;
+    }
+    // Then utime and stime in clock ticks, fields 14 and 15 of the whole line
+    let mut fields = fields.skip(10);
+    let ticks: u64 = fields.next()?.parse::<u64>().ok()? + fields.next()?.parse::<u64>().ok()?;
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks_per_sec <= 0 {
+        return None;
+    }
+    Some(Duration::from_millis(ticks * 1000 / ticks_per_sec as u64))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn exited_cpu_time(_pid: u32) -> Option<Duration> {
+    None
+}
+
 impl Default for ProcessRegistry {
     fn default() -> Self {
         Self::new()
@@ -528,3 +618,10 @@ impl Default for ProcessRegistryState {
         Self(Arc::new(ProcessRegistry::new()))
     }
 }
+
+impl ProcessRegistryState {
+    /// Registry state whose entries survive an app restart
+    pub fn with_database(db_path: PathBuf) -> Self {
+        Self(Arc::new(ProcessRegistry::with_database(db_path)))
+    }
+}
@@ -113,6 +113,7 @@ export const ClaudeCodeSession: React.FC<ClaudeCodeSessionProps> = ({
   const queuedPromptsRef = useRef<Array<{ id: string; prompt: string; model: "sonnet" | "opus" }>>([]);
   const isMountedRef = useRef(true);
   const isListeningRef = useRef(false);
+  const streamIdRef = useRef<string | null>(null);
   const sessionStartTime = useRef<number>(Date.now());
   
   // Session metrics state for enhanced analytics
@@ -474,14 +475,18 @@ export const ClaudeCodeSession: React.FC<ClaudeCodeSessionProps> = ({
         // Claude Code may emit a *new* session_id even when we pass --resume. If
         // we listen only on the old session-scoped channel we will miss the
         // stream until the user navigates away & back. To avoid this we:
-        //   • Always start with GENERIC listeners (no suffix) so we catch the
-        //     very first "system:init" message regardless of the session id.
+        //   • Always start with listeners on a STREAM channel unique to this run
+        //     so we catch the very first "system:init" message regardless of
+        //     the session id, without seeing other sessions' output.
         //   • Once that init message provides the *actual* session_id, we
         //     dynamically switch to session-scoped listeners and stop the
-        //     generic ones to prevent duplicate handling.
+        //     stream ones to prevent duplicate handling.
         // --------------------------------------------------------------------
 
-        console.log('[ClaudeCodeSession] Setting up generic event listeners first');
+        console.log('[ClaudeCodeSession] Setting up stream event listeners first');
+
+        const streamId = crypto.randomUUID();
+        streamIdRef.current = streamId;
 
         let currentSessionId: string | null = claudeSessionId || effectiveSession?.id || null;
 
@@ -508,8 +513,8 @@ export const ClaudeCodeSession: React.FC<ClaudeCodeSessionProps> = ({
           unlistenRefs.current = [specificOutputUnlisten, specificErrorUnlisten, specificCompleteUnlisten];
         };
 
-        // Generic listeners (catch-all)
-        const genericOutputUnlisten = await listen<string>('claude-output', async (event) => {
+        // Stream listeners (catch everything this run emits)
+        const streamOutputUnlisten = await listen<string>(`claude-output:${streamId}`, async (event) => {
           handleStreamMessage(event.payload);
 
           // Attempt to extract session_id on the fly (for the very first init)
@@ -517,7 +522,7 @@ export const ClaudeCodeSession: React.FC<ClaudeCodeSessionProps> = ({
             const msg = JSON.parse(event.payload) as ClaudeStreamMessage;
             if (msg.type === 'system' && msg.subtype === 'init' && msg.session_id) {
               if (!currentSessionId || currentSessionId !== msg.session_id) {
-                console.log('[ClaudeCodeSession] Detected new session_id from generic listener:', msg.session_id);
+                console.log('[ClaudeCodeSession] Detected new session_id from stream listener:', msg.session_id);
                 currentSessionId = msg.session_id;
                 setClaudeSessionId(msg.session_id);
 
@@ -727,18 +732,18 @@ export const ClaudeCodeSession: React.FC<ClaudeCodeSessionProps> = ({
           }
         };
 
-        const genericErrorUnlisten = await listen<string>('claude-error', (evt) => {
+        const streamErrorUnlisten = await listen<string>(`claude-error:${streamId}`, (evt) => {
           console.error('Claude error:', evt.payload);
           setError(evt.payload);
         });
 
-        const genericCompleteUnlisten = await listen<boolean>('claude-complete', (evt) => {
-          console.log('[ClaudeCodeSession] Received claude-complete (generic):', evt.payload);
+        const streamCompleteUnlisten = await listen<boolean>(`claude-complete:${streamId}`, (evt) => {
+          console.log('[ClaudeCodeSession] Received claude-complete (stream):', evt.payload);
           processComplete(evt.payload);
         });
 
-        // Store the generic unlisteners for now; they may be replaced later.
-        unlistenRefs.current = [genericOutputUnlisten, genericErrorUnlisten, genericCompleteUnlisten];
+        // Store the stream unlisteners for now; they may be replaced later.
+        unlistenRefs.current = [streamOutputUnlisten, streamErrorUnlisten, streamCompleteUnlisten];
 
         // --------------------------------------------------------------------
         // 2️⃣  Auto-checkpoint logic moved after listener setup (unchanged)
@@ -803,13 +808,13 @@ export const ClaudeCodeSession: React.FC<ClaudeCodeSessionProps> = ({
           console.log('[ClaudeCodeSession] Resuming session:', effectiveSession.id);
           trackEvent.sessionResumed(effectiveSession.id);
           trackEvent.modelSelected(model);
-          await api.resumeClaudeCode(projectPath, effectiveSession.id, prompt, model);
+          await api.resumeClaudeCode(projectPath, effectiveSession.id, prompt, model, streamId);
         } else {
           console.log('[ClaudeCodeSession] Starting new session');
           setIsFirstPrompt(false);
           trackEvent.sessionCreated(model, 'prompt_input');
           trackEvent.modelSelected(model);
-          await api.executeClaudeCode(projectPath, prompt, model);
+          await api.executeClaudeCode(projectPath, prompt, model, streamId);
         }
       }
     } catch (err) {
@@ -911,13 +916,14 @@ export const ClaudeCodeSession: React.FC<ClaudeCodeSessionProps> = ({
   };
 
   const handleCancelExecution = async () => {
-    if (!claudeSessionId || !isLoading) return;
+    if (!isLoading) return;
     
     try {
       const sessionStartTime = messages.length > 0 ? messages[0].timestamp || Date.now() : Date.now();
       const duration = Date.now() - sessionStartTime;
       
-      await api.cancelClaudeExecution(claudeSessionId);
+      // Before Claude reports its session ID, the run is only known by its stream
+      await api.cancelClaudeExecution(claudeSessionId ?? undefined, streamIdRef.current ?? undefined);
       
       // Calculate metrics for enhanced analytics
       const metrics = sessionMetrics.current;
@@ -150,7 +150,17 @@ export const TimelineNavigator: React.FC<TimelineNavigatorProps> = ({
   };
 
   const handleRestoreCheckpoint = async (checkpoint: Checkpoint) => {
-    if (!confirm(`Restore to checkpoint "${checkpoint.description || checkpoint.id.slice(0, 8)}"? Current state will be saved as a new checkpoint.`)) {
+    let conflictNote = "";
+    try {
+      const conflicts = await api.checkRestoreConflicts(sessionId, projectId, projectPath);
+      if (conflicts.length > 0) {
+        conflictNote = `\n\n${conflicts.length} file(s) were modified outside the session and will be kept in that checkpoint: ${conflicts.map(c => c.path).join(", ")}`;
+      }
+    } catch (err) {
+      console.error("Failed to check for external edits:", err);
+    }
+
+    if (!confirm(`Restore to checkpoint "${checkpoint.description || checkpoint.id.slice(0, 8)}"? Current state will be saved as a new checkpoint.${conflictNote}`)) {
       return;
     }
 
@@ -167,11 +177,13 @@ export const TimelineNavigator: React.FC<TimelineNavigatorProps> = ({
         projectId,
         projectPath,
         currentMessageIndex,
-        "Auto-save before restore"
+        "Auto-save before restore",
+        true
       );
       
       // Then restore
-      await api.restoreCheckpoint(checkpoint.id, sessionId, projectId, projectPath);
+      // Anything edited after the auto-save is stashed rather than lost
+      await api.restoreCheckpoint(checkpoint.id, sessionId, projectId, projectPath, 'stash');
       
       // Track checkpoint restoration
       trackEvent.checkpointRestored({
@@ -1,5 +1,5 @@
 import { useState, useCallback } from 'react';
-import { api } from '@/lib/api';
+import { api, type ConflictResolution } from '@/lib/api';
 
 // Local checkpoint format for UI display
 interface Checkpoint {
@@ -70,7 +70,20 @@ export function useCheckpoints({ sessionId, projectId, projectPath, onToast }: U
     if (!sessionId) return;
     
     try {
-      await api.restoreCheckpoint(checkpointId, sessionId, projectId, projectPath);
+      const conflicts = await api.checkRestoreConflicts(sessionId, projectId, projectPath);
+      let onConflict: ConflictResolution | undefined;
+      if (conflicts.length > 0) {
+        const files = conflicts.map(c => c.path).join(", ");
+        if (confirm(`${conflicts.length} file(s) were modified outside the session: ${files}\n\nSave them in a checkpoint before restoring?`)) {
+          onConflict = 'stash';
+        } else if (confirm("Discard the external edits and restore anyway?")) {
+          onConflict = 'force';
+        } else {
+          return false;
+        }
+      }
+
+      await api.restoreCheckpoint(checkpointId, sessionId, projectId, projectPath, onConflict);
       showToast("Checkpoint restored successfully", 'success');
       // Return true to indicate success
       return true;
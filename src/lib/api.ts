@@ -4,7 +4,7 @@ import type { HooksConfiguration } from '@/types/hooks';
 /** Process type for tracking in ProcessRegistry */
 export type ProcessType = 
   | { AgentRun: { agent_id: number; agent_name: string } }
-  | { ClaudeSession: { session_id: string } };
+  | { ClaudeSession: { session_id: string; stream_id?: string } };
 
 /** Information about a running process */
 export interface ProcessInfo {
@@ -122,6 +122,34 @@ export interface Agent {
   hooks?: string; // JSON string of HooksConfiguration
   created_at: string;
   updated_at: string;
+  timeout_secs?: number;
+  max_output_tokens?: number;
+  max_memory_mb?: number;
+  max_cpu_secs?: number;
+  max_open_files?: number;
+}
+
+/**
+ * Resource limits for an agent's runs; a missing limit leaves that resource unlimited
+ */
+export interface AgentLimits {
+  /** Wall-clock time before a run is stopped as 'timed_out' */
+  timeout_secs?: number;
+  /** Output tokens a run may produce before it is stopped as 'limit_exceeded' */
+  max_output_tokens?: number;
+  /**
+   * Heap and other private data of the agent process (Unix only). Allocations past it fail
+   * inside the process, which can't be told apart from other errors, so a run that hits it
+   * ends as 'failed' rather than 'limit_exceeded'
+   */
+  max_memory_mb?: number;
+  /** CPU time of the agent process before it is stopped as 'limit_exceeded' (Unix only) */
+  max_cpu_secs?: number;
+  /**
+   * Open file descriptors of the agent process (Unix only). Like the memory limit, a run
+   * that hits it ends as 'failed' rather than 'limit_exceeded'
+   */
+  max_open_files?: number;
 }
 
 export interface AgentExport {
@@ -154,11 +182,37 @@ export interface AgentRun {
   model: string;
   project_path: string;
   session_id: string;
-  status: string; // 'pending', 'running', 'completed', 'failed', 'cancelled'
+  status: string; // 'pending', 'running', 'completed', 'failed', 'cancelled', 'timed_out', 'limit_exceeded'
   pid?: number;
   process_started_at?: string;
   created_at: string;
   completed_at?: string;
+  failure_reason?: string;
+}
+
+/**
+ * A slice of a running process's live output, read from a line cursor
+ */
+export interface OutputChunk {
+  /** Line number of the first returned line; above the requested offset if lines were lost */
+  offset: number;
+  /** Cursor to pass to the next read */
+  next_offset: number;
+  total_lines: number;
+  lines: string[];
+}
+
+/**
+ * A checkpoint taken automatically during an agent run
+ */
+export interface AgentRunCheckpoint {
+  id: number;
+  run_id: number;
+  checkpoint_id: string;
+  session_id: string;
+  project_id: string;
+  trigger: 'run_start' | 'tool_use';
+  created_at: string;
 }
 
 export interface AgentRunMetrics {
@@ -177,7 +231,7 @@ export interface AgentRunWithMetrics {
   model: string;
   project_path: string;
   session_id: string;
-  status: string; // 'pending', 'running', 'completed', 'failed', 'cancelled'
+  status: string; // 'pending', 'running', 'completed', 'failed', 'cancelled', 'timed_out', 'limit_exceeded'
   pid?: number;
   duration_ms?: number;
   total_tokens?: number;
@@ -251,7 +305,11 @@ export interface Checkpoint {
   timestamp: string;
   description?: string;
   parentCheckpointId?: string;
+  mergeParentId?: string;
   metadata: CheckpointMetadata;
+  tags?: string[];
+  /** Made without the user asking, so its description was generated */
+  autoGenerated?: boolean;
 }
 
 /**
@@ -276,6 +334,7 @@ export interface FileSnapshot {
   isDeleted: boolean;
   permissions?: number;
   size: number;
+  kind?: 'file' | 'symlink' | 'directory';
 }
 
 /**
@@ -313,6 +372,19 @@ export interface CheckpointResult {
   warnings: string[];
 }
 
+/**
+ * A file edited outside the session since the last checkpoint
+ */
+export interface ExternalChange {
+  path: string;
+  kind: 'created' | 'modified' | 'deleted';
+}
+
+/**
+ * What to do with external edits when restoring a checkpoint
+ */
+export type ConflictResolution = 'abort' | 'force' | 'stash';
+
 /**
  * Diff between two checkpoints
  */
@@ -700,6 +772,7 @@ export const api = {
    * @param default_task - Optional default task
    * @param model - Optional model (defaults to 'sonnet')
    * @param hooks - Optional hooks configuration as JSON string
+   * @param limits - Optional resource limits for the agent's runs
    * @returns Promise resolving to the created agent
    */
   async createAgent(
@@ -708,7 +781,8 @@ export const api = {
     system_prompt: string, 
     default_task?: string, 
     model?: string,
-    hooks?: string
+    hooks?: string,
+    limits?: AgentLimits
   ): Promise<Agent> {
     try {
       return await invoke<Agent>('create_agent', { 
@@ -717,7 +791,8 @@ export const api = {
         systemPrompt: system_prompt,
         defaultTask: default_task,
         model,
-        hooks
+        hooks,
+        limits
       });
     } catch (error) {
       console.error("Failed to create agent:", error);
@@ -734,6 +809,7 @@ export const api = {
    * @param default_task - Optional default task
    * @param model - Optional model
    * @param hooks - Optional hooks configuration as JSON string
+   * @param limits - Optional resource limits, replacing all current ones; omit to keep them
    * @returns Promise resolving to the updated agent
    */
   async updateAgent(
@@ -743,7 +819,8 @@ export const api = {
     system_prompt: string, 
     default_task?: string, 
     model?: string,
-    hooks?: string
+    hooks?: string,
+    limits?: AgentLimits
   ): Promise<Agent> {
     try {
       return await invoke<Agent>('update_agent', { 
@@ -753,7 +830,8 @@ export const api = {
         systemPrompt: system_prompt,
         defaultTask: default_task,
         model,
-        hooks
+        hooks,
+        limits
       });
     } catch (error) {
       console.error("Failed to update agent:", error);
@@ -907,6 +985,20 @@ export const api = {
     }
   },
 
+  /**
+   * Lists the checkpoints taken automatically during an agent run
+   * @param runId - The run ID
+   * @returns Promise resolving to the run's checkpoints, oldest first
+   */
+  async listAgentRunCheckpoints(runId: number): Promise<AgentRunCheckpoint[]> {
+    try {
+      return await invoke<AgentRunCheckpoint[]>('list_agent_run_checkpoints', { runId });
+    } catch (error) {
+      console.error("Failed to list agent run checkpoints:", error);
+      throw new Error(`Failed to list agent run checkpoints: ${error instanceof Error ? error.message : 'Unknown error'}`);
+    }
+  },
+
   /**
    * Lists all currently running agent sessions
    * @returns Promise resolving to list of running agent sessions
@@ -989,6 +1081,50 @@ export const api = {
     }
   },
 
+  /**
+   * Read live output incrementally, starting at a line cursor
+   * @param runId - The run ID to read output for
+   * @param offset - Line to start at; pass the previous chunk's next_offset
+   * @param limit - Maximum number of lines to return
+   * @returns Promise resolving to the lines read and the next cursor
+   */
+  async readLiveSessionOutput(runId: number, offset: number, limit?: number): Promise<OutputChunk> {
+    try {
+      return await invoke<OutputChunk>('read_live_session_output', { runId, offset, limit });
+    } catch (error) {
+      console.error("Failed to read live session output:", error);
+      throw new Error(`Failed to read live session output: ${error instanceof Error ? error.message : 'Unknown error'}`);
+    }
+  },
+
+  /**
+   * Gets how many bytes of live output each process keeps in memory
+   */
+  async getLiveOutputBufferLimit(): Promise<number> {
+    return invoke<number>('get_live_output_buffer_limit');
+  },
+
+  /**
+   * Sets how many bytes of live output each process keeps in memory before spilling to disk
+   */
+  async setLiveOutputBufferLimit(maxBytes: number): Promise<void> {
+    return invoke('set_live_output_buffer_limit', { maxBytes });
+  },
+
+  /**
+   * Gets how long, in milliseconds, a killed session's processes get to exit before SIGKILL
+   */
+  async getProcessKillGraceMs(): Promise<number> {
+    return invoke<number>('get_process_kill_grace_ms');
+  },
+
+  /**
+   * Sets how long, in milliseconds, a killed session's processes get to exit before SIGKILL
+   */
+  async setProcessKillGraceMs(graceMs: number): Promise<void> {
+    return invoke('set_process_kill_grace_ms', { graceMs });
+  },
+
   /**
    * Start streaming real-time output for a running session
    * @param runId - The run ID to stream output for
@@ -1027,31 +1163,33 @@ export const api = {
 
   /**
    * Executes a new interactive Claude Code session with streaming output
+   * @param streamId - Optional ID to receive this run's events on `claude-output:{streamId}` etc.
    */
-  async executeClaudeCode(projectPath: string, prompt: string, model: string): Promise<void> {
-    return invoke("execute_claude_code", { projectPath, prompt, model });
+  async executeClaudeCode(projectPath: string, prompt: string, model: string, streamId?: string): Promise<void> {
+    return invoke("execute_claude_code", { projectPath, prompt, model, streamId });
   },
 
   /**
    * Continues an existing Claude Code conversation with streaming output
    */
-  async continueClaudeCode(projectPath: string, prompt: string, model: string): Promise<void> {
-    return invoke("continue_claude_code", { projectPath, prompt, model });
+  async continueClaudeCode(projectPath: string, prompt: string, model: string, streamId?: string): Promise<void> {
+    return invoke("continue_claude_code", { projectPath, prompt, model, streamId });
   },
 
   /**
    * Resumes an existing Claude Code session by ID with streaming output
    */
-  async resumeClaudeCode(projectPath: string, sessionId: string, prompt: string, model: string): Promise<void> {
-    return invoke("resume_claude_code", { projectPath, sessionId, prompt, model });
+  async resumeClaudeCode(projectPath: string, sessionId: string, prompt: string, model: string, streamId?: string): Promise<void> {
+    return invoke("resume_claude_code", { projectPath, sessionId, prompt, model, streamId });
   },
 
   /**
-   * Cancels the currently running Claude Code execution
-   * @param sessionId - Optional session ID to cancel a specific session
+   * Cancels a running Claude Code execution
+   * @param sessionId - Session to cancel; may be omitted only while a single session is running
+   * @param streamId - Stream the run was started with, for runs that have no session ID yet
    */
-  async cancelClaudeExecution(sessionId?: string): Promise<void> {
-    return invoke("cancel_claude_execution", { sessionId });
+  async cancelClaudeExecution(sessionId?: string, streamId?: string): Promise<void> {
+    return invoke("cancel_claude_execution", { sessionId, streamId });
   },
 
   /**
@@ -1071,6 +1209,16 @@ export const api = {
     return invoke("get_claude_session_output", { sessionId });
   },
 
+  /**
+   * Reads a Claude session's live output incrementally, starting at a line cursor
+   * @param sessionId - The session ID to read output for
+   * @param offset - Line to start at; pass the previous chunk's next_offset
+   * @param limit - Maximum number of lines to return
+   */
+  async readClaudeSessionOutput(sessionId: string, offset: number, limit?: number): Promise<OutputChunk> {
+    return invoke<OutputChunk>("read_claude_session_output", { sessionId, offset, limit });
+  },
+
   /**
    * Lists files and directories in a given path
    */
@@ -1159,14 +1307,16 @@ export const api = {
     projectId: string,
     projectPath: string,
     messageIndex?: number,
-    description?: string
+    description?: string,
+    autoGenerated?: boolean
   ): Promise<CheckpointResult> {
     return invoke("create_checkpoint", {
       sessionId,
       projectId,
       projectPath,
       messageIndex,
-      description
+      description,
+      autoGenerated
     });
   },
 
@@ -1177,10 +1327,27 @@ export const api = {
     checkpointId: string,
     sessionId: string,
     projectId: string,
-    projectPath: string
+    projectPath: string,
+    onConflict?: ConflictResolution
   ): Promise<CheckpointResult> {
     return invoke("restore_checkpoint", {
       checkpointId,
+      sessionId,
+      projectId,
+      projectPath,
+      onConflict
+    });
+  },
+
+  /**
+   * Lists files modified outside the session that a restore would overwrite
+   */
+  async checkRestoreConflicts(
+    sessionId: string,
+    projectId: string,
+    projectPath: string
+  ): Promise<ExternalChange[]> {
+    return invoke("check_restore_conflicts", {
       sessionId,
       projectId,
       projectPath
@@ -1279,6 +1446,30 @@ export const api = {
     }
   },
 
+  /**
+   * Gets diff between a checkpoint and the current contents of the project directory
+   */
+  async getWorkingTreeDiff(
+    checkpointId: string,
+    sessionId: string,
+    projectId: string,
+    projectPath: string,
+    contextLines?: number
+  ): Promise<CheckpointDiff> {
+    try {
+      return await invoke<CheckpointDiff>("get_working_tree_diff", {
+        checkpointId,
+        sessionId,
+        projectId,
+        projectPath,
+        contextLines
+      });
+    } catch (error) {
+      console.error("Failed to get working tree diff:", error);
+      throw error;
+    }
+  },
+
   /**
    * Tracks a message for checkpointing
    */